@@ -0,0 +1,60 @@
+//! Counts the allocations of building an [`AnimationSet`], with the tag names
+//! borrowed from the file and with them copied by `into_owned`.
+//!
+//! Run with `cargo bench --bench animation_set_alloc`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use assu_parser::{loader::AsepriteFile, output::AnimationSet};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ITERATIONS: usize = 100;
+
+fn count_allocations(into_owned: bool, input: &[u8]) -> usize {
+    let config = texture_packer::TexturePackerConfig {
+        allow_rotation: false,
+        trim: false,
+        ..Default::default()
+    };
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ITERATIONS {
+        let file = AsepriteFile::from_bytes(input).unwrap();
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let set = AnimationSet::from_ase(file, "bench", &mut packer).unwrap();
+        if into_owned {
+            std::hint::black_box(set.into_owned());
+        } else {
+            std::hint::black_box(set);
+        }
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / ITERATIONS
+}
+
+fn main() {
+    for name in ["animated", "userdata"] {
+        let input = std::fs::read(format!("tests/aseprite_files/{name}.aseprite")).unwrap();
+        let borrowed = count_allocations(false, &input);
+        let owned = count_allocations(true, &input);
+        println!("{name}: {borrowed} allocations borrowed, {owned} with owned tag names");
+    }
+}
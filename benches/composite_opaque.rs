@@ -0,0 +1,42 @@
+//! Times compositing a large sprite made of fully opaque Normal layers, which skip the per channel
+//! blending, against the same sprite with a layer opacity of 254, which goes through it.
+//!
+//! Run with `cargo bench --bench composite_opaque`
+
+use std::time::{Duration, Instant};
+
+use assu_parser::{encoder::AsepriteWriter, loader::AsepriteFile};
+
+const SIZE: u16 = 1024;
+const ITERATIONS: u32 = 10;
+
+fn time_compositing(file: &AsepriteFile<'_>) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(file.combined_frame_image(0).unwrap());
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut writer = AsepriteWriter::new(SIZE, SIZE);
+    let frame = writer.add_frame(100);
+    for (index, color) in [[200, 30, 30, 255], [30, 200, 30, 255], [30, 30, 200, 255]].into_iter().enumerate() {
+        let layer = writer.add_layer(&format!("Layer {index}"));
+        let offset = index as u32 * 64;
+        let img = image::RgbaImage::from_pixel(SIZE as u32 - offset, SIZE as u32 - offset, image::Rgba(color));
+        writer.add_cel(frame, layer, (offset as i16, offset as i16), img).unwrap();
+    }
+    let bytes = writer.to_bytes().unwrap();
+    let mut file = AsepriteFile::from_bytes(&bytes).unwrap();
+
+    let opaque = time_compositing(&file);
+    for layer in file.layers.iter_mut() {
+        layer.chunk.opacity = 254;
+    }
+    let translucent = time_compositing(&file);
+
+    println!("{SIZE}x{SIZE}, 3 layers");
+    println!("opaque layers:        {opaque:?} per frame");
+    println!("layer opacity of 254: {translucent:?} per frame");
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use assu_parser::loader::AsepriteFile;
+use libfuzzer_sys::fuzz_target;
+
+// Run with `cargo +nightly fuzz run from_bytes`
+fuzz_target!(|data: &[u8]| {
+    let _ = AsepriteFile::from_bytes(data);
+});
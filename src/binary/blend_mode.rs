@@ -5,7 +5,7 @@ use super::{
     scalars::{word, Word},
 };
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, FromRepr)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, FromRepr)]
 pub enum BlendMode {
     Normal,
     Multiply,
@@ -31,7 +31,38 @@ pub enum BlendMode {
 
 impl From<Word> for BlendMode {
     fn from(word: Word) -> Self {
-        BlendMode::from_repr(word.into()).unwrap_or(BlendMode::Unknown(word))
+        // from_repr also matches the discriminant of Unknown, with a default value inside
+        match BlendMode::from_repr(word.into()) {
+            Some(BlendMode::Unknown(_)) | None => BlendMode::Unknown(word),
+            Some(blend_mode) => blend_mode,
+        }
+    }
+}
+
+impl From<BlendMode> for Word {
+    fn from(blend_mode: BlendMode) -> Self {
+        match blend_mode {
+            BlendMode::Normal => 0,
+            BlendMode::Multiply => 1,
+            BlendMode::Screen => 2,
+            BlendMode::Overlay => 3,
+            BlendMode::Darken => 4,
+            BlendMode::Lighten => 5,
+            BlendMode::ColorDodge => 6,
+            BlendMode::ColorBurn => 7,
+            BlendMode::HardLight => 8,
+            BlendMode::SoftLight => 9,
+            BlendMode::Difference => 10,
+            BlendMode::Exclusion => 11,
+            BlendMode::Hue => 12,
+            BlendMode::Saturation => 13,
+            BlendMode::Color => 14,
+            BlendMode::Luminosity => 15,
+            BlendMode::Addition => 16,
+            BlendMode::Subtract => 17,
+            BlendMode::Divide => 18,
+            BlendMode::Unknown(word) => word,
+        }
     }
 }
 
@@ -50,6 +81,10 @@ fn test_parse_blend_mode() {
         parse_blend_mode(b"\x12\x00").unwrap(),
         (&b""[..], BlendMode::Divide)
     );
+    assert_eq!(
+        parse_blend_mode(b"\x13\x00").unwrap(),
+        (&b""[..], BlendMode::Unknown(19))
+    );
     assert_eq!(
         parse_blend_mode(b"\x37\x13").unwrap(),
         (&b""[..], BlendMode::Unknown(0x1337))
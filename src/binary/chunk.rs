@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use nom::{bytes::complete::take, multi::count};
 
 use super::{
@@ -30,7 +31,9 @@ pub enum Chunk<'a> {
     ColorProfile(ColorProfileChunk<'a>),
     ExternalFiles(ExternalFilesChunk<'a>),
     Mask(MaskChunk<'a>),
-    Path, // Never used.
+    /// Reserved by the spec but never written by aseprite, it has no documented body.
+    /// Whatever it holds is skipped, like any chunk the rest of the frame is found from the chunk size.
+    Path,
     Tags(TagsChunk<'a>),
     Palette(PaletteChunk<'a>),
     UserData(UserDataChunk<'a>),
@@ -44,8 +47,8 @@ pub fn parse_chunks(input: &[u8], chunk_count: usize) -> ParseResult<'_, Vec<Chu
 }
 
 pub fn parse_chunk(input: &[u8]) -> ParseResult<'_, Chunk<'_>> {
-    let (input, size) = dword_size(input, ParseError::InvalidFrameSize)?;
-    // FIXME handle underflows
+    // The size includes itself, `dword_size` makes sure this can't underflow
+    let (input, size) = dword_size(input, ParseError::InvalidChunkSize)?;
     let (rest, input) = take(size - 4)(input)?;
     let (chunk_data, chunk_type) = parse_chunk_type(input)?;
     let chunk = match chunk_type {
@@ -69,3 +72,30 @@ pub fn parse_chunk(input: &[u8]) -> ParseResult<'_, Chunk<'_>> {
     };
     Ok((rest, chunk))
 }
+
+#[test]
+fn test_path_chunk() {
+    use super::test_utils::{chunk, edit_chunks, fixture};
+    use crate::loader::AsepriteFile;
+
+    let input = [chunk(ChunkType::Path, &[1, 2, 3, 4, 5]), vec![9, 9]].concat();
+    let (rest, path) = parse_chunk(&input).unwrap();
+    assert!(path.is_path());
+    assert_eq!(rest, [9, 9]);
+
+    // Before the layer and its cel
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.insert(3, chunk(ChunkType::Path, &[1, 2, 3, 4, 5]));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.layers.len(), 1);
+    assert_eq!(file.frames[0].cells.len(), 1);
+}
+
+#[test]
+fn test_chunk_size_underflow() {
+    assert!(matches!(
+        parse_chunk(&[2, 0, 0, 0, 0x04, 0x20]),
+        Err(nom::Err::Failure(ParseError::InvalidChunkSize(2)))
+    ));
+}
@@ -26,7 +26,7 @@ pub struct CelChunk<'a> {
 
 #[derive(Debug, FromRepr)]
 
-enum CelType {
+pub(crate) enum CelType {
     /// 0 - Raw Image Data (unused, compressed image is preferred)
     RawImageData,
     /// 1 - Linked Cel
@@ -44,6 +44,18 @@ impl From<Word> for CelType {
     }
 }
 
+impl From<CelType> for Word {
+    fn from(cel_type: CelType) -> Self {
+        match cel_type {
+            CelType::RawImageData => 0,
+            CelType::LinkedCel => 1,
+            CelType::CompressedImage => 2,
+            CelType::CompressedTilemap => 3,
+            CelType::Unknown(word) => word,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum CelContent<'a> {
     Image(Image<'a>),
@@ -141,3 +153,39 @@ pub fn parse_cel_chunk(input: &[u8]) -> ParseResult<'_, CelChunk<'_>> {
         },
     ))
 }
+
+#[test]
+fn test_tilemap_unsupported() {
+    use crate::{
+        binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture, string}},
+        loader::{AsepriteFile, LoadSpriteError},
+    };
+    let mut layer = Vec::new();
+    layer.extend(1u16.to_le_bytes()); // flags
+    layer.extend(2u16.to_le_bytes()); // tilemap
+    layer.extend([0; 10]); // child level, default size, blend mode
+    layer.extend([255, 0, 0, 0]); // opacity
+    layer.extend(string("Tilemap").unwrap());
+    layer.extend(0u32.to_le_bytes()); // tileset index
+
+    let mut cel = Vec::new();
+    cel.extend([0; 6]); // layer index, x, y
+    cel.push(255); // opacity
+    cel.extend(3u16.to_le_bytes()); // compressed tilemap
+    cel.extend([0; 7]); // z-index, reserved
+    cel.extend([1, 0, 1, 0, 32, 0]); // size, bits per tile
+    for mask in [0x1fffffffu32, 0x20000000, 0x40000000, 0x80000000] {
+        cel.extend(mask.to_le_bytes());
+    }
+    cel.extend([0; 10]);
+    cel.extend([0; 4]); // tiles, never decompressed
+
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[3] = chunk(ChunkType::Layer, &layer);
+        chunks[4] = chunk(ChunkType::Cel, &cel);
+    });
+    assert!(matches!(
+        AsepriteFile::from_bytes(&input),
+        Err(LoadSpriteError::Unsupported("tilemaps"))
+    ));
+}
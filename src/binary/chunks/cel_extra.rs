@@ -17,6 +17,29 @@ pub struct CelExtraChunk<'a> {
     pub future: &'a [u8],
 }
 
+impl CelExtraChunk<'_> {
+    /// The precise bounds of the cel, if they are set
+    pub fn precise_bounds(&self) -> Option<PreciseBounds> {
+        self.flags
+            .contains(CelExtraFlags::PRECISE_BOUNDS_ARE_SET)
+            .then(|| PreciseBounds {
+                x: self.precise_x_position.to_f64(),
+                y: self.precise_y_position.to_f64(),
+                width: self.width_of_the_cel.to_f64(),
+                height: self.height_of_the_cel.to_f64(),
+            })
+    }
+}
+
+/// Subpixel position and size of a cel in the sprite
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreciseBounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
 bitflags! {
     #[derive(Debug)]
     pub struct CelExtraFlags: Dword {
@@ -42,3 +65,31 @@ pub fn parse_cel_extra_chunk(input: &[u8]) -> ParseResult<'_, CelExtraChunk<'_>>
         },
     ))
 }
+
+#[test]
+fn test_cel_extra() {
+    use crate::{
+        binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture}},
+        loader::AsepriteFile,
+    };
+    let mut cel_extra = Vec::new();
+    cel_extra.extend(1u32.to_le_bytes()); // precise bounds are set
+    for (high, low) in [(2u16, 0x8000u16), (3, 0x4000), (16, 0), (8, 0xc000)] {
+        cel_extra.extend(low.to_le_bytes());
+        cel_extra.extend(high.to_le_bytes());
+    }
+    cel_extra.extend([0; 16]);
+
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.insert(5, chunk(ChunkType::CelExtra, &cel_extra));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(
+        file.frames[0].cells[0].precise_bounds,
+        Some(PreciseBounds { x: 2.5, y: 3.25, width: 16.0, height: 8.75 })
+    );
+
+    let input = fixture("default.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.frames[0].cells[0].precise_bounds, None);
+}
@@ -39,6 +39,39 @@ pub enum ColorProfile<'a> {
     Unknown(Word),
 }
 
+/// What the color profile of a file amounts to, see [`ColorProfileChunk::kind`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorProfileKind {
+    NoColorProfile,
+    Srgb,
+    /// sRGB with a special fixed gamma (1.0 = linear)
+    FixedGamma(f64),
+    EmbeddedIcc,
+    Unknown(Word),
+}
+
+impl<'a> ColorProfileChunk<'a> {
+    pub fn kind(&self) -> ColorProfileKind {
+        match self.profile {
+            ColorProfile::NoColorProfile => ColorProfileKind::NoColorProfile,
+            ColorProfile::Srgb => match self.fixed_gamma {
+                Some(gamma) => ColorProfileKind::FixedGamma(gamma.to_f64()),
+                None => ColorProfileKind::Srgb,
+            },
+            ColorProfile::EmbeddedICC(_) => ColorProfileKind::EmbeddedIcc,
+            ColorProfile::Unknown(word) => ColorProfileKind::Unknown(word),
+        }
+    }
+
+    /// The raw ICC profile data, if one is embedded
+    pub fn icc_profile(&self) -> Option<&'a [u8]> {
+        match self.profile {
+            ColorProfile::EmbeddedICC(data) => Some(data),
+            _ => None,
+        }
+    }
+}
+
 pub fn parse_color_profile(input: &[u8]) -> ParseResult<'_, ColorProfileChunk<'_>> {
     let (input, profile_type) = word(input)?;
     let profile_type = ColorProfileType::from_repr(profile_type.into())
@@ -67,3 +100,37 @@ pub fn parse_color_profile(input: &[u8]) -> ParseResult<'_, ColorProfileChunk<'_
         },
     ))
 }
+
+#[test]
+fn test_color_profile_kind() {
+    use crate::{
+        binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture}},
+        loader::AsepriteFile,
+    };
+    let input = fixture("default.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::Srgb);
+    assert_eq!(file.icc_profile(), None);
+
+    let mut fixed_gamma = Vec::new();
+    fixed_gamma.extend([1, 0, 1, 0]); // sRGB, fixed gamma
+    fixed_gamma.extend([0, 0x80, 2, 0]); // 2.5
+    fixed_gamma.extend([0; 8]);
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[0] = chunk(ChunkType::ColorProfile, &fixed_gamma);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::FixedGamma(2.5));
+
+    let mut icc = Vec::new();
+    icc.extend([2, 0, 0, 0]); // ICC, no fixed gamma
+    icc.extend([0; 12]);
+    icc.extend(4u32.to_le_bytes());
+    icc.extend(b"icc!");
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[0] = chunk(ChunkType::ColorProfile, &icc);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::EmbeddedIcc);
+    assert_eq!(file.icc_profile(), Some(&b"icc!"[..]));
+}
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use nom::{bytes::complete::take, multi::count};
 use strum_macros::FromRepr;
 
@@ -19,7 +20,7 @@ pub struct ExternalFile<'a> {
     pub entry_id: Dword,
     /// Type
     pub file_type: ExternalFileType,
-    /// External file name
+    /// External file name or extension ID
     pub file_name: &'a str,
 }
 
@@ -29,6 +30,7 @@ pub enum ExternalFileType {
     ExternalPalette,
     ExternalTileset,
     ExtensionNameForProperties,
+    ExtensionNameForTileManagement,
     Unknown(Byte),
 }
 
@@ -60,3 +62,47 @@ pub fn parse_external_file(input: &[u8]) -> ParseResult<'_, ExternalFile<'_>> {
         },
     ))
 }
+
+#[test]
+fn test_external_tileset() {
+    use crate::{
+        binary::{
+            chunk_type::ChunkType,
+            chunks::tileset::TilesetTiles,
+            test_utils::{chunk, edit_chunks, fixture, string},
+        },
+        loader::AsepriteFile,
+    };
+    let mut external_files = Vec::new();
+    external_files.extend(1u32.to_le_bytes()); // number of entries
+    external_files.extend([0; 8]);
+    external_files.extend(7u32.to_le_bytes()); // entry id
+    external_files.push(1); // tileset
+    external_files.extend([0; 7]);
+    external_files.extend(string("tiles.aseprite").unwrap());
+
+    let mut tileset = Vec::new();
+    tileset.extend(0u32.to_le_bytes()); // id
+    tileset.extend(1u32.to_le_bytes()); // external file flag
+    tileset.extend(4u32.to_le_bytes()); // number of tiles
+    tileset.extend([8, 0, 8, 0, 1, 0]); // tile size, base index
+    tileset.extend([0; 14]);
+    tileset.extend(string("Tileset").unwrap());
+    tileset.extend(7u32.to_le_bytes()); // external file id
+    tileset.extend(3u32.to_le_bytes()); // tileset id in the external file
+
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.insert(1, chunk(ChunkType::ExternalFile, &external_files));
+        chunks.insert(2, chunk(ChunkType::Tileset, &tileset));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.external_files.len(), 1);
+    let TilesetTiles::TilesetExternalFile { external_file_id, tileset_id } = file.tilesets[0].tiles else {
+        panic!("expected an external tileset");
+    };
+    assert_eq!(tileset_id, 3);
+    let external_file = file.external_file(external_file_id).unwrap();
+    assert!(matches!(external_file.file_type, ExternalFileType::ExternalTileset));
+    assert_eq!(external_file.file_name, "tiles.aseprite");
+    assert!(file.external_file(8).is_none());
+}
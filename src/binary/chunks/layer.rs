@@ -26,9 +26,9 @@ bitflags! {
         const EDITABLE = 0x2;
         const LOCK_MOVEMENT = 0x4;
         const BACKGROUND = 0x8;
-        const PREFER_LINKED_CELS = 0x16;
-        const COLLAPSED = 0x32;
-        const REFERENCE = 0x64;
+        const PREFER_LINKED_CELS = 0x10;
+        const COLLAPSED = 0x20;
+        const REFERENCE = 0x40;
     }
 }
 
@@ -51,6 +51,17 @@ impl From<Word> for LayerType {
     }
 }
 
+impl From<LayerType> for Word {
+    fn from(layer_type: LayerType) -> Self {
+        match layer_type {
+            LayerType::Normal => 0,
+            LayerType::Group => 1,
+            LayerType::Tilemap => 2,
+            LayerType::Unknown(n) => n,
+        }
+    }
+}
+
 pub fn parse_layer_chunk(input: &[u8]) -> ParseResult<'_, LayerChunk<'_>> {
     let (input, flags) = word(input)?;
     let flags = LayerFlags::from_bits_truncate(flags);
@@ -91,3 +102,43 @@ fn test_layers() {
     assert_eq!(file.layers[1].name(), "Layer 2");
     assert_eq!(file.layers[2].name(), "Layer 3");
 }
+
+#[test]
+fn test_unknown_layer_type() {
+    use crate::{binary::test_utils::{edit_chunks, fixture, set_layer_type}, loader::AsepriteFile};
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the layer chunk
+        set_layer_type(&mut chunks[3], LayerType::Unknown(7));
+    });
+    assert!(AsepriteFile::from_bytes(&input).is_err());
+}
+
+#[test]
+fn test_cel_on_missing_layer() {
+    use crate::{binary::test_utils::{edit_chunks, fixture, set_cel_layer}, loader::AsepriteFile};
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[4] is the cel chunk
+        set_cel_layer(&mut chunks[4], 3);
+    });
+    assert!(AsepriteFile::from_bytes(&input).is_err());
+}
+
+#[test]
+fn test_reference_layer() {
+    use crate::{binary::test_utils::{edit_chunks, fixture, set_layer_flags}, loader::AsepriteFile};
+
+    let flags = LayerFlags::VISIBLE | LayerFlags::LOCK_MOVEMENT | LayerFlags::REFERENCE;
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the layer chunk
+        set_layer_flags(&mut chunks[3], flags);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let layer = &file.layers[0];
+    assert!(layer.visible());
+    assert!(layer.is_reference());
+    assert!(layer.is_movement_locked());
+    assert!(!layer.is_editable());
+    assert!(!layer.is_background());
+    assert!(!layer.prefers_linked_cels());
+    assert!(!layer.is_collapsed());
+}
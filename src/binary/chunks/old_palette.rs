@@ -1,5 +1,9 @@
 use nom::multi::count;
 
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::binary::palette::Palette;
 use crate::binary::{
     errors::ParseResult,
     scalars::{byte, parse_rgb, word, Byte, RGB},
@@ -24,6 +28,28 @@ pub struct Packet {
     pub colors: Vec<RGB>,
 }
 
+#[cfg(feature = "std")]
+impl OldPaletteChunk {
+    /// Applies the packets to `palette`, each one skipping entries and then replacing the next ones,
+    /// growing the palette as needed. Entries the packets don't reach are kept.
+    /// `max_value` is 255 for the 0x0004 chunk and 63 for the 0x0011 one.
+    pub fn apply_to(&self, palette: &mut Palette, max_value: u8) {
+        let scale = |c: u8| (c.min(max_value) as u16 * 255 / max_value as u16) as u8;
+        let mut index = 0;
+        for packet in self.packets.iter() {
+            index += packet.entries_to_skip as usize;
+            let req_len = index + packet.colors.len();
+            if palette.entries.len() < req_len {
+                palette.entries.resize(req_len, (image::Rgba([0, 0, 0, 0]), None));
+            }
+            for c in packet.colors.iter() {
+                palette.entries[index].0 = image::Rgba([scale(c.red), scale(c.green), scale(c.blue), 255]);
+                index += 1;
+            }
+        }
+    }
+}
+
 pub fn parse_old_palette_chunk(input: &[u8]) -> ParseResult<'_, OldPaletteChunk> {
     let (input, number_of_packets) = word(input)?;
     let (input, packets) = count(parse_packet, number_of_packets.into())(input)?;
@@ -47,3 +73,65 @@ pub fn parse_packet(input: &[u8]) -> ParseResult<'_, Packet> {
         },
     ))
 }
+
+#[test]
+fn test_old_palette() {
+    use crate::{binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture}}, loader::AsepriteFile};
+
+    let mut data = Vec::new();
+    data.extend(2u16.to_le_bytes()); // packets
+    data.extend([0, 1, 10, 20, 30]);
+    data.extend([2, 2, 63, 0, 0, 0, 63, 0]);
+    let (_, old_palette) = parse_old_palette_chunk(&data).unwrap();
+
+    let apply = |max_value| {
+        let mut palette = Palette::default();
+        old_palette.apply_to(&mut palette, max_value);
+        palette.entries
+    };
+    let transparent = (image::Rgba([0, 0, 0, 0]), None);
+    assert_eq!(
+        apply(255),
+        [
+            (image::Rgba([10, 20, 30, 255]), None),
+            transparent.clone(),
+            transparent.clone(),
+            (image::Rgba([63, 0, 0, 255]), None),
+            (image::Rgba([0, 63, 0, 255]), None),
+        ]
+    );
+    assert_eq!(
+        apply(63),
+        [
+            (image::Rgba([40, 80, 121, 255]), None),
+            transparent.clone(),
+            transparent,
+            (image::Rgba([255, 0, 0, 255]), None),
+            (image::Rgba([0, 255, 0, 255]), None),
+        ]
+    );
+
+    // A later chunk only changes the entries its packets reach
+    let mut update = Vec::new();
+    update.extend(1u16.to_le_bytes());
+    update.extend([3, 1, 1, 2, 3]);
+    let (_, update) = parse_old_palette_chunk(&update).unwrap();
+    let mut palette = Palette::default();
+    old_palette.apply_to(&mut palette, 255);
+    update.apply_to(&mut palette, 255);
+    assert_eq!(palette.entries.len(), 5);
+    assert_eq!(palette.entries[0].0, image::Rgba([10, 20, 30, 255]));
+    assert_eq!(palette.entries[3].0, image::Rgba([1, 2, 3, 255]));
+    assert_eq!(palette.entries[4].0, image::Rgba([0, 63, 0, 255]));
+
+    // The loader only parses them, they're written back as they were found
+    // chunks[1] is the new palette chunk, chunks[2] the old one
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[2] = chunk(ChunkType::Palette0004, &data);
+        chunks.remove(1);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(file.palette_entries().is_empty());
+    let reloaded = file.to_bytes().unwrap();
+    assert_eq!(AsepriteFile::from_bytes(&reloaded).unwrap().frames[0].other_chunks, file.frames[0].other_chunks);
+}
@@ -1,11 +1,9 @@
-use std::ops::Range;
-
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use nom::{bytes::complete::take, combinator::{cond, verify}, multi::count};
 
 use crate::binary::{
-    errors::{ParseError, ParseResult},
-    palette::PaletteError,
+    errors::ParseResult,
     scalars::{
         dword, parse_color, parse_string, word, Color, Word
     },
@@ -58,3 +56,32 @@ pub fn parse_palette_entry(input: &[u8]) -> ParseResult<'_, PaletteEntry<'_>> {
     Ok((input, PaletteEntry { color, name }))
 }
 
+
+#[test]
+fn test_palette_names() {
+    use crate::{binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture, string}}, loader::AsepriteFile};
+
+    let mut data = Vec::new();
+    data.extend(2u32.to_le_bytes()); // palette size
+    data.extend(0u32.to_le_bytes()); // first index
+    data.extend(1u32.to_le_bytes()); // last index
+    data.extend([0; 8]);
+    data.extend(0u16.to_le_bytes());
+    data.extend([0, 0, 0, 255]);
+    data.extend(PaletteEntryFlags::HAS_NAME.bits().to_le_bytes());
+    data.extend([255, 200, 150, 255]);
+    data.extend(string("Skin").unwrap());
+
+    // chunks[1] is the palette chunk
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[1] = chunk(ChunkType::Palette, &data);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(
+        file.palette_entries(),
+        &[
+            (image::Rgba([0, 0, 0, 255]), None),
+            (image::Rgba([255, 200, 150, 255]), Some("Skin".to_string())),
+        ]
+    );
+}
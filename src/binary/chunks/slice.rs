@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use nom::{combinator::cond, multi::count};
 
@@ -6,7 +7,7 @@ use crate::binary::{
     scalars::{dword, long, parse_string, Dword, Long},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SliceChunk<'a> {
     pub name: &'a str,
     pub flags: SliceFlags,
@@ -107,3 +108,54 @@ pub fn parse_pivot(input: &[u8]) -> ParseResult<'_, Pivot> {
     let (input, y) = long(input)?;
     Ok((input, Pivot { x, y }))
 }
+
+#[test]
+fn test_slice_user_data() {
+    use crate::binary::{chunk_type::ChunkType, test_utils::{chunk, edit_chunks, fixture, slice, string}};
+    use crate::loader::AsepriteFile;
+
+    let mut user_data = 3u32.to_le_bytes().to_vec(); // text and color
+    user_data.extend(string("hurtbox").unwrap());
+    user_data.extend([255, 0, 0, 255]);
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.push(slice("head", &[(0, 1, 2, 3, 4)]));
+        chunks.push(chunk(ChunkType::UserData, &user_data));
+        chunks.push(slice("feet", &[(0, 0, 0, 1, 1)]));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.slices.len(), 2);
+
+    let head = file.slice("head").unwrap();
+    assert_eq!(head.user_data.text, Some("hurtbox"));
+    assert_eq!(head.user_data.color.map(|c| (c.red, c.green, c.blue, c.alpha)), Some((255, 0, 0, 255)));
+    let key = head.chunk.slice_keys[0];
+    assert_eq!((key.x, key.y, key.width, key.height), (1, 2, 3, 4));
+
+    let feet = file.slice("feet").unwrap();
+    assert_eq!(feet.user_data.text, None);
+    assert!(file.slice("tail").is_none());
+}
+
+#[test]
+fn test_slice_rect() {
+    use crate::binary::test_utils::{edit_chunks, fixture, slice};
+    use crate::loader::AsepriteFile;
+
+    // animated.aseprite has 8 frames, the bar shrinks from frame 3 on
+    let input = edit_chunks(&fixture("animated.aseprite"), 0, |chunks| {
+        chunks.push(slice("health", &[(3, 2, 1, 6, 2), (0, 2, 1, 10, 2)]));
+        chunks.push(slice("late", &[(5, -1, -2, 3, 3)]));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.frames.len(), 8);
+
+    assert_eq!(file.slice_rect("health", 0), Some((2, 1, 10, 2)));
+    assert_eq!(file.slice_rect("health", 2), Some((2, 1, 10, 2)));
+    assert_eq!(file.slice_rect("health", 3), Some((2, 1, 6, 2)));
+    assert_eq!(file.slice_rect("health", 7), Some((2, 1, 6, 2)));
+    assert_eq!(file.slice_rect("health", 8), None);
+
+    assert_eq!(file.slice_rect("late", 4), None);
+    assert_eq!(file.slice_rect("late", 5), Some((-1, -2, 3, 3)));
+    assert_eq!(file.slice_rect("missing", 0), None);
+}
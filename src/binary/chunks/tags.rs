@@ -1,11 +1,10 @@
-
-
+use alloc::vec::Vec;
 use nom::{bytes::complete::take, multi::count};
 use strum_macros::FromRepr;
 
 use crate::binary::{
     errors::{ParseError, ParseResult},
-    scalars::{byte, parse_string, word, Byte, Word},
+    scalars::{byte, parse_rgb, parse_string, word, Byte, Word, RGB},
 };
 
 #[derive(Debug)]
@@ -29,10 +28,13 @@ pub struct TagChunk<'a> {
     ///       and once in reverse)
     ///   n = Plays N times
     pub animation_repeat: Word,
+    /// Deprecated, the color in the user data of the tag is used instead.
+    /// Older files only have this one.
+    pub color: RGB,
     pub name: &'a str,
 }
 
-#[derive(FromRepr, Debug, Copy, Clone)]
+#[derive(FromRepr, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AnimationDirection {
     Forward,
     Reverse,
@@ -41,12 +43,84 @@ pub enum AnimationDirection {
     Unknown(Byte),
 }
 
+/// How many times a tag plays, from [`TagChunk::animation_repeat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopBehavior {
+    /// Not specified, aseprite loops forever in the UI but plays it once when exporting
+    Infinite,
+    /// Plays once, for ping-pong that's just one direction
+    Once,
+    /// Plays N times, for ping-pong each direction counts as one
+    Times(Word),
+}
+
+impl From<Word> for LoopBehavior {
+    fn from(repeat: Word) -> Self {
+        match repeat {
+            0 => LoopBehavior::Infinite,
+            1 => LoopBehavior::Once,
+            n => LoopBehavior::Times(n),
+        }
+    }
+}
+
 impl From<Byte> for AnimationDirection {
     fn from(byte: Byte) -> Self {
         AnimationDirection::from_repr(byte.into()).unwrap_or(AnimationDirection::Unknown(byte))
     }
 }
 
+impl From<AnimationDirection> for Byte {
+    fn from(direction: AnimationDirection) -> Self {
+        match direction {
+            AnimationDirection::Forward => 0,
+            AnimationDirection::Reverse => 1,
+            AnimationDirection::PingPong => 2,
+            AnimationDirection::PingPongReverse => 3,
+            AnimationDirection::Unknown(byte) => byte,
+        }
+    }
+}
+
+/// The names Aseprite uses in its JSON export, unknown directions are written as their raw value
+impl core::fmt::Display for AnimationDirection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AnimationDirection::Forward => f.write_str("forward"),
+            AnimationDirection::Reverse => f.write_str("reverse"),
+            AnimationDirection::PingPong => f.write_str("pingpong"),
+            AnimationDirection::PingPongReverse => f.write_str("pingpong_reverse"),
+            AnimationDirection::Unknown(byte) => write!(f, "{byte}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseAnimationDirectionError;
+
+impl core::fmt::Display for ParseAnimationDirectionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected forward, reverse, pingpong, pingpong_reverse or a number")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseAnimationDirectionError {}
+
+impl core::str::FromStr for AnimationDirection {
+    type Err = ParseAnimationDirectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(AnimationDirection::Forward),
+            "reverse" => Ok(AnimationDirection::Reverse),
+            "pingpong" => Ok(AnimationDirection::PingPong),
+            "pingpong_reverse" => Ok(AnimationDirection::PingPongReverse),
+            _ => s.parse::<Byte>().map(AnimationDirection::from).map_err(|_| ParseAnimationDirectionError),
+        }
+    }
+}
+
 pub fn parse_tags_chunk(input: &[u8]) -> ParseResult<'_, TagsChunk<'_>> {
     let (input, number_of_tags) = word(input)?;
     let (input, _) = take(8usize)(input)?;
@@ -66,8 +140,7 @@ pub fn parse_tag(input: &[u8]) -> ParseResult<'_, TagChunk<'_>> {
     let animation_direction = AnimationDirection::from(animation_direction);
     let (input, animation_repeat) = word(input)?;
     let (input, _) = take(6usize)(input)?;
-    let (input, color) = take(3usize)(input)?;
-    let _ = color; // color of the tag, is deprecated, color in userdata used instead
+    let (input, color) = parse_rgb(input)?;
     let (input, _) = byte(input)?;
     let (input, name) = parse_string(input)?;
     Ok((
@@ -76,6 +149,7 @@ pub fn parse_tag(input: &[u8]) -> ParseResult<'_, TagChunk<'_>> {
             frames: (from_frame, to_frame),
             animation_direction,
             animation_repeat,
+            color,
             name,
         },
     ))
@@ -93,3 +167,91 @@ fn test_tags() {
     assert_eq!(file.tags[1].name(), "Tag 2");
     assert_eq!(file.tags[2].name(), "Tag 3");
 }
+
+#[test]
+fn test_tag_range_out_of_bounds() {
+    use crate::{
+        binary::{chunk_type::ChunkType, test_utils::{edit_chunks, fixture, is_chunk, set_tag_range}},
+        loader::{AsepriteFile, LoadSpriteError},
+        output::AnimationSet,
+    };
+
+    // tags.aseprite has a single frame
+    for (from, to) in [(0u16, 1u16), (0, 200), (1, 1)] {
+        let input = edit_chunks(&fixture("tags.aseprite"), 0, |chunks| {
+            let tags = chunks.iter_mut().find(|c| is_chunk(c, ChunkType::Tags)).unwrap();
+            set_tag_range(tags, 0, from, to);
+        });
+        match AsepriteFile::from_bytes(&input) {
+            Err(LoadSpriteError::Parse { message, .. }) => assert!(message.contains("Tag 1"), "{message}"),
+            other => panic!("{from}..={to} loaded: {other:?}"),
+        }
+    }
+
+    // tags edited after loading don't make the animations panic either
+    let input = fixture("tags.aseprite");
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    file.tags[0].chunk.frames = (0, 3);
+    let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+    let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+    assert!(AnimationSet::from_ase(file, "tags", &mut packer).is_err());
+}
+
+#[test]
+fn test_animation_direction_str() {
+    use alloc::string::ToString;
+
+    for (direction, name) in [
+        (AnimationDirection::Forward, "forward"),
+        (AnimationDirection::Reverse, "reverse"),
+        (AnimationDirection::PingPong, "pingpong"),
+        (AnimationDirection::PingPongReverse, "pingpong_reverse"),
+        (AnimationDirection::Unknown(7), "7"),
+    ] {
+        assert_eq!(direction.to_string(), name);
+        assert_eq!(name.parse::<AnimationDirection>(), Ok(direction));
+    }
+    // known values are never unknown
+    assert_eq!("2".parse::<AnimationDirection>(), Ok(AnimationDirection::PingPong));
+    assert_eq!("ping-pong".parse::<AnimationDirection>(), Err(ParseAnimationDirectionError));
+    assert!("256".parse::<AnimationDirection>().is_err());
+}
+
+#[test]
+fn test_loop_behavior() {
+    use crate::loader::AsepriteFile;
+    let input = std::fs::read("tests/aseprite_files/tags.aseprite").unwrap();
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    let tag = &mut file.tags[0];
+    assert_eq!(tag.loop_behavior(), LoopBehavior::Infinite);
+    tag.chunk.animation_repeat = 1;
+    assert_eq!(tag.loop_behavior(), LoopBehavior::Once);
+    tag.chunk.animation_repeat = 2;
+    assert_eq!(tag.loop_behavior(), LoopBehavior::Times(2));
+    tag.chunk.animation_repeat = 65535;
+    assert_eq!(tag.loop_behavior(), LoopBehavior::Times(65535));
+}
+
+#[test]
+fn test_tag_color() {
+    use crate::{binary::test_utils::{edit_chunks, fixture, set_tag_color, user_data}, loader::AsepriteFile};
+
+    // chunks[3] is the tags chunk, followed by the user data of each tag
+    let legacy_color = edit_chunks(&fixture("tags.aseprite"), 0, |chunks| {
+        set_tag_color(&mut chunks[3], 0, [10, 20, 30]);
+    });
+
+    let legacy_only = edit_chunks(&legacy_color, 0, |chunks| {
+        chunks.drain(4..7);
+    });
+    let file = AsepriteFile::from_bytes(&legacy_only).unwrap();
+    assert_eq!(file.tags[0].color(), Some(image::Rgb([10, 20, 30])));
+    // no user data and the deprecated color left unset
+    assert_eq!(file.tags[1].color(), None);
+
+    let with_user_data = edit_chunks(&legacy_color, 0, |chunks| {
+        chunks[4] = user_data(Some("t1"), Some([40, 50, 60, 255]));
+    });
+    let file = AsepriteFile::from_bytes(&with_user_data).unwrap();
+    assert_eq!(file.tags[0].color(), Some(image::Rgb([40, 50, 60])));
+}
@@ -1,13 +1,15 @@
 use bitflags::bitflags;
 use nom::{
     bytes::complete::take,
-    combinator::{cond, flat_map, verify}, error::{make_error, ErrorKind},
+    combinator::{flat_map, verify},
 };
 
 use crate::binary::{
     errors::ParseResult,
     scalars::{dword, parse_string, short, word, Dword, Short, Word},
 };
+#[cfg(feature = "std")]
+use crate::loader::LoadSpriteError;
 
 #[derive(Debug, Clone, Copy)]
 pub struct TilesetChunk<'a> {
@@ -34,6 +36,73 @@ pub struct TilesetChunk<'a> {
     pub tiles: TilesetTiles<'a>,
 }
 
+impl TilesetChunk<'_> {
+    /// Where the tile a tilemap cel refers to sits in the tileset image, `None` for the empty tile.
+    /// `tile` is the value stored in the tilemap and `bitmask_tile_id` the cel's tile ID mask,
+    /// which strips the flip bits.
+    ///
+    /// The empty tile is ID 0 with [`TilesetFlags::TILE_0_EMPTY`] and `0xffffffff` without it,
+    /// every other ID is the tile's index in the image as is: [`base_index`](Self::base_index)
+    /// only changes the numbers Aseprite shows, see [`display_id`](Self::display_id).
+    /// The index isn't checked against [`number_of_tiles`](Self::number_of_tiles).
+    pub fn tile_index(&self, tile: Dword, bitmask_tile_id: Dword) -> Option<usize> {
+        if self.flags.contains(TilesetFlags::TILE_0_EMPTY) {
+            match tile & bitmask_tile_id {
+                0 => None,
+                id => Some(id as usize),
+            }
+        } else if tile == 0xffffffff {
+            None
+        } else {
+            Some((tile & bitmask_tile_id) as usize)
+        }
+    }
+
+    /// The number Aseprite shows for the tile at `index`, which depends on [`base_index`](Self::base_index)
+    pub fn display_id(&self, index: usize) -> i64 {
+        index as i64 - 1 + self.base_index as i64
+    }
+}
+
+#[cfg(feature = "std")]
+impl TilesetChunk<'_> {
+    /// The image of the tile a tilemap cel refers to, `None` for the empty tile.
+    /// `tile` and `bitmask_tile_id` are as in [`Self::tile_index`], which does the lookup.
+    pub fn tile_image(&self, tile: Dword, bitmask_tile_id: Dword) -> Result<Option<image::RgbaImage>, LoadSpriteError> {
+        use std::io::Read;
+
+        let Some(index) = self.tile_index(tile, bitmask_tile_id) else {
+            return Ok(None);
+        };
+        let TilesetTiles::CompressedTiles(data) = self.tiles else {
+            return Err(LoadSpriteError::Unsupported("external tilesets"));
+        };
+        if index >= self.number_of_tiles as usize {
+            return Err(LoadSpriteError::Parse {
+                message: format!(
+                    "tile {} out of range of tileset {:?} with {} tiles",
+                    self.display_id(index),
+                    self.name,
+                    self.number_of_tiles
+                ),
+                offset: None,
+            });
+        }
+
+        // Tiles are stacked vertically, only inflate up to the one asked for
+        let tile_size = self.width as usize * self.height as usize * 4;
+        let mut pixels = vec![0; tile_size * (index + 1)];
+        flate2::read::ZlibDecoder::new(data)
+            .read_exact(&mut pixels)
+            .map_err(|e| LoadSpriteError::Parse {
+                message: format!("tileset {:?} can't be decompressed: {e}", self.name),
+                offset: None,
+            })?;
+        let image = image::RgbaImage::from_raw(self.width as u32, self.height as u32, pixels.split_off(tile_size * index));
+        Ok(image)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum TilesetTiles<'a> {
     /// Compressed Tileset image (see NOTE.3):
@@ -120,5 +189,54 @@ pub fn parse_external_file(input: &[u8]) -> ParseResult<'_, TilesetTiles<'_>> {
 use nom::combinator::map;
 
 pub fn parse_tiles(input: &[u8]) -> ParseResult<'_, TilesetTiles<'_>> {
-    map(flat_map(dword, take), |data| TilesetTiles::CompressedTiles(data))(input)
+    map(flat_map(dword, take), TilesetTiles::CompressedTiles)(input)
+}
+
+#[test]
+fn test_tile_index() {
+    let tileset = TilesetChunk {
+        id: 0,
+        flags: TilesetFlags::TILES | TilesetFlags::TILE_0_EMPTY,
+        number_of_tiles: 3,
+        width: 1,
+        height: 1,
+        base_index: 1,
+        name: "tiles",
+        tiles: TilesetTiles::CompressedTiles(&[]),
+    };
+    let mask = 0x1fffffff;
+    assert_eq!(tileset.tile_index(0, mask), None);
+    assert_eq!(tileset.tile_index(1, mask), Some(1));
+    assert_eq!(tileset.tile_index(0x80000002, mask), Some(2));
+    assert_eq!(tileset.display_id(1), 1);
+    assert_eq!(TilesetChunk { base_index: 0, ..tileset }.display_id(1), 0);
+
+    let old = TilesetChunk { flags: TilesetFlags::TILES, ..tileset };
+    assert_eq!(old.tile_index(0, mask), Some(0));
+    assert_eq!(old.tile_index(0xffffffff, mask), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_tile_image() {
+    use crate::binary::test_utils::zlib;
+
+    // Tile 0 is the empty one, 1 is red and 2 is green
+    let data = zlib(&[0, 0, 0, 0, 255, 0, 0, 255, 0, 255, 0, 255]);
+    let tileset = TilesetChunk {
+        id: 0,
+        flags: TilesetFlags::TILES | TilesetFlags::TILE_0_EMPTY,
+        number_of_tiles: 3,
+        width: 1,
+        height: 1,
+        base_index: 1,
+        name: "tiles",
+        tiles: TilesetTiles::CompressedTiles(&data),
+    };
+
+    let mask = 0x1fffffff;
+    assert!(tileset.tile_image(0, mask).unwrap().is_none());
+    assert_eq!(tileset.tile_image(1, mask).unwrap().unwrap().as_raw(), &[255, 0, 0, 255]);
+    assert_eq!(tileset.tile_image(2, mask).unwrap().unwrap().as_raw(), &[0, 255, 0, 255]);
+    assert!(matches!(tileset.tile_image(3, mask), Err(LoadSpriteError::Parse { .. })));
 }
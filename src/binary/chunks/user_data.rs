@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use bitflags::bitflags;
 use nom::{
     bytes::complete::take,
@@ -241,3 +242,17 @@ pub fn parse_vector(input: &[u8]) -> ParseResult<'_, Vector<'_>> {
     };
     Ok((input, vec))
 }
+
+#[test]
+fn test_invalid_utf8_text() {
+    use crate::{binary::test_utils::{edit_chunks, fixture}, loader::{AsepriteFile, LoadSpriteError}};
+    // Errors in frames after the first one used to be swallowed by `many1`
+    let input = edit_chunks(&fixture("userdata.aseprite"), 1, |chunks| {
+        // chunks[1] is the user data of the first cel, its text "l1f2" is at the end
+        *chunks[1].last_mut().unwrap() = 0xff;
+    });
+    let Err(LoadSpriteError::Parse { message, .. }) = AsepriteFile::from_bytes(&input) else {
+        panic!("expected a parse error");
+    };
+    assert!(message.contains("Utf8Error"), "{message}");
+}
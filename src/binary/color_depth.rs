@@ -57,3 +57,13 @@ fn test_bpp() {
     assert_eq!(ColorDepth::Grayscale.bpp(), 16);
     assert_eq!(ColorDepth::Indexed.bpp(), 8);
 }
+
+#[test]
+fn test_indexed_unsupported() {
+    use crate::loader::{AsepriteFile, LoadSpriteError};
+    let input = std::fs::read("tests/aseprite_files/indexed.aseprite").unwrap();
+    assert!(matches!(
+        AsepriteFile::from_bytes(&input),
+        Err(LoadSpriteError::Unsupported("indexed color depth"))
+    ));
+}
@@ -1,4 +1,4 @@
-use std::str::Utf8Error;
+use core::str::Utf8Error;
 
 use nom::IResult;
 
@@ -33,6 +33,17 @@ pub enum ParseError<'a> {
     Nom(nom::error::Error<&'a [u8]>),
 }
 
+impl<'a> ParseError<'a> {
+    /// The input left when parsing failed, for the errors that know it
+    pub fn remaining_input(&self) -> Option<&'a [u8]> {
+        match self {
+            ParseError::Nom(e) => Some(e.input),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl<'a> std::error::Error for ParseError<'a> {}
 
 impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
@@ -6,7 +6,8 @@ use super::{
     scalars::{byte, dword, short, word, Byte, Dword, Short, Word},
 };
 
-const HEADER_MAGIC_NUMBER: [u8; 2] = 0xA5E0u16.to_le_bytes();
+pub(crate) const HEADER_MAGIC_NUMBER: [u8; 2] = 0xA5E0u16.to_le_bytes();
+pub(crate) const HEADER_SIZE: usize = 128;
 
 /// A 128-byte header (same as FLC/FLI header, but with other magic number)
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -51,7 +52,7 @@ pub struct Header {
 }
 
 pub fn parse_header(input: &[u8]) -> ParseResult<'_, Header> {
-    let (rest, input) = take(128usize)(input)?;
+    let (rest, input) = take(HEADER_SIZE)(input)?;
     let (input, file_size) = dword(input)?;
     let (input, _) = tag(HEADER_MAGIC_NUMBER)(input)?;
     let (input, frames) = word(input)?;
@@ -109,7 +110,7 @@ fn test_parse_header() {
             frames: 1,
             width: 32,
             height: 32,
-            color_depth: super::color_depth::ColorDepth::Rgba,
+            color_depth: ColorDepth::Rgba,
             flags: 1,
             transparent_index: 0,
             color_count: 32,
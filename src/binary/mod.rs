@@ -14,3 +14,6 @@ pub mod palette;
 pub mod raw_file;
 pub mod raw_frame;
 pub mod scalars;
+
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod test_utils;
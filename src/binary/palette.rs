@@ -1,18 +1,26 @@
-use thiserror::Error;
-
-use super::scalars::Color;
-
+#[cfg(feature = "std")]
 #[derive(Debug, Default)]
 pub struct Palette {
-    pub colors: Vec<image::Rgba<u8>>,
+    /// Colors by palette index, with the name given to them in aseprite
+    pub entries: Vec<(image::Rgba<u8>, Option<String>)>,
 }
 
-#[derive(Debug, Copy, Clone, Error)]
+#[derive(Debug, Copy, Clone)]
 pub enum PaletteError {
-    #[error("First color index not in range 0..255")]
     FirstColorIndexOutOfBounds,
-    #[error("Last color index not in range 0..255")]
     LastColorIndexOutOfBounds,
-    #[error("First color index > last color index")]
     FirstColorIndexGreaterThanLastColorIndex,
-}
\ No newline at end of file
+}
+
+impl core::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            PaletteError::FirstColorIndexOutOfBounds => "First color index not in range 0..255",
+            PaletteError::LastColorIndexOutOfBounds => "Last color index not in range 0..255",
+            PaletteError::FirstColorIndexGreaterThanLastColorIndex => "First color index > last color index",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PaletteError {}
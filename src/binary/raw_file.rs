@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use super::{
     errors::ParseError,
     header::{parse_header, Header},
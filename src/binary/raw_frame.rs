@@ -1,11 +1,13 @@
+use alloc::vec::Vec;
 use nom::{
     bytes::complete::{tag, take},
-    combinator::{all_consuming, complete},
-    multi::many1,
+    combinator::consumed,
+    error::{ErrorKind, ParseError as _},
+    multi::count,
 };
 
 use super::{
-    chunk::{parse_chunks, Chunk},
+    chunk::{parse_chunk, Chunk},
     chunks::cel::CelChunk,
     errors::{ParseError, ParseResult},
     scalars::{dword, dword_size, word, Word},
@@ -15,6 +17,8 @@ use super::{
 pub struct RawFrame<'a> {
     pub duration: Word,
     pub chunks: Vec<Chunk<'a>>,
+    /// The bytes of each chunk (size and type included), in the same order as `chunks`
+    pub raw_chunks: Vec<&'a [u8]>,
 }
 
 impl<'a> RawFrame<'a> {
@@ -29,21 +33,89 @@ impl<'a> RawFrame<'a> {
     }
 }
 
-const FRAME_MAGIC_NUMBER: [u8; 2] = 0xF1FAu16.to_le_bytes();
+pub(crate) const FRAME_MAGIC_NUMBER: [u8; 2] = 0xF1FAu16.to_le_bytes();
+#[cfg(feature = "std")]
+pub(crate) const FRAME_HEADER_SIZE: usize = 16;
 
-pub fn parse_frames(input: &[u8]) -> ParseResult<'_, Vec<RawFrame<'_>>> {
-    complete(all_consuming(many1(parse_rawframe)))(input)
+/// Parses frames up to the end of the input, whatever the frame count of the header says.
+/// Zeros after the last frame are padding and left unparsed.
+pub fn parse_frames(mut input: &[u8]) -> ParseResult<'_, Vec<RawFrame<'_>>> {
+    let mut frames = Vec::new();
+    while input.iter().any(|&b| b != 0) {
+        let (rest, frame) = parse_rawframe(input)?;
+        frames.push(frame);
+        input = rest;
+    }
+    if frames.is_empty() {
+        return Err(nom::Err::Error(ParseError::from_error_kind(input, ErrorKind::Many1)));
+    }
+    Ok((input, frames))
 }
 
 pub fn parse_rawframe(input: &[u8]) -> ParseResult<'_, RawFrame<'_>> {
+    // The size includes itself, `dword_size` makes sure this can't underflow
     let (input, size) = dword_size(input, ParseError::InvalidFrameSize)?;
-    // FIXME handle underflows
     let (rest, input) = take(size - 4)(input)?;
     let (input, _) = tag(FRAME_MAGIC_NUMBER)(input)?;
-    let (input, chunk_count) = word(input)?;
+    let (input, old_chunk_count) = word(input)?;
     let (input, duration) = word(input)?;
     let (input, _) = take(2usize)(input)?;
-    let (input, chunk_count) = dword(input)?;
-    let (_, chunks) = parse_chunks(input, chunk_count as usize)?;
-    Ok((rest, RawFrame { duration, chunks }))
+    let (input, new_chunk_count) = dword(input)?;
+    // Files from before the dword count have 0 there, the word count is 0xFFFF when it can't hold the real one
+    let chunk_count = match new_chunk_count {
+        0 => old_chunk_count as usize,
+        n => n as usize,
+    };
+    let (_, chunks) = count(consumed(parse_chunk), chunk_count)(input)?;
+    let (raw_chunks, chunks) = chunks.into_iter().unzip();
+    Ok((rest, RawFrame { duration, chunks, raw_chunks }))
+}
+
+#[test]
+fn test_frame_size_underflow() {
+    assert!(matches!(
+        parse_rawframe(&[0u8; 16]),
+        Err(nom::Err::Failure(ParseError::InvalidFrameSize(0)))
+    ));
+}
+
+#[test]
+fn test_chunk_count() {
+    use super::{chunk_type::ChunkType, raw_file::parse_raw_file, test_utils::{chunk, edit_chunks, fixture}};
+
+    const FRAME_CHUNK_COUNT: usize = 128 + 12;
+
+    let input = fixture("default.aseprite");
+    let chunk_count = parse_raw_file(&input).unwrap().frames[0].chunks.len();
+
+    // Old files only have the word count
+    let mut old = input.clone();
+    old[FRAME_CHUNK_COUNT..FRAME_CHUNK_COUNT + 4].copy_from_slice(&[0; 4]);
+    assert_eq!(parse_raw_file(&old).unwrap().frames[0].chunks.len(), chunk_count);
+
+    // More chunks than the word count can hold, it's 0xFFFF and the dword count has the real one
+    let input = edit_chunks(&input, 0, |chunks| {
+        chunks.extend(core::iter::repeat_n(chunk(ChunkType::Path, &[]), 70_000));
+    });
+    assert_eq!(parse_raw_file(&input).unwrap().frames[0].chunks.len(), chunk_count + 70_000);
+}
+
+#[test]
+fn test_trailing_padding() {
+    use super::test_utils::{fixture, frame_bytes};
+
+    let input = fixture("animated.aseprite");
+    let frames = &input[frame_bytes(&input, 0).start..];
+    let frame_count = parse_frames(frames).unwrap().1.len();
+
+    let mut padded = frames.to_vec();
+    padded.extend([0; 100]);
+    let (rest, frames) = parse_frames(&padded).unwrap();
+    assert_eq!(frames.len(), frame_count);
+    assert_eq!(rest, [0; 100]);
+
+    // Anything else after the last frame is still an error
+    padded.push(1);
+    assert!(parse_frames(&padded).is_err());
+    assert!(parse_frames(&[0; 16]).is_err());
 }
@@ -8,7 +8,7 @@
 
 use nom::{
     bytes::complete::take,
-    combinator::{flat_map, map_res},
+    combinator::flat_map,
     number::complete::{le_i16, le_i32, le_u128, le_u16, le_u32, le_u8},
 };
 
@@ -43,8 +43,16 @@ pub struct Rect {
     pub size: Size,
 }
 
+/// 32-bit fixed point (16.16) value, stored as (high, low)
 #[derive(Debug, Copy, Clone)]
-pub struct Fixed(u16, u16);
+pub struct Fixed(pub u16, pub u16);
+
+impl Fixed {
+    pub fn to_f64(self) -> f64 {
+        let raw = ((self.0 as u32) << 16 | self.1 as u32) as i32;
+        raw as f64 / 65536.0
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct RGB {
@@ -117,8 +125,14 @@ pub fn dword_size<'a>(input: &'a [u8], f: fn(Dword) -> ParseError<'a>) -> ParseR
     }
 }
 
+/// Parse a length prefixed UTF-8 string.
+/// Invalid UTF-8 is a [`ParseError::Utf8Error`] failure instead of a
+/// recoverable error so it isn't swallowed by the surrounding combinators.
 pub fn parse_string(input: &[u8]) -> ParseResult<'_, &str> {
-    map_res(flat_map(word, take), std::str::from_utf8)(input)
+    let (input, bytes) = flat_map(word, take)(input)?;
+    let string = core::str::from_utf8(bytes)
+        .map_err(|e| nom::Err::Failure(ParseError::Utf8Error(e)))?;
+    Ok((input, string))
 }
 
 pub fn parse_uuid(input: &[u8]) -> ParseResult<'_, Uuid> {
@@ -153,3 +167,18 @@ pub fn parse_rgb(input: &[u8]) -> ParseResult<'_, RGB> {
     let (input, blue) = byte(input)?;
     Ok((input, RGB { red, green, blue }))
 }
+
+#[test]
+fn test_fixed() {
+    assert_eq!(fixed(b"\x00\x80\x02\x00").unwrap().1.to_f64(), 2.5);
+    assert_eq!(fixed(b"\x00\x80\xfe\xff").unwrap().1.to_f64(), -1.5);
+}
+
+#[test]
+fn test_parse_string() {
+    assert_eq!(parse_string(b"\x03\x00abc").unwrap(), (&b""[..], "abc"));
+    assert!(matches!(
+        parse_string(b"\x03\x00a\xff\xfe"),
+        Err(nom::Err::Failure(ParseError::Utf8Error(_)))
+    ));
+}
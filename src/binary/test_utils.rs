@@ -0,0 +1,280 @@
+//! Helpers for tests that need files the fixtures don't cover.
+//!
+//! Instead of shipping a binary fixture for every odd case, the tests take
+//! one of the files in `tests/aseprite_files`, edit the chunks of a frame
+//! and let [`edit_chunks`] fix up the frame and file sizes afterwards.
+
+use std::ops::Range;
+
+use super::{
+    blend_mode::BlendMode,
+    chunk_type::ChunkType,
+    chunks::{
+        cel::{CelChunk, CelContent},
+        layer::{LayerFlags, LayerType},
+        user_data::UserDataChunk,
+    },
+    header::HEADER_SIZE,
+    image::Image,
+    raw_frame::FRAME_HEADER_SIZE,
+    scalars::{Byte, Color, Dword, Word},
+};
+use crate::encoder::{self, cel_chunk, user_data_chunk};
+
+pub(crate) use crate::encoder::{chunk, string};
+
+// Offsets of the fields the tests edit, chunk offsets count the chunk size and type
+const HEADER_FRAMES: usize = 6;
+const HEADER_WIDTH: usize = 8;
+const HEADER_HEIGHT: usize = 10;
+const HEADER_COLOR_DEPTH: usize = 12;
+const LAYER_FLAGS: usize = 6;
+const LAYER_TYPE: usize = 8;
+const LAYER_CHILD_LEVEL: usize = 10;
+const LAYER_BLEND_MODE: usize = 16;
+const CEL_LAYER: usize = 6;
+const CEL_POSITION: usize = 8;
+const CEL_OPACITY: usize = 12;
+const CEL_Z_INDEX: usize = 15;
+
+/// Reads one of the files in `tests/aseprite_files`
+pub(crate) fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/aseprite_files/{name}")).unwrap()
+}
+
+/// Builds an uncompressed cel chunk, `pixels` are RGBA row by row
+pub(crate) fn raw_cel(layer_index: Word, position: (i16, i16), size: (Word, Word), pixels: &[u8]) -> Vec<u8> {
+    image_cel(layer_index, position, size, pixels, false)
+}
+
+/// Builds a compressed cel chunk, `data` is taken as is for the zlib stream so it can be broken on purpose
+pub(crate) fn compressed_cel(layer_index: Word, position: (i16, i16), size: (Word, Word), data: &[u8]) -> Vec<u8> {
+    image_cel(layer_index, position, size, data, true)
+}
+
+/// Compresses `pixels` like Aseprite does for [`compressed_cel`]
+pub(crate) fn zlib(pixels: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(pixels).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn image_cel(layer_index: Word, position: (i16, i16), size: (Word, Word), data: &[u8], compressed: bool) -> Vec<u8> {
+    let image = Image { width: size.0, height: size.1, data, compressed };
+    cel(layer_index, position, CelContent::Image(image))
+}
+
+fn cel(layer_index: Word, position: (i16, i16), content: CelContent<'_>) -> Vec<u8> {
+    cel_chunk(&CelChunk { layer_index, x: position.0, y: position.1, opacity: 255, z_index: 0, content }).unwrap()
+}
+
+/// Builds a cel chunk linked to the cel of the same layer in frame `frame_position`
+pub(crate) fn linked_cel(layer_index: Word, frame_position: Word) -> Vec<u8> {
+    cel(layer_index, (0, 0), CelContent::LinkedCel { frame_position })
+}
+
+/// Builds a cel chunk linked to frame `frame_position` with the layer, position, opacity and z-index of the raw cel `cel`
+pub(crate) fn linked_cel_like(cel: &[u8], frame_position: Word) -> Vec<u8> {
+    let mut link = linked_cel(read_word(cel, CEL_LAYER), frame_position);
+    link[CEL_POSITION..=CEL_OPACITY].copy_from_slice(&cel[CEL_POSITION..=CEL_OPACITY]);
+    link[CEL_Z_INDEX..CEL_Z_INDEX + 2].copy_from_slice(&cel[CEL_Z_INDEX..CEL_Z_INDEX + 2]);
+    link
+}
+
+/// Whether the raw chunk `chunk` is of type `chunk_type`
+pub(crate) fn is_chunk(chunk: &[u8], chunk_type: ChunkType) -> bool {
+    read_word(chunk, 4) == chunk_type as Word
+}
+
+/// Sets the layer index of the raw cel chunk `cel`
+pub(crate) fn set_cel_layer(cel: &mut [u8], layer_index: Word) {
+    write_word(cel, CEL_LAYER, layer_index);
+}
+
+/// Sets the opacity of the raw cel chunk `cel`
+pub(crate) fn set_cel_opacity(cel: &mut [u8], opacity: Byte) {
+    cel[CEL_OPACITY] = opacity;
+}
+
+/// Sets the flags of the raw layer chunk `layer`
+pub(crate) fn set_layer_flags(layer: &mut [u8], flags: LayerFlags) {
+    write_word(layer, LAYER_FLAGS, flags.bits());
+}
+
+/// Sets the type of the raw layer chunk `layer`, see [`tilemap_layer`] for tilemaps which need more data
+pub(crate) fn set_layer_type(layer: &mut [u8], layer_type: LayerType) {
+    assert!(!matches!(layer_type, LayerType::Tilemap), "use tilemap_layer");
+    write_word(layer, LAYER_TYPE, layer_type.into());
+}
+
+/// Sets how deep in groups the raw layer chunk `layer` is
+pub(crate) fn set_child_level(layer: &mut [u8], child_level: Word) {
+    write_word(layer, LAYER_CHILD_LEVEL, child_level);
+}
+
+/// Sets the blend mode of the raw layer chunk `layer`
+pub(crate) fn set_blend_mode(layer: &mut [u8], blend_mode: BlendMode) {
+    write_word(layer, LAYER_BLEND_MODE, blend_mode.into());
+}
+
+/// Turns a copy of the raw (non tilemap) layer chunk `layer` into a tilemap layer using tileset `tileset_index`
+pub(crate) fn tilemap_layer(layer: &[u8], tileset_index: Dword) -> Vec<u8> {
+    let mut data = layer[6..].to_vec();
+    write_word(&mut data, LAYER_TYPE - 6, LayerType::Tilemap.into());
+    data.extend(tileset_index.to_le_bytes());
+    chunk(ChunkType::Layer, &data)
+}
+
+/// Sets the range of the tag at `tag_index` in the raw tags chunk `tags`
+pub(crate) fn set_tag_range(tags: &mut [u8], tag_index: usize, from: Word, to: Word) {
+    let offset = tag_offset(tags, tag_index);
+    write_word(tags, offset, from);
+    write_word(tags, offset + 2, to);
+}
+
+/// Sets the deprecated color of the tag at `tag_index` in the raw tags chunk `tags`
+pub(crate) fn set_tag_color(tags: &mut [u8], tag_index: usize, color: [Byte; 3]) {
+    // after the range, direction, repeat and reserved bytes
+    let offset = tag_offset(tags, tag_index) + 13;
+    tags[offset..offset + 3].copy_from_slice(&color);
+}
+
+fn tag_offset(tags: &[u8], tag_index: usize) -> usize {
+    // chunk header, tag count and reserved
+    let mut offset = 6 + 2 + 8;
+    for _ in 0..tag_index {
+        // range, direction, repeat, reserved and color, then the name
+        offset += 17;
+        offset += 2 + read_word(tags, offset) as usize;
+    }
+    offset
+}
+
+/// Builds a user data chunk with an optional text and RGBA color
+pub(crate) fn user_data(text: Option<&str>, color: Option<[Byte; 4]>) -> Vec<u8> {
+    let color = color.map(|[red, green, blue, alpha]| Color { red, green, blue, alpha });
+    user_data_chunk(&UserDataChunk { text, color }).unwrap()
+}
+
+/// Sets the frame count in the header of `file`, without touching the frames
+pub(crate) fn set_frame_count(file: &mut [u8], frames: Word) {
+    write_word(file, HEADER_FRAMES, frames);
+}
+
+/// Sets the canvas size in the header of `file`
+pub(crate) fn set_canvas_size(file: &mut [u8], width: Word, height: Word) {
+    write_word(file, HEADER_WIDTH, width);
+    write_word(file, HEADER_HEIGHT, height);
+}
+
+/// Sets the color depth (bits per pixel) in the header of `file`
+pub(crate) fn set_color_depth(file: &mut [u8], bpp: Word) {
+    write_word(file, HEADER_COLOR_DEPTH, bpp);
+}
+
+/// Where frame `frame_index` is in `file`, header included
+pub(crate) fn frame_bytes(file: &[u8], frame_index: usize) -> Range<usize> {
+    let mut offset = HEADER_SIZE;
+    for _ in 0..frame_index {
+        offset += read_dword(file, offset) as usize;
+    }
+    offset..offset + read_dword(file, offset) as usize
+}
+
+/// The first `len` bytes of `file`, with the file size in the header updated
+pub(crate) fn truncated(file: &[u8], len: usize) -> Vec<u8> {
+    let mut out = file[..len].to_vec();
+    out[..4].copy_from_slice(&(len as Dword).to_le_bytes());
+    out
+}
+
+/// Builds a slice chunk without nine patch or pivot, `keys` are `(frame, x, y, width, height)`
+pub(crate) fn slice(name: &str, keys: &[(Dword, i32, i32, Dword, Dword)]) -> Vec<u8> {
+    slice_chunk(name, 0, keys.iter().map(|&key| (key, None)))
+}
+
+/// Builds a slice chunk with a pivot, `keys` are `(frame, x, y, width, height, pivot_x, pivot_y)`
+/// with the pivot relative to the slice origin.
+pub(crate) fn pivot_slice(name: &str, keys: &[(Dword, i32, i32, Dword, Dword, i32, i32)]) -> Vec<u8> {
+    let keys = keys.iter().map(|&(frame, x, y, width, height, pivot_x, pivot_y)| {
+        ((frame, x, y, width, height), Some((pivot_x, pivot_y)))
+    });
+    slice_chunk(name, 2, keys)
+}
+
+fn slice_chunk(
+    name: &str,
+    flags: Dword,
+    keys: impl ExactSizeIterator<Item = ((Dword, i32, i32, Dword, Dword), Option<(i32, i32)>)>,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend((keys.len() as Dword).to_le_bytes());
+    data.extend(flags.to_le_bytes());
+    data.extend([0; 4]); // reserved
+    data.extend(string(name).unwrap());
+    for ((frame, x, y, width, height), pivot) in keys {
+        data.extend(frame.to_le_bytes());
+        data.extend(x.to_le_bytes());
+        data.extend(y.to_le_bytes());
+        data.extend(width.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        if let Some((pivot_x, pivot_y)) = pivot {
+            data.extend(pivot_x.to_le_bytes());
+            data.extend(pivot_y.to_le_bytes());
+        }
+    }
+    chunk(ChunkType::Slice, &data)
+}
+
+/// Lets `f` edit the raw chunks (size, type and data) of frame `frame_index`
+/// and returns the file with the frame and file sizes updated accordingly.
+pub(crate) fn edit_chunks(
+    file: &[u8],
+    frame_index: usize,
+    f: impl FnOnce(&mut Vec<Vec<u8>>),
+) -> Vec<u8> {
+    let mut f = Some(f);
+    let mut out = file[..HEADER_SIZE].to_vec();
+    let mut offset = HEADER_SIZE;
+    let mut index = 0;
+    while offset < file.len() {
+        let frame_size = read_dword(file, offset) as usize;
+        let frame = &file[offset..offset + frame_size];
+        if index == frame_index {
+            let mut chunks = Vec::new();
+            let mut chunk_offset = FRAME_HEADER_SIZE;
+            while chunk_offset < frame.len() {
+                let chunk_size = read_dword(frame, chunk_offset) as usize;
+                chunks.push(frame[chunk_offset..chunk_offset + chunk_size].to_vec());
+                chunk_offset += chunk_size;
+            }
+            if let Some(f) = f.take() {
+                f(&mut chunks);
+            }
+
+            // after the frame size, magic number and old chunk count
+            out.extend(encoder::frame_bytes(read_word(frame, 8), &chunks));
+        } else {
+            out.extend(frame);
+        }
+        offset += frame_size;
+        index += 1;
+    }
+    let file_size = out.len() as Dword;
+    out[..4].copy_from_slice(&file_size.to_le_bytes());
+    out
+}
+
+fn read_word(input: &[u8], offset: usize) -> Word {
+    Word::from_le_bytes(input[offset..offset + 2].try_into().unwrap())
+}
+
+fn write_word(output: &mut [u8], offset: usize, word: Word) {
+    output[offset..offset + 2].copy_from_slice(&word.to_le_bytes());
+}
+
+fn read_dword(input: &[u8], offset: usize) -> Dword {
+    Dword::from_le_bytes(input[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,543 @@
+//! Writes sprites back into the .aseprite format.
+//!
+//! [`AsepriteWriter`] builds new files, it only writes what's needed to describe a sprite:
+//! RGBA color depth, normal layers, image cels and tags.
+//! [`AsepriteFile::to_bytes`] writes a loaded file back.
+
+use std::{io::Write, ops::RangeInclusive};
+
+use crate::{
+    binary::{
+        blend_mode::BlendMode,
+        chunk_type::ChunkType,
+        chunks::{
+            cel::{CelChunk, CelContent, CelType},
+            cel_extra::{CelExtraFlags, PreciseBounds},
+            color_profile::{ColorProfile, ColorProfileChunk, ColorProfileFlags},
+            layer::{LayerChunk, LayerFlags, LayerType},
+            tags::{AnimationDirection, TagChunk},
+            user_data::{UserDataChunk, UserDataFlags},
+        },
+        color_depth::ColorDepth,
+        header::{Header, HEADER_MAGIC_NUMBER, HEADER_SIZE},
+        image::Image,
+        palette::Palette,
+        raw_frame::{FRAME_HEADER_SIZE, FRAME_MAGIC_NUMBER},
+        scalars::{Dword, Fixed, Word, RGB},
+    },
+    loader::AsepriteFile,
+};
+
+#[derive(Debug, Clone)]
+struct WriterCel {
+    layer_index: usize,
+    position: (i16, i16),
+    img: image::RgbaImage,
+}
+
+#[derive(Debug, Clone)]
+struct WriterFrame {
+    /// In milliseconds
+    duration: Word,
+    cels: Vec<WriterCel>,
+}
+
+/// Builds a RGBA .aseprite file
+///
+/// ```
+/// # use assu_parser::{encoder::AsepriteWriter, loader::AsepriteFile};
+/// let mut writer = AsepriteWriter::new(16, 16);
+/// let layer = writer.add_layer("Body");
+/// let frame = writer.add_frame(100);
+/// writer.add_cel(frame, layer, (2, 3), image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]))).unwrap();
+/// writer.add_tag("idle", 0..=0);
+///
+/// let bytes = writer.to_bytes().unwrap();
+/// let file = AsepriteFile::from_bytes(&bytes).unwrap();
+/// assert_eq!(file.tags[0].name(), "idle");
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsepriteWriter {
+    width: Word,
+    height: Word,
+    layers: Vec<String>,
+    frames: Vec<WriterFrame>,
+    tags: Vec<(String, RangeInclusive<usize>)>,
+    compress: bool,
+}
+
+impl AsepriteWriter {
+    pub fn new(width: Word, height: Word) -> Self {
+        Self {
+            width,
+            height,
+            layers: Vec::new(),
+            frames: Vec::new(),
+            tags: Vec::new(),
+            compress: true,
+        }
+    }
+
+    /// Cels are zlib compressed by default, like aseprite does
+    pub fn set_compress(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Adds a layer on top of the others, returns its index
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(name.to_string());
+        self.layers.len() - 1
+    }
+
+    /// Adds an empty frame at the end, returns its index
+    pub fn add_frame(&mut self, duration_ms: Word) -> usize {
+        self.frames.push(WriterFrame {
+            duration: duration_ms,
+            cels: Vec::new(),
+        });
+        self.frames.len() - 1
+    }
+
+    /// Draws `img` at `position` on the canvas, on layer `layer_index` of frame `frame_index`.
+    /// Fails if there is no such frame, see [`Self::add_frame`].
+    pub fn add_cel(&mut self, frame_index: usize, layer_index: usize, position: (i16, i16), img: image::RgbaImage) -> anyhow::Result<()> {
+        let frame_count = self.frames.len();
+        let frame = self
+            .frames
+            .get_mut(frame_index)
+            .ok_or_else(|| anyhow::anyhow!("cel on missing frame {frame_index}, there are {frame_count} frames"))?;
+        frame.cels.push(WriterCel {
+            layer_index,
+            position,
+            img,
+        });
+        Ok(())
+    }
+
+    pub fn add_tag(&mut self, name: &str, frames: RangeInclusive<usize>) {
+        self.tags.push((name.to_string(), frames));
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.frames.is_empty(), "a file needs at least one frame");
+        for (name, frames) in self.tags.iter() {
+            anyhow::ensure!(
+                frames.start() <= frames.end() && *frames.end() < self.frames.len(),
+                "tag {name:?} has invalid frames {frames:?}"
+            );
+        }
+
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut chunks = Vec::new();
+            if index == 0 {
+                chunks.push(color_profile_chunk(&ColorProfileChunk {
+                    fixed_gamma: None,
+                    profile: ColorProfile::Srgb,
+                }));
+                for name in self.layers.iter() {
+                    chunks.push(layer_chunk(&LayerChunk {
+                        flags: LayerFlags::VISIBLE | LayerFlags::EDITABLE,
+                        layer_type: LayerType::Normal,
+                        child_level: 0,
+                        blend_mode: BlendMode::Normal,
+                        opacity: 255,
+                        name,
+                        tileset_index: None,
+                    })?);
+                }
+                if !self.tags.is_empty() {
+                    let tags = self
+                        .tags
+                        .iter()
+                        .map(|(name, frames)| {
+                            Ok(TagChunk {
+                                frames: ((*frames.start()).try_into()?, (*frames.end()).try_into()?),
+                                animation_direction: AnimationDirection::Forward,
+                                animation_repeat: 0,
+                                color: RGB { red: 0, green: 0, blue: 0 },
+                                name,
+                            })
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    chunks.push(tags_chunk(&tags)?);
+                }
+            }
+            // The loader expects the cels in layer order
+            let mut cels = frame.cels.iter().collect::<Vec<_>>();
+            cels.sort_by_key(|cel| cel.layer_index);
+            for cel in cels {
+                anyhow::ensure!(cel.layer_index < self.layers.len(), "cel on missing layer {}", cel.layer_index);
+                chunks.push(self.cel_chunk(cel)?);
+            }
+            frames.push(frame_bytes(frame.duration, &chunks));
+        }
+
+        let file_size = HEADER_SIZE + frames.iter().map(Vec::len).sum::<usize>();
+        w.write_all(&header(&Header {
+            file_size: file_size.try_into()?,
+            frames: self.frames.len().try_into()?,
+            width: self.width,
+            height: self.height,
+            color_depth: ColorDepth::Rgba,
+            flags: 1, // layer opacity is valid
+            transparent_index: 0,
+            color_count: 0,
+            pixel_width: 1,
+            pixel_height: 1,
+            grid_x: 0,
+            grid_y: 0,
+            grid_width: 16,
+            grid_height: 16,
+        }))?;
+        for frame in frames {
+            w.write_all(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn cel_chunk(&self, cel: &WriterCel) -> anyhow::Result<Vec<u8>> {
+        let data = if self.compress {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(cel.img.as_raw())?;
+            encoder.finish()?
+        } else {
+            cel.img.as_raw().clone()
+        };
+        cel_chunk(&CelChunk {
+            layer_index: cel.layer_index.try_into()?,
+            x: cel.position.0,
+            y: cel.position.1,
+            opacity: 255,
+            z_index: 0,
+            content: CelContent::Image(Image {
+                width: cel.img.width().try_into()?,
+                height: cel.img.height().try_into()?,
+                data: &data,
+                compressed: self.compress,
+            }),
+        })
+    }
+}
+
+impl AsepriteFile<'_> {
+    /// Serializes the file back into the .aseprite format.
+    ///
+    /// Layers, cels, tags, the palette and the color profile are written from what was loaded,
+    /// everything else is written back as it was found (see [`Frame::other_chunks`](crate::wrappers::Frame::other_chunks)).
+    /// Only the text and color of user data are kept.
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// let bytes = file.to_bytes().unwrap();
+    /// let reloaded = AsepriteFile::from_bytes(&bytes).unwrap();
+    /// assert_eq!(reloaded.images_decompressed, file.images_decompressed);
+    /// ```
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut chunks = Vec::new();
+            if index == 0 {
+                chunks.push(color_profile_chunk(&self.color_profile));
+                if !self.palette.entries.is_empty() {
+                    chunks.push(palette_chunk(&self.palette)?);
+                }
+            }
+            chunks.extend(frame.other_chunks.iter().map(|chunk| chunk.to_vec()));
+            if index == 0 {
+                for layer in self.layers.iter() {
+                    chunks.push(layer_chunk(&layer.chunk)?);
+                    chunks.push(user_data_chunk(&layer.user_data)?);
+                }
+                if !self.tags.is_empty() {
+                    chunks.push(tags_chunk(&self.tags.iter().map(|tag| tag.chunk).collect::<Vec<_>>())?);
+                    for tag in self.tags.iter() {
+                        chunks.push(user_data_chunk(&tag.user_data)?);
+                    }
+                }
+            }
+            for cel in frame.cells.iter() {
+                chunks.push(cel_chunk(&cel.chunk)?);
+                if let Some(bounds) = cel.precise_bounds {
+                    chunks.push(cel_extra_chunk(&bounds));
+                }
+                chunks.push(user_data_chunk(&cel.user_data)?);
+            }
+            frames.push(frame_bytes(frame.duration.try_into()?, &chunks));
+        }
+
+        let file_size = HEADER_SIZE + frames.iter().map(Vec::len).sum::<usize>();
+        let mut out = header(&Header {
+            file_size: file_size.try_into()?,
+            frames: self.frames.len().try_into()?,
+            color_count: match self.palette.entries.len() {
+                0 => self.header.color_count,
+                len => len.try_into().unwrap_or(0),
+            },
+            ..self.header
+        });
+        out.extend(frames.concat());
+        Ok(out)
+    }
+}
+
+fn header(header: &Header) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE);
+    out.extend(header.file_size.to_le_bytes());
+    out.extend(HEADER_MAGIC_NUMBER);
+    out.extend(header.frames.to_le_bytes());
+    out.extend(header.width.to_le_bytes());
+    out.extend(header.height.to_le_bytes());
+    out.extend(header.color_depth.bpp().to_le_bytes());
+    out.extend(header.flags.to_le_bytes());
+    out.extend(100u16.to_le_bytes()); // deprecated speed
+    out.extend([0; 8]);
+    out.push(header.transparent_index);
+    out.extend([0; 3]);
+    out.extend(header.color_count.to_le_bytes());
+    out.push(header.pixel_width);
+    out.push(header.pixel_height);
+    out.extend(header.grid_x.to_le_bytes());
+    out.extend(header.grid_y.to_le_bytes());
+    out.extend(header.grid_width.to_le_bytes());
+    out.extend(header.grid_height.to_le_bytes());
+    out.resize(HEADER_SIZE, 0);
+    out
+}
+
+pub(crate) fn cel_chunk(cel: &CelChunk<'_>) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    data.extend(cel.layer_index.to_le_bytes());
+    data.extend(cel.x.to_le_bytes());
+    data.extend(cel.y.to_le_bytes());
+    data.push(cel.opacity);
+    match &cel.content {
+        CelContent::Image(image) => {
+            let cel_type = if image.compressed { CelType::CompressedImage } else { CelType::RawImageData };
+            data.extend(Word::from(cel_type).to_le_bytes());
+            data.extend(cel.z_index.to_le_bytes());
+            data.extend([0; 5]);
+            data.extend(image.width.to_le_bytes());
+            data.extend(image.height.to_le_bytes());
+            data.extend(image.data);
+        }
+        CelContent::LinkedCel { frame_position } => {
+            data.extend(Word::from(CelType::LinkedCel).to_le_bytes());
+            data.extend(cel.z_index.to_le_bytes());
+            data.extend([0; 5]);
+            data.extend(frame_position.to_le_bytes());
+        }
+        CelContent::CompressedTilemap { .. } | CelContent::Unknown(_) => {
+            anyhow::bail!("writing tilemap and unknown cels is not supported")
+        }
+    }
+    Ok(chunk(ChunkType::Cel, &data))
+}
+
+fn cel_extra_chunk(bounds: &PreciseBounds) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(CelExtraFlags::PRECISE_BOUNDS_ARE_SET.bits().to_le_bytes());
+    for value in [bounds.x, bounds.y, bounds.width, bounds.height] {
+        data.extend((((value * 65536.0) as i32) as Dword).to_le_bytes());
+    }
+    data.extend([0; 16]);
+    chunk(ChunkType::CelExtra, &data)
+}
+
+fn tags_chunk(tags: &[TagChunk<'_>]) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    data.extend(Word::try_from(tags.len())?.to_le_bytes());
+    data.extend([0; 8]);
+    for tag in tags {
+        data.extend(tag.frames.0.to_le_bytes());
+        data.extend(tag.frames.1.to_le_bytes());
+        data.push(tag.animation_direction.into());
+        data.extend(tag.animation_repeat.to_le_bytes());
+        data.extend([0; 6]);
+        data.extend([tag.color.red, tag.color.green, tag.color.blue]);
+        data.push(0);
+        data.extend(string(tag.name)?);
+    }
+    Ok(chunk(ChunkType::Tags, &data))
+}
+
+pub(crate) fn user_data_chunk(user_data: &UserDataChunk<'_>) -> anyhow::Result<Vec<u8>> {
+    let mut flags = UserDataFlags::empty();
+    flags.set(UserDataFlags::HAS_TEXT, user_data.text.is_some());
+    flags.set(UserDataFlags::HAS_COLOR, user_data.color.is_some());
+    let mut data = flags.bits().to_le_bytes().to_vec();
+    if let Some(text) = user_data.text {
+        data.extend(string(text)?);
+    }
+    if let Some(color) = user_data.color {
+        data.extend([color.red, color.green, color.blue, color.alpha]);
+    }
+    Ok(chunk(ChunkType::UserData, &data))
+}
+
+fn palette_chunk(palette: &Palette) -> anyhow::Result<Vec<u8>> {
+    let len = Dword::try_from(palette.entries.len())?;
+    let mut data = Vec::new();
+    data.extend(len.to_le_bytes());
+    data.extend(0u32.to_le_bytes()); // first index
+    data.extend((len - 1).to_le_bytes()); // last index
+    data.extend([0; 8]);
+    for (color, name) in palette.entries.iter() {
+        data.extend(Word::from(name.is_some()).to_le_bytes()); // flags: has name
+        data.extend(color.0);
+        if let Some(name) = name {
+            data.extend(string(name)?);
+        }
+    }
+    Ok(chunk(ChunkType::Palette, &data))
+}
+
+fn color_profile_chunk(profile: &ColorProfileChunk<'_>) -> Vec<u8> {
+    let (profile_type, icc) = match profile.profile {
+        ColorProfile::NoColorProfile => (0, None),
+        ColorProfile::Srgb => (1, None),
+        ColorProfile::EmbeddedICC(icc) => (2, Some(icc)),
+        ColorProfile::Unknown(profile_type) => (profile_type, None),
+    };
+    let mut flags = ColorProfileFlags::empty();
+    flags.set(ColorProfileFlags::FIXED_GAMMA, profile.fixed_gamma.is_some());
+    let Fixed(high, low) = profile.fixed_gamma.unwrap_or(Fixed(0, 0));
+
+    let mut data = Vec::new();
+    data.extend(Word::to_le_bytes(profile_type));
+    data.extend(flags.bits().to_le_bytes());
+    data.extend(low.to_le_bytes());
+    data.extend(high.to_le_bytes());
+    data.extend([0; 8]);
+    if let Some(icc) = icc {
+        data.extend((icc.len() as Dword).to_le_bytes());
+        data.extend(icc);
+    }
+    chunk(ChunkType::ColorProfile, &data)
+}
+
+pub(crate) fn layer_chunk(layer: &LayerChunk<'_>) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    data.extend(layer.flags.bits().to_le_bytes());
+    data.extend(Word::from(layer.layer_type).to_le_bytes());
+    data.extend(layer.child_level.to_le_bytes());
+    data.extend(0u16.to_le_bytes()); // default width
+    data.extend(0u16.to_le_bytes()); // default height
+    data.extend(Word::from(layer.blend_mode).to_le_bytes());
+    data.push(layer.opacity);
+    data.extend([0; 3]);
+    data.extend(string(layer.name)?);
+    if let Some(tileset_index) = layer.tileset_index {
+        data.extend(tileset_index.to_le_bytes());
+    }
+    Ok(chunk(ChunkType::Layer, &data))
+}
+
+/// A frame with its header, `chunks` are complete (size and type included)
+pub(crate) fn frame_bytes(duration: Word, chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.concat();
+    let mut out = Vec::with_capacity(FRAME_HEADER_SIZE + body.len());
+    out.extend(((FRAME_HEADER_SIZE + body.len()) as Dword).to_le_bytes());
+    out.extend(FRAME_MAGIC_NUMBER);
+    out.extend((chunks.len().min(0xFFFF) as Word).to_le_bytes());
+    out.extend(duration.to_le_bytes());
+    out.extend([0; 2]);
+    out.extend((chunks.len() as Dword).to_le_bytes());
+    out.extend(body);
+    out
+}
+
+/// A chunk with its size and type
+pub(crate) fn chunk(chunk_type: ChunkType, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 6);
+    out.extend(((data.len() + 6) as Dword).to_le_bytes());
+    out.extend((chunk_type as Word).to_le_bytes());
+    out.extend(data);
+    out
+}
+
+/// A string as stored in the file, length prefixed
+pub(crate) fn string(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = Word::try_from(s.len())?.to_le_bytes().to_vec();
+    out.extend(s.as_bytes());
+    Ok(out)
+}
+
+#[test]
+fn test_round_trip() {
+    use crate::loader::AsepriteFile;
+
+    let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    let original = AsepriteFile::from_bytes(&input).unwrap();
+
+    for compress in [true, false] {
+        let mut writer = AsepriteWriter::new(original.canvas_width(), original.canvas_height());
+        writer.set_compress(compress);
+        let layer = writer.add_layer("Flattened");
+        for index in 0..original.frames.len() {
+            let frame = writer.add_frame(original.frames[index].duration as Word);
+            writer.add_cel(frame, layer, (0, 0), original.combined_frame_image(index).unwrap()).unwrap();
+        }
+        writer.add_tag("first", 0..=0);
+        writer.add_tag("rest", 1..=2);
+
+        let bytes = writer.to_bytes().unwrap();
+        let file = AsepriteFile::from_bytes(&bytes).unwrap();
+        assert_eq!(file.frames.len(), original.frames.len());
+        assert_eq!(file.layers[0].name(), "Flattened");
+        assert_eq!(file.tags.iter().map(|t| t.name()).collect::<Vec<_>>(), ["first", "rest"]);
+        assert_eq!(file.tag("rest").unwrap().frame_range(), 1..=2);
+        for index in 0..original.frames.len() {
+            assert_eq!(file.frames[index].duration, original.frames[index].duration);
+            let cel = &file.frames[index].cells[0];
+            assert_eq!(file.images_decompressed[cel.image_index], original.combined_frame_image(index).unwrap());
+        }
+    }
+
+    let mut writer = AsepriteWriter::new(8, 8);
+    writer.add_frame(100);
+    writer.add_tag("too long", 0..=1);
+    assert!(writer.to_bytes().is_err());
+
+    let layer = writer.add_layer("Layer");
+    assert!(writer.add_cel(1, layer, (0, 0), image::RgbaImage::new(1, 1)).is_err());
+}
+
+#[test]
+fn test_to_bytes() {
+    use crate::loader::AsepriteFile;
+
+    let input = std::fs::read("tests/aseprite_files/tags.aseprite").unwrap();
+    let original = AsepriteFile::from_bytes(&input).unwrap();
+    let bytes = original.to_bytes().unwrap();
+    let file = AsepriteFile::from_bytes(&bytes).unwrap();
+
+    assert_eq!(file.tags.len(), 3);
+    assert_eq!(
+        file.tags.iter().map(|t| t.name()).collect::<Vec<_>>(),
+        original.tags.iter().map(|t| t.name()).collect::<Vec<_>>()
+    );
+    for (tag, original_tag) in file.tags.iter().zip(original.tags.iter()) {
+        assert_eq!(tag.frame_range(), original_tag.frame_range());
+        assert_eq!(tag.color(), original_tag.color());
+    }
+    assert_eq!(Header { file_size: 0, ..file.header }, Header { file_size: 0, ..original.header });
+    assert_eq!(file.palette.entries, original.palette.entries);
+    assert_eq!(
+        file.layers.iter().map(|l| l.name()).collect::<Vec<_>>(),
+        original.layers.iter().map(|l| l.name()).collect::<Vec<_>>()
+    );
+    assert_eq!(file.images_decompressed, original.images_decompressed);
+    for (frame, original_frame) in file.frames.iter().zip(original.frames.iter()) {
+        assert_eq!(frame.duration, original_frame.duration);
+        assert_eq!(frame.other_chunks, original_frame.other_chunks);
+    }
+}
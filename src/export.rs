@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use crate::loader::AsepriteFile;
+
+impl AsepriteFile<'_> {
+    /// Write one PNG per frame of `tag` (or all frames) into `dir`, creating it if needed.
+    /// Files are named `{frame index}.png`, or `{tag}_{index in tag}.png` when a tag is given,
+    /// with path separators, `..` and characters that aren't portable in a file name replaced by `_` in the tag name.
+    /// Returns the written paths in frame order.
+    pub fn export_png_sequence(&self, dir: &Path, tag: Option<&str>) -> anyhow::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        let prefix = tag.map(file_name_safe);
+        self.frame_range(tag)?
+            .enumerate()
+            .map(|(tag_index, index)| {
+                let file_name = match &prefix {
+                    Some(prefix) => format!("{prefix}_{tag_index}.png"),
+                    None => format!("{index}.png"),
+                };
+                let path = dir.join(file_name);
+                self.combined_frame_image(index)?.save_with_format(&path, image::ImageFormat::Png)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// The frames to encode for `tag` (or all frames, in order) and whether they loop forever, see [`TagTimeline::of`].
+    /// A tag with a repeat count has its repeats spelled out and plays once, one that doesn't specify it loops forever.
+    ///
+    /// [`TagTimeline::of`]: crate::timeline::TagTimeline::of
+    #[cfg(any(feature = "gif", feature = "apng"))]
+    fn animation_order(&self, tag: Option<&str>) -> Result<(Vec<usize>, bool), crate::loader::LoadSpriteError> {
+        use crate::{binary::chunks::tags::LoopBehavior, timeline::TagTimeline};
+
+        let Some(name) = tag else {
+            return Ok(((0..self.frames.len()).collect(), true));
+        };
+        let tag = self.tag(name).ok_or_else(|| crate::loader::LoadSpriteError::MissingTag(name.to_string()))?;
+        let timeline = TagTimeline::of(self, tag);
+        Ok((timeline.frames, timeline.loop_behavior == LoopBehavior::Infinite))
+    }
+
+    /// Write the frames of `tag` (or all frames) as an animated GIF, looping forever
+    /// unless the tag has a repeat count. Each frame is quantized to a 256 color palette.
+    #[cfg(feature = "gif")]
+    pub fn export_gif<W: std::io::Write>(&self, tag: Option<&str>, w: W) -> anyhow::Result<()> {
+        use image::codecs::gif::{GifEncoder, Repeat};
+
+        let (order, looping) = self.animation_order(tag)?;
+        let mut encoder = GifEncoder::new(w);
+        // the repeats are in the frames already, a finite GIF plays them once
+        encoder.set_repeat(if looping { Repeat::Infinite } else { Repeat::Finite(0) })?;
+        for index in order {
+            let img = self.combined_frame_image(index)?;
+            let delay = image::Delay::from_numer_denom_ms(self.frames[index].duration, 1);
+            encoder.encode_frame(image::Frame::from_parts(img, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+
+    /// Write the frames of `tag` (or all frames) as an animated PNG, lossless and with full alpha.
+    /// The animation loops forever unless the tag has a repeat count.
+    #[cfg(feature = "apng")]
+    pub fn export_apng<W: std::io::Write>(&self, tag: Option<&str>, w: W) -> anyhow::Result<()> {
+        let (order, looping) = self.animation_order(tag)?;
+        // the repeats are in the frames already, 0 plays is forever
+        let num_plays = if looping { 0 } else { 1 };
+
+        let mut encoder = png::Encoder::new(w, self.canvas_width() as u32, self.canvas_height() as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(order.len() as u32, num_plays)?;
+        let mut writer = encoder.write_header()?;
+        for index in order {
+            let img = self.combined_frame_image(index)?;
+            writer.set_frame_delay(self.frames[index].duration as u16, 1000)?;
+            writer.write_image_data(&img)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// `name` with everything that isn't portable in a file name replaced by `_`:
+/// path separators, `..`, characters Windows rejects and control characters.
+fn file_name_safe(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    safe.replace("..", "__")
+}
+
+#[test]
+fn test_file_name_safe() {
+    assert_eq!(file_name_safe("walk left"), "walk left");
+    assert_eq!(file_name_safe("../../etc/passwd"), "______etc_passwd");
+    assert_eq!(file_name_safe("C:\\a\nb*?.x"), "C__a_b__.x");
+}
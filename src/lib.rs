@@ -1,4 +1,5 @@
 #![doc = include_str!("../README.md")]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(
     nonstandard_style,
@@ -21,9 +22,23 @@
 )]
 #![allow(clippy::uninlined_format_args)]
 
+extern crate alloc;
+
 pub mod binary;
+pub mod metadata;
+#[cfg(feature = "std")]
+pub mod encoder;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
 pub mod loader;
+#[cfg(feature = "std")]
 pub mod make_image;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "std")]
 pub mod wrappers;
+#[cfg(feature = "std")]
 pub mod output;
+#[cfg(feature = "std")]
 pub use output::*;
\ No newline at end of file
@@ -1,26 +1,61 @@
-use std::{borrow::Cow, collections::HashMap, mem::zeroed};
+use std::{collections::BTreeSet, ops::{Range, RangeInclusive}};
 
 use crate::{binary::{
-    chunk::Chunk, chunks::{
-        cel::CelContent, color_profile::ColorProfileChunk, tileset::TilesetChunk,
-    }, color_depth::ColorDepth, header::Header, image::Image, palette::Palette, raw_file::{parse_raw_file, RawFile}
-}, make_image::{CroppedImage, LoadImageError}};
+    blend_mode::BlendMode, chunk::Chunk, chunks::{
+        cel::CelContent, color_profile::{ColorProfileChunk, ColorProfileKind}, external_files::ExternalFile, layer::LayerType,
+        tags::LoopBehavior, tileset::TilesetChunk,
+    }, color_depth::ColorDepth, header::Header, image::Image, palette::Palette, raw_file::{parse_raw_file, RawFile}, raw_frame::RawFrame, scalars::{Dword, Word}
+}};
 
+use crate::timeline::TagTimeline;
 use crate::wrappers::*;
 
-use itertools::Itertools;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum LoadSpriteError {
-    #[error("parsing failed {message}")]
-    Parse { message: String },
+    #[error("parsing failed {message}{}", .offset.map(|offset| format!(" at byte {offset}")).unwrap_or_default())]
+    Parse {
+        message: String,
+        /// Where in the file parsing stopped, when known
+        offset: Option<usize>,
+    },
     #[error("missing tag: {0}")]
     MissingTag(String),
     #[error("missing layer: {0}")]
     MissingLayer(String),
     #[error("frame index out of range: {0}")]
     FrameIndexOutOfRange(usize),
+    #[error("unsupported feature: {0}")]
+    Unsupported(&'static str),
+    #[error("layer {layer:?} uses the {blend_mode:?} blend mode, which isn't rendered like in Aseprite")]
+    UnsupportedBlendMode {
+        layer: String,
+        blend_mode: BlendMode,
+    },
+    #[error("a {width}x{height} image takes more than the {limit} bytes allowed")]
+    ImageTooLarge {
+        width: Word,
+        height: Word,
+        limit: usize,
+    },
+    #[error("image {index} can't be decoded")]
+    Image {
+        /// Index in [`AsepriteFile::images`]
+        index: usize,
+        source: crate::make_image::LoadImageError,
+    },
+}
+
+/// The most bytes [`AsepriteFile::from_bytes`] lets the canvas or a cel image take once decoded to RGBA
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 1 << 30;
+
+/// Fails if a `width` by `height` RGBA image takes more than `limit` bytes
+fn check_image_size(width: Word, height: Word, limit: usize) -> Result<(), LoadSpriteError> {
+    if width as usize * height as usize * 4 > limit {
+        return Err(LoadSpriteError::ImageTooLarge { width, height, limit });
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -35,29 +70,78 @@ pub struct AsepriteFile<'a> {
     pub frames: Vec<Frame<'a>>,
     /// All tags in the file
     pub tags: Vec<Tag<'a>>,
+    /// All slices in the file
+    pub slices: Vec<Slice<'a>>,
     /// All images in the file
     pub images: Vec<Image<'a>>,
     pub images_decompressed: Vec<image::RgbaImage>,
     pub tilesets: Vec<TilesetChunk<'a>>,
+    /// Files referenced by external tilesets and palettes
+    pub external_files: Vec<ExternalFile<'a>>,
+    /// The most bytes an image may take once decoded to RGBA, on loading and for the images made from the file
+    /// like [`Self::contact_sheet`]. [`DEFAULT_MAX_IMAGE_BYTES`] unless set with [`Self::from_bytes_with_limit`].
+    pub max_image_bytes: usize,
+}
+
+/// The raw bytes of the chunks of `frame` that the loader doesn't turn into layers, cels, tags,
+/// the palette or the color profile. User data stays with the chunk it belongs to.
+fn other_chunks<'a>(frame: &RawFrame<'a>) -> Vec<&'a [u8]> {
+    let mut out = Vec::new();
+    // User data chunks still owed to the last layer, cel or tags chunk
+    let mut owned_user_data = 0;
+    for (chunk, raw) in frame.chunks.iter().zip(&frame.raw_chunks) {
+        match chunk {
+            Chunk::Layer(_) | Chunk::Cel(_) => owned_user_data = 1,
+            Chunk::Tags(tags) => owned_user_data = tags.tags.len(),
+            Chunk::CelExtra(_) => (),
+            Chunk::UserData(_) if owned_user_data > 0 => owned_user_data -= 1,
+            Chunk::Palette(_) | Chunk::ColorProfile(_) => owned_user_data = 0,
+            _ => {
+                owned_user_data = 0;
+                out.push(*raw);
+            }
+        }
+    }
+    out
+}
+
+fn parse(data: &[u8]) -> Result<RawFile<'_>, LoadSpriteError> {
+    parse_raw_file(data).map_err(|e| {
+        let rest = match &e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.remaining_input(),
+            nom::Err::Incomplete(_) => None,
+        };
+        // the input left is a slice of `data`, its position is how far parsing got
+        let offset = rest
+            .map(|rest| (rest.as_ptr() as usize).wrapping_sub(data.as_ptr() as usize))
+            .filter(|&offset| offset <= data.len());
+        LoadSpriteError::Parse { message: e.to_string(), offset }
+    })
 }
 
 impl<'a> AsepriteFile<'a> {
-    fn new<'b: 'a>(file: RawFile<'b>) -> Result<Self, LoadSpriteError> {
+    fn new<'b: 'a>(file: RawFile<'b>, max_image_bytes: usize) -> Result<Self, LoadSpriteError> {
+        check_image_size(file.header.width, file.header.height, max_image_bytes)?;
         let mut color_profile = None;
         let mut palette = Palette::default();
         let mut frames = Vec::with_capacity(file.frames.len());
         let mut layers = Vec::new();
         let mut images = Vec::new();
         let mut tags = Vec::new();
+        let mut slices = Vec::new();
         let mut tilesets = Vec::new();
+        let mut external_files = Vec::new();
 
 
         let mut image_map = ahash::HashMap::default();
+        // (frame index, cel index) of the linked cels
+        let mut linked_cels = Vec::new();
 
         for raw_frame in file.frames.into_iter() {
             frames.push(Frame {
                 duration: raw_frame.duration as u32,
                 cells: Default::default(),
+                other_chunks: other_chunks(&raw_frame),
             });
             let mut chunk_it = raw_frame.chunks.into_iter().peekable();
             while let Some(chunk) = chunk_it.next() {
@@ -75,17 +159,31 @@ impl<'a> AsepriteFile<'a> {
                         //    "Color palettes are in FLI color chunks (it could be type=11 or type=4). For color depths more than 8bpp, palettes are optional."
                         //    Guessing type=11/4 is referring to the old palette chunks? This one is 0x2019
                         let req_len = chunk.first_index as usize + chunk.entries.len();
-                        if palette.colors.len() < req_len {
-                            palette.colors.resize(req_len, image::Rgba::<u8>::zeroed());
+                        // The header stores the color count in a word, anything past that is garbage
+                        if req_len > Word::MAX as usize + 1 {
+                            return Err(LoadSpriteError::Parse {
+                                message: format!("Palette entry {} is out of range", req_len - 1),
+                                offset: None,
+                            });
+                        }
+                        if palette.entries.len() < req_len {
+                            palette.entries.resize(req_len, (image::Rgba::<u8>::zeroed(), None));
                         }
 
                         for (idx, entry) in chunk.entries.iter().enumerate() {
-                            let c = &mut palette.colors[chunk.first_index as usize + idx]; 
+                            let (c, name) = &mut palette.entries[chunk.first_index as usize + idx]; 
                             c.0 = [entry.color.red, entry.color.green, entry.color.blue, entry.color.alpha];
+                            *name = entry.name.map(str::to_string);
                         }
                     } 
                     Chunk::Layer(chunk) => {
                         // In the first frame, should get all the layer chunks first, then all the actual data in the first frame (cells, etc.)
+                        if let LayerType::Unknown(layer_type) = chunk.layer_type {
+                            return Err(LoadSpriteError::Parse {
+                                message: format!("Layer {:?} has unknown type {layer_type}", chunk.name),
+                                offset: None,
+                            });
+                        }
                         let user_data = if let Some(Chunk::UserData(user_data)) =
                             chunk_it.next_if(Chunk::is_user_data)
                         {
@@ -102,8 +200,31 @@ impl<'a> AsepriteFile<'a> {
                     Chunk::Tileset(t) => {
                         tilesets.push(t);
                     }
+                    Chunk::ExternalFiles(chunk) => {
+                        // Referenced by id from tilesets/palettes stored in other files
+                        external_files.extend(chunk.files);
+                    }
                     // Everything below shows up after the above in the first frame, or in any frame after
                     Chunk::Cel(chunk) => {
+                        // Compositing indexes the layers with this, an invalid index would panic there
+                        if chunk.layer_index as usize >= layers.len() {
+                            return Err(LoadSpriteError::Parse {
+                                message: format!(
+                                    "Cel references layer {} but there are only {} layers",
+                                    chunk.layer_index,
+                                    layers.len()
+                                ),
+                                offset: None,
+                            });
+                        }
+                        // The cel extra chunk comes before the cel's user data
+                        let precise_bounds = if let Some(Chunk::CelExtra(cel_extra)) =
+                            chunk_it.next_if(Chunk::is_cel_extra)
+                        {
+                            cel_extra.precise_bounds()
+                        } else {
+                            None
+                        };
                         let user_data = if let Some(Chunk::UserData(user_data)) =
                             chunk_it.next_if(Chunk::is_user_data)
                         {
@@ -122,18 +243,21 @@ impl<'a> AsepriteFile<'a> {
                                 );
                                 image_index
                             }
-                            CelContent::LinkedCel { frame_position } => {
-                                image_map[&(frame_position as usize, chunk.layer_index)]
+                            CelContent::LinkedCel { .. } => {
+                                // Resolved once all the frames are loaded, the linked cel could come later
+                                linked_cels.push((frames.len() - 1, frames.last().unwrap().cells.len()));
+                                usize::MAX
                             }
                             CelContent::CompressedTilemap { .. } => {
                                 // "data" has all the tiles. A "tile" is a "bits_per_tile" bitmask, apparently always 32-bit right now.
                                 // & it with "bitmask_tile_id" to get the tile id, etc. for flips
                                 // To get the associated tileset -> get layer of cel -> layer should have "tileset index" -> index tilesets gotten in first frame
-                                todo!()
+                                return Err(LoadSpriteError::Unsupported("tilemaps"));
                             }
                             CelContent::Unknown(_) => {
                                 return Err(LoadSpriteError::Parse {
                                     message: "CelContent has unknown type!".to_string(),
+                                    offset: None,
                                 });
                             }
                         };
@@ -141,6 +265,7 @@ impl<'a> AsepriteFile<'a> {
                             chunk,
                             user_data,
                             image_index,
+                            precise_bounds,
                         });
                     }                   
                     Chunk::Tags(tags_chunk) => {
@@ -156,14 +281,24 @@ impl<'a> AsepriteFile<'a> {
                         }))
                     }
                     // below aren't needed for current functionality
-                    Chunk::Slice(_) => (), // what are these for?
-                    Chunk::ExternalFiles(_) => {} // Not sure in what situations external files are used
+                    Chunk::Slice(chunk) => {
+                        let user_data = if let Some(Chunk::UserData(user_data)) =
+                            chunk_it.next_if(Chunk::is_user_data)
+                        {
+                            user_data
+                        } else {
+                            Default::default()
+                        };
+                        slices.push(Slice { chunk, user_data });
+                    }
                     Chunk::UserData(_) => {} // we parse all of the ones we want in their respective sections
                     // Above might be useful
-                    Chunk::CelExtra(_) => {} // Not sure what this is for (precise position? width/height scaled in real time?)
+                    Chunk::CelExtra(_) => {} // parsed right after its cel
                     // below is old/deprecated
-                    Chunk::Palette0004(_) => {} // only used by old versions of ase
-                    Chunk::Palette0011(_) => {} // only used by old versions of ase
+                    // only used by old versions of ase, newer ones also save them next to 0x2019 for backwards compatibility.
+                    // Only indexed images would need them, see OldPaletteChunk::apply_to
+                    Chunk::Palette0004(_) => {}
+                    Chunk::Palette0011(_) => {}
                     Chunk::Mask(_) => {} // deprecated by ase
                     Chunk::Path => {} // unused by ase
                     Chunk::Unsupported(_) => {}
@@ -171,30 +306,79 @@ impl<'a> AsepriteFile<'a> {
             }
         }
 
-        if file.header.color_depth != ColorDepth::Rgba {
-            return Err(LoadSpriteError::Parse {
-                message: format!("Expecting color depth to be Rgba, not {:?}", file.header.color_depth),
-            })
+        for (frame_index, cel_index) in linked_cels {
+            let cel = &mut frames[frame_index].cells[cel_index];
+            let CelContent::LinkedCel { frame_position } = cel.chunk.content else {
+                unreachable!("only linked cels are left to resolve");
+            };
+            cel.image_index = *image_map.get(&(frame_position as usize, cel.chunk.layer_index)).ok_or_else(|| {
+                LoadSpriteError::Parse {
+                    message: format!(
+                        "Cel on layer {} links to frame {frame_position} which has no cel there",
+                        cel.chunk.layer_index
+                    ),
+                    offset: None,
+                }
+            })?;
+        }
+
+        for tag in tags.iter() {
+            // Exporting indexes the frames with the tag's range
+            let (from, to) = tag.chunk.frames;
+            if from > to || to as usize >= frames.len() {
+                return Err(LoadSpriteError::Parse {
+                    message: format!(
+                        "Tag {:?} covers frames {from}..={to} but there are only {} frames",
+                        tag.name(),
+                        frames.len()
+                    ),
+                    offset: None,
+                });
+            }
+        }
+
+        match file.header.color_depth {
+            ColorDepth::Rgba => (),
+            ColorDepth::Grayscale => return Err(LoadSpriteError::Unsupported("grayscale color depth")),
+            ColorDepth::Indexed => return Err(LoadSpriteError::Unsupported("indexed color depth")),
+            ColorDepth::Unknown(bpp) => return Err(LoadSpriteError::Parse {
+                message: format!("Unknown color depth {bpp}"),
+                offset: None,
+            }),
         }
 
         let mut decompressor = flate2::Decompress::new(true);
-        let images_decompressed: Result<Vec<_>, _> = images.iter().map(|image| {
+        let images_decompressed: Result<Vec<_>, _> = images.iter().enumerate().map(|(index, image)| {
+            check_image_size(image.width, image.height, max_image_bytes)?;
             let img = if image.compressed {
+                // zlib can't do better than ~1032:1, don't allocate a huge buffer for a few bytes
+                if image.pixel_count() * 4 > image.data.len().saturating_mul(1032) + 1024 {
+                    return Err(LoadSpriteError::Parse {
+                        message: format!(
+                            "{} bytes of compressed data can't hold a {}x{} image",
+                            image.data.len(),
+                            image.width,
+                            image.height
+                        ),
+                        offset: None,
+                    });
+                }
                 // Pretty sure the images are always compressed
                 //let mut buf = vec![0; image.pixel_count() * 4];
                 let mut buf = image::RgbaImage::new(image.width as u32, image.height as u32);
                 decompressor.reset(true);
-                decompressor.decompress(image.data, &mut buf, flate2::FlushDecompress::Finish)
-                    .map_err(|e| 
-                        LoadSpriteError::Parse {
-                            message: format!("failed to decompress: {e}"),
-                        }
-                    )?;
+                let status = decompressor.decompress(image.data, &mut buf, flate2::FlushDecompress::Finish)
+                    .map_err(|e| LoadSpriteError::Image { index, source: e.into() })?;
+                if status != flate2::Status::StreamEnd {
+                    let source = crate::make_image::LoadImageError::TruncatedData { decoded: decompressor.total_out() as usize, expected: buf.len() };
+                    return Err(LoadSpriteError::Image { index, source });
+                }
                 buf
             } else {
                 image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.data.to_owned())
                     .ok_or_else(|| LoadSpriteError::Parse {
                         message:"image::RgbaImage::from_raw error".to_string(),
+                        offset: None,
                     })?
             };
 
@@ -203,31 +387,82 @@ impl<'a> AsepriteFile<'a> {
 
         let images_decompressed = images_decompressed?;
 
-        Ok(Self {
+        let mut out = Self {
             header: file.header,
             color_profile: color_profile.ok_or_else(|| LoadSpriteError::Parse {
                 message: "Color profile chunk not found".to_string(),
+                offset: None,
             })?,
             palette,
             layers,
             frames,
             tags,
+            slices,
             images,
             images_decompressed,
             tilesets,
-        })
+            external_files,
+            max_image_bytes,
+        };
+        // Identical cels are common on these layers, elsewhere hashing every image isn't worth it
+        if out.layers.iter().any(Layer::prefers_linked_cels) {
+            let preferred = out.layers.iter().map(Layer::prefers_linked_cels).collect::<Vec<_>>();
+            out.dedup_images_where(|layer_index| preferred[layer_index]);
+        }
+        Ok(out)
     }
 
     /// Load a aseprite file from a byte slice
     pub fn from_bytes<'b: 'a>(data: &'b [u8]) -> Result<AsepriteFile<'a>, LoadSpriteError> {
-        let raw_file = parse_raw_file(data).map_err(|e| LoadSpriteError::Parse {
-            message: e.to_string(),
-        })?;
-        
-        let ase = Self::new(raw_file)?;
+        Self::from_bytes_with_limit(data, DEFAULT_MAX_IMAGE_BYTES)
+    }
+
+    /// Same as [`Self::from_bytes`], but fails with [`LoadSpriteError::ImageTooLarge`] when the canvas
+    /// or a cel image would take more than `max_image_bytes` once decoded, instead of [`DEFAULT_MAX_IMAGE_BYTES`].
+    /// Lower it when loading untrusted files, the header alone can claim a canvas of several gigabytes.
+    pub fn from_bytes_with_limit<'b: 'a>(data: &'b [u8], max_image_bytes: usize) -> Result<AsepriteFile<'a>, LoadSpriteError> {
+        let raw_file = parse(data)?;
+        let ase = Self::new(raw_file, max_image_bytes)?;
         Ok(ase)
     }
 
+    /// Same as [`Self::from_bytes`], but first looks for everything in the file this crate can't load
+    /// or doesn't render like Aseprite does: color depths other than RGBA, tilemaps and blend modes
+    /// that aren't implemented. Reports all of them instead of stopping at the first one.
+    /// Images are limited to `max_image_bytes`, see [`Self::from_bytes_with_limit`].
+    pub fn from_bytes_validated<'b: 'a>(data: &'b [u8], max_image_bytes: usize) -> Result<AsepriteFile<'a>, Vec<LoadSpriteError>> {
+        let raw_file = parse(data).map_err(|e| vec![e])?;
+
+        let mut errors = Vec::new();
+        match raw_file.header.color_depth {
+            ColorDepth::Rgba | ColorDepth::Unknown(_) => (),
+            ColorDepth::Grayscale => errors.push(LoadSpriteError::Unsupported("grayscale color depth")),
+            ColorDepth::Indexed => errors.push(LoadSpriteError::Unsupported("indexed color depth")),
+        }
+        let layers = raw_file.frames.iter().flat_map(|frame| &frame.chunks).filter_map(|chunk| match chunk {
+            Chunk::Layer(layer) => Some(layer),
+            _ => None,
+        });
+        let mut tilemaps = false;
+        for layer in layers {
+            tilemaps |= matches!(layer.layer_type, LayerType::Tilemap);
+            if !crate::make_image::renders_blend_mode(layer.blend_mode) {
+                errors.push(LoadSpriteError::UnsupportedBlendMode {
+                    layer: layer.name.to_string(),
+                    blend_mode: layer.blend_mode,
+                });
+            }
+        }
+        if tilemaps {
+            errors.push(LoadSpriteError::Unsupported("tilemaps"));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Self::new(raw_file, max_image_bytes).map_err(|e| vec![e])
+    }
+
     pub fn canvas_height(&self) -> u16 {
         self.header.height
     }
@@ -239,4 +474,723 @@ impl<'a> AsepriteFile<'a> {
     pub fn pixel_count(&self) -> usize {
         self.header.width as usize * self.header.height as usize
     }
+
+    /// Number of frames in the file
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// assert_eq!(file.frame_count(), 3);
+    /// assert_eq!(file.layer_count(), 3);
+    /// assert_eq!(file.tag_count(), 0);
+    /// ```
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the file is a single still image: one frame, and so no tag that spans more than that frame
+    pub fn is_static(&self) -> bool {
+        self.frames.len() == 1 && self.tags.iter().all(|tag| tag.frame_range() == (0..=0))
+    }
+
+    /// The frame count of the header and the number of frames actually in the file, when they disagree.
+    /// Some tools write a wrong count, the frames in the file are the ones loaded.
+    pub fn frame_count_mismatch(&self) -> Option<(Word, usize)> {
+        (self.header.frames as usize != self.frames.len()).then_some((self.header.frames, self.frames.len()))
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn tag_count(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Get a tag by name
+    pub fn tag(&self, name: &str) -> Option<&Tag<'a>> {
+        self.tags.iter().find(|t| t.name() == name)
+    }
+
+    /// The names of the tags, in file order
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/tags.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// assert_eq!(file.tag_names().collect::<Vec<_>>(), ["Tag 1", "Tag 2", "Tag 3"]);
+    /// ```
+    pub fn tag_names(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(Tag::name)
+    }
+
+    /// The tags in the order they start, then end, for timelines. Tags spanning the same frames keep their file order.
+    pub fn tags_by_frame(&self) -> Vec<&Tag<'a>> {
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort_by_key(|tag| (*tag.frame_range().start(), *tag.frame_range().end()));
+        tags
+    }
+
+    /// Every blend mode set on a layer of the file, to warn about modes that aren't rendered like Aseprite does
+    pub fn used_blend_modes(&self) -> BTreeSet<BlendMode> {
+        self.layers.iter().map(|layer| layer.chunk.blend_mode).collect()
+    }
+
+    /// The layers shown as authored, with their index: visible themselves and inside visible groups.
+    /// Groups are layers too and are included when shown.
+    pub fn visible_layers(&self) -> impl Iterator<Item = (usize, &Layer<'a>)> {
+        // whether the group at each child level above the current layer is shown
+        let mut shown: Vec<bool> = Vec::new();
+        self.layers.iter().enumerate().filter(move |(_, layer)| {
+            let level = layer.chunk.child_level as usize;
+            shown.truncate(level);
+            let parent = shown.last().copied().unwrap_or(true);
+            shown.resize(level, parent);
+            let visible = parent && layer.visible();
+            shown.push(visible);
+            visible
+        })
+    }
+
+    /// Get a slice by name
+    pub fn slice(&self, name: &str) -> Option<&Slice<'a>> {
+        self.slices.iter().find(|s| s.name() == name)
+    }
+
+    /// The `(x, y, width, height)` of slice `name` on the canvas at `frame_index`.
+    /// `None` if there is no such slice, or it has no key yet at that frame.
+    pub fn slice_rect(&self, name: &str, frame_index: usize) -> Option<(i32, i32, u32, u32)> {
+        if frame_index >= self.frames.len() {
+            return None;
+        }
+        let key = self.slice(name)?.key_at(frame_index)?;
+        Some((key.x, key.y, key.width, key.height))
+    }
+
+    /// The pivot of slice `name` on the canvas at `frame_index`.
+    /// `None` if there is no such slice, it has no key yet at that frame or the key has no pivot.
+    pub fn slice_pivot(&self, name: &str, frame_index: usize) -> Option<(i32, i32)> {
+        if frame_index >= self.frames.len() {
+            return None;
+        }
+        let key = self.slice(name)?.key_at(frame_index)?;
+        // relative to the slice origin in the file
+        key.pivot.map(|pivot| (key.x + pivot.x, key.y + pivot.y))
+    }
+
+    /// The `(width, height)` of the cel of layer `layer_index` in frame `frame_index`, as stored in the file.
+    /// `None` if there is no such cel.
+    pub fn cel_dimensions(&self, frame_index: usize, layer_index: usize) -> Option<(Word, Word)> {
+        let cel = self.frames.get(frame_index)?.cell_at_layer_index(layer_index)?;
+        let image = self.images.get(cel.image_index)?;
+        Some((image.width, image.height))
+    }
+
+    /// The image at `image_index` (see [`Cel::image_index`]) as stored in the file:
+    /// `(data, compressed, width, height)`, with `data` zlib compressed if `compressed` is set.
+    /// For callers decoding the pixels themselves, e.g. on the GPU.
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/default.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// let cel = file.frames[0].cell_at_layer_index(0).unwrap();
+    /// let (data, compressed, width, height) = file.raw_cel(cel.image_index).unwrap();
+    /// assert!(compressed);
+    /// let img = &file.images_decompressed[cel.image_index];
+    /// assert_eq!((width as u32, height as u32), img.dimensions());
+    ///
+    /// use std::io::Read;
+    /// let mut pixels = Vec::new();
+    /// flate2::read::ZlibDecoder::new(data).read_to_end(&mut pixels).unwrap();
+    /// assert_eq!(&pixels, img.as_raw());
+    /// ```
+    pub fn raw_cel(&self, image_index: usize) -> Option<(&'a [u8], bool, Word, Word)> {
+        let image = self.images.get(image_index)?;
+        Some((image.data, image.compressed, image.width, image.height))
+    }
+
+    /// Number of cels on layer `layer_index`, over all frames
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// // layer 0 has a cel in every frame
+    /// assert_eq!(file.layer_cel_count(0), file.frame_count());
+    /// assert_eq!(file.layer_cel_count(1), 2);
+    /// assert_eq!(file.frame_cel_count(1), 2);
+    /// ```
+    pub fn layer_cel_count(&self, layer_index: usize) -> usize {
+        self.iter_cels().filter(|(_, index, _)| *index == layer_index).count()
+    }
+
+    /// Number of cels in frame `frame_index`, 0 if there is no such frame
+    pub fn frame_cel_count(&self, frame_index: usize) -> usize {
+        self.frames.get(frame_index).map_or(0, |frame| frame.cells.len())
+    }
+
+    /// Iterates over the cels of every frame as `(frame_index, layer_index, cel)`
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// assert_eq!(file.iter_cels().count(), 8);
+    /// // layer 1 has nothing drawn in frame 1
+    /// assert_eq!(file.iter_cels().filter(|(_, layer_index, _)| *layer_index == 1).count(), 2);
+    /// ```
+    pub fn iter_cels(&self) -> impl Iterator<Item = (usize, usize, &Cel<'a>)> {
+        self.frames.iter().enumerate().flat_map(|(frame_index, frame)| {
+            frame.cells.iter().map(move |cel| (frame_index, cel.layer_index(), cel))
+        })
+    }
+
+    /// The palette colors by index, with their names
+    pub fn palette_entries(&self) -> &[(image::Rgba<u8>, Option<String>)] {
+        &self.palette.entries
+    }
+
+    /// Whether at least one tag covers frame `frame_index`
+    pub fn is_tagged(&self, frame_index: usize) -> bool {
+        self.tags.iter().any(|t| t.frame_range().contains(&frame_index))
+    }
+
+    /// The frames covered by `tag`, or all frames if `tag` is `None`
+    pub fn frame_range(&self, tag: Option<&str>) -> Result<RangeInclusive<usize>, LoadSpriteError> {
+        match tag {
+            Some(name) => self
+                .tag(name)
+                .map(Tag::frame_range)
+                .ok_or_else(|| LoadSpriteError::MissingTag(name.to_string())),
+            None => match self.frames.len() {
+                0 => Err(LoadSpriteError::FrameIndexOutOfRange(0)),
+                len => Ok(0..=len - 1),
+            },
+        }
+    }
+
+    /// Each played frame of `tag` (or all frames, in order) with its `[start, end)` window in milliseconds
+    /// from the start of the animation. Follows the direction and repeat count of the tag, see [`TagTimeline::windows`].
+    /// Empty if there is no such tag.
+    pub fn frame_timeline(&self, tag: Option<&str>) -> Vec<(usize, Range<u32>)> {
+        let order = match tag {
+            Some(name) => match self.tag(name) {
+                Some(tag) => tag.repeated_order(),
+                None => return Vec::new(),
+            },
+            None => (0..self.frames.len()).collect(),
+        };
+        TagTimeline::new(self, order, LoopBehavior::Once).windows().collect()
+    }
+
+    pub fn color_profile_kind(&self) -> ColorProfileKind {
+        self.color_profile.kind()
+    }
+
+    /// The embedded ICC profile, for color managed pipelines
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.color_profile.icc_profile()
+    }
+
+    /// Get the external file with the given entry id, as referenced by
+    /// [`TilesetTiles::TilesetExternalFile`](crate::binary::chunks::tileset::TilesetTiles::TilesetExternalFile)
+    pub fn external_file(&self, entry_id: Dword) -> Option<&ExternalFile<'a>> {
+        self.external_files.iter().find(|f| f.entry_id == entry_id)
+    }
+
+    /// A human readable report of the layout of the file: canvas, color depth, layers, tags and frame durations.
+    /// Unlike the `Debug` output it leaves the pixel data out and its format is stable, for logs and snapshot tests.
+    pub fn summary(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = writeln!(out, "canvas: {}x{}", self.canvas_width(), self.canvas_height());
+        let _ = writeln!(out, "color depth: {:?}", self.header.color_depth);
+        let _ = writeln!(out, "layers: {}", self.layers.len());
+        for (index, layer) in self.layers.iter().enumerate() {
+            let flags = layer.chunk.flags.iter_names().map(|(name, _)| name.to_lowercase()).collect::<Vec<_>>();
+            let _ = writeln!(
+                out,
+                "  {}{index}: {:?}, type {:?}, blend {:?}, opacity {}, flags [{}]",
+                // children of groups are indented
+                "  ".repeat(layer.chunk.child_level as usize),
+                layer.name(),
+                layer.chunk.layer_type,
+                layer.chunk.blend_mode,
+                layer.chunk.opacity,
+                flags.join(", "),
+            );
+        }
+        let _ = writeln!(out, "tags: {}", self.tags.len());
+        for tag in &self.tags {
+            let range = tag.frame_range();
+            let _ = writeln!(
+                out,
+                "  {:?}: frames {}..={}, {:?}, repeat {}",
+                tag.name(),
+                range.start(),
+                range.end(),
+                tag.chunk.animation_direction,
+                tag.chunk.animation_repeat,
+            );
+        }
+        let _ = writeln!(out, "frames: {}", self.frames.len());
+        for (index, frame) in self.frames.iter().enumerate() {
+            let _ = writeln!(out, "  {index}: {}ms, {} cels", frame.duration, frame.cells.len());
+        }
+        out
+    }
+
+    /// Collapses identical images into one, rewriting [`Cel::image_index`].
+    /// Linked cels already share their image, this also catches cels drawn identically on their own.
+    pub fn dedup_images(&mut self) {
+        self.dedup_images_where(|_| true);
+    }
+
+    /// Same as [`Self::dedup_images`], only for the images of cels on the layers `f` accepts.
+    /// Done when loading for the layers that [prefer linked cels](Layer::prefers_linked_cels).
+    pub fn dedup_images_where(&mut self, f: impl Fn(usize) -> bool) {
+        let mut candidates = vec![false; self.images_decompressed.len()];
+        for cel in self.frames.iter().flat_map(|frame| frame.cells.iter()) {
+            if f(cel.layer_index()) {
+                candidates[cel.image_index] = true;
+            }
+        }
+
+        let mut kept = Vec::with_capacity(self.images_decompressed.len());
+        let mut remap = Vec::with_capacity(self.images_decompressed.len());
+        {
+            let mut seen = ahash::HashMap::default();
+            let mut next = 0;
+            for (img, &candidate) in self.images_decompressed.iter().zip(&candidates) {
+                let index = if candidate { *seen.entry(img).or_insert(next) } else { next };
+                kept.push(index == next);
+                if index == next {
+                    next += 1;
+                }
+                remap.push(index);
+            }
+        }
+
+        let mut kept_it = kept.iter();
+        self.images.retain(|_| *kept_it.next().unwrap());
+        let mut kept_it = kept.iter();
+        self.images_decompressed.retain(|_| *kept_it.next().unwrap());
+
+        for cel in self.frames.iter_mut().flat_map(|f| f.cells.iter_mut()) {
+            cel.image_index = remap[cel.image_index];
+        }
+    }
+}
+
+#[test]
+fn test_dedup_images() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let pixels = [255, 0, 0, 255].repeat(4);
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the only layer, chunks[4] its cel
+        let layer = chunks[3].clone();
+        chunks[4] = raw_cel(0, (1, 1), (2, 2), &pixels);
+        chunks.push(layer);
+        chunks.push(raw_cel(1, (5, 5), (2, 2), &pixels));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.images_decompressed.len(), 2);
+
+    file.dedup_images();
+    assert_eq!(file.images.len(), 1);
+    assert_eq!(file.images_decompressed.len(), 1);
+    let cells = &file.frames[0].cells;
+    assert_eq!(cells[0].image_index, 0);
+    assert_eq!(cells[1].image_index, 0);
+    assert_eq!(file.images_decompressed[0].as_raw(), &pixels);
+
+    // Distinct images are left alone
+    let input = fixture("animated.aseprite");
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    let before = (0..file.frames.len()).map(|i| file.combined_frame_image(i).unwrap()).collect::<Vec<_>>();
+    file.dedup_images();
+    let after = (0..file.frames.len()).map(|i| file.combined_frame_image(i).unwrap()).collect::<Vec<_>>();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn test_cel_dimensions() {
+    for name in ["combine.aseprite", "linkedcells.aseprite"] {
+        let input = crate::binary::test_utils::fixture(name);
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        let mut checked = 0;
+        for (frame_index, frame) in file.frames.iter().enumerate() {
+            for layer_index in 0..file.layers.len() {
+                let dimensions = file.cel_dimensions(frame_index, layer_index);
+                match frame.cell_at_layer_index(layer_index) {
+                    Some(cel) => {
+                        let (width, height) = dimensions.unwrap();
+                        assert_eq!((width as u32, height as u32), file.images_decompressed[cel.image_index].dimensions());
+                        checked += 1;
+                    }
+                    None => assert_eq!(dimensions, None),
+                }
+            }
+        }
+        assert!(checked > 0);
+        assert_eq!(file.cel_dimensions(file.frames.len(), 0), None);
+    }
+}
+
+#[test]
+fn test_parse_error_offset() {
+    use crate::binary::test_utils::{fixture, frame_bytes, truncated};
+
+    let input = fixture("animated.aseprite");
+    // cut the first frame in half
+    let first_frame = frame_bytes(&input, 0);
+    let cut = (first_frame.start + first_frame.end) / 2;
+    let truncated = truncated(&input, cut);
+
+    match AsepriteFile::from_bytes(&truncated) {
+        Err(e @ LoadSpriteError::Parse { offset: Some(offset), .. }) => {
+            // parsing stops somewhere in the first frame
+            assert!((first_frame.start..=cut).contains(&offset), "{offset}");
+            assert!(e.to_string().contains(&format!("at byte {offset}")), "{e}");
+        }
+        other => panic!("expected a parse error with an offset, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_corrupt_zlib_stream() {
+    use crate::binary::test_utils::{compressed_cel, edit_chunks, fixture, zlib};
+    use std::error::Error;
+
+    let pixels = zlib(&[255; 4 * 4 * 4]);
+    // chunks[4] is the cel of the only layer
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // garbage after the zlib header
+        let mut data = pixels.clone();
+        data[2..].fill(0xFF);
+        chunks[4] = compressed_cel(0, (0, 0), (4, 4), &data);
+    });
+    let err = AsepriteFile::from_bytes(&input).unwrap_err();
+    assert!(matches!(err, LoadSpriteError::Image { index: 0, .. }), "{err:?}");
+    // the causes are reported by `source`, not repeated in the message
+    assert_eq!(err.to_string(), "image 0 can't be decoded");
+    let source = err.source().unwrap().downcast_ref::<crate::make_image::LoadImageError>().unwrap();
+    assert!(matches!(source, crate::make_image::LoadImageError::DecompressError(_)));
+    assert_eq!(source.to_string(), "decompression failed");
+    // the detail from flate2
+    assert!(source.source().is_some());
+
+    // a stream cut short doesn't silently leave the image half empty
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = compressed_cel(0, (0, 0), (4, 4), &pixels[..pixels.len() - 4]);
+    });
+    let err = AsepriteFile::from_bytes(&input).unwrap_err();
+    let LoadSpriteError::Image { index: 0, source } = &err else {
+        panic!("expected an image error, got {err:?}");
+    };
+    assert!(matches!(source, crate::make_image::LoadImageError::TruncatedData { expected: 64, .. }), "{source:?}");
+}
+
+#[test]
+fn test_summary() {
+    let input = crate::binary::test_utils::fixture("animated.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let expected = "\
+canvas: 16x16
+color depth: Rgba
+layers: 2
+  0: \"Layer 1\", type Normal, blend Normal, opacity 255, flags [visible, editable]
+  1: \"Hitbox\", type Normal, blend Normal, opacity 255, flags [visible, editable]
+tags: 2
+  \"yipee\": frames 0..=3, Forward, repeat 0
+  \"ye\": frames 4..=7, Forward, repeat 0
+frames: 8
+  0: 100ms, 2 cels
+  1: 100ms, 2 cels
+  2: 100ms, 2 cels
+  3: 100ms, 2 cels
+  4: 100ms, 2 cels
+  5: 100ms, 2 cels
+  6: 100ms, 2 cels
+  7: 100ms, 2 cels
+";
+    assert_eq!(file.summary(), expected);
+}
+
+#[test]
+fn test_visible_layers() {
+    use crate::binary::chunks::layer::LayerFlags;
+    use crate::binary::test_utils::{edit_chunks, fixture, set_child_level, set_layer_flags, set_layer_type};
+
+    // (flags, type, child level) of the layers, after the one of the fixture
+    let layers = [
+        (LayerFlags::empty(), LayerType::Normal, 0), // 1: hidden
+        (LayerFlags::empty(), LayerType::Group, 0),  // 2: hidden group
+        (LayerFlags::VISIBLE, LayerType::Normal, 1), // 3: visible in the hidden group
+        (LayerFlags::VISIBLE, LayerType::Group, 0),  // 4: visible group
+        (LayerFlags::VISIBLE, LayerType::Normal, 1), // 5: visible in the visible group
+        (LayerFlags::empty(), LayerType::Normal, 1), // 6: hidden in the visible group
+        (LayerFlags::VISIBLE, LayerType::Normal, 0), // 7: visible
+    ];
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the only layer
+        for (flags, layer_type, child_level) in layers {
+            let mut layer = chunks[3].clone();
+            set_layer_flags(&mut layer, flags);
+            set_layer_type(&mut layer, layer_type);
+            set_child_level(&mut layer, child_level);
+            chunks.push(layer);
+        }
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.layers.len(), 8);
+    assert!(!file.layers[1].visible());
+
+    let visible = file.visible_layers().map(|(index, _)| index).collect::<Vec<_>>();
+    assert_eq!(visible, [0, 4, 5, 7]);
+    assert!(file.visible_layers().all(|(index, layer)| std::ptr::eq(layer, &file.layers[index])));
+}
+
+#[test]
+fn test_prefer_linked_cels_dedup() {
+    use crate::binary::chunks::layer::LayerFlags;
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel, set_layer_flags};
+
+    let pixels = [255, 0, 0, 255].repeat(4);
+    let edit = |flags: LayerFlags| edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the only layer, chunks[4] its cel
+        let mut layer = chunks[3].clone();
+        set_layer_flags(&mut chunks[3], flags);
+        chunks[4] = raw_cel(0, (1, 1), (2, 2), &pixels);
+        set_layer_flags(&mut layer, LayerFlags::VISIBLE);
+        chunks.push(layer);
+        chunks.push(raw_cel(1, (3, 3), (2, 2), &pixels));
+        chunks.push(raw_cel(1, (5, 5), (2, 2), &pixels));
+        chunks.push(raw_cel(0, (0, 0), (2, 2), &pixels));
+    });
+
+    let input = edit(LayerFlags::VISIBLE | LayerFlags::PREFER_LINKED_CELS);
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(file.layers[0].prefers_linked_cels());
+    assert!(!file.layers[1].prefers_linked_cels());
+    // the two cels of layer 0 share an image, the ones of layer 1 are left alone
+    let indices = file.frames[0].cells.iter().map(|c| (c.layer_index(), c.image_index)).collect::<Vec<_>>();
+    assert_eq!(indices, [(0, 0), (1, 1), (1, 2), (0, 0)]);
+    assert_eq!(file.images_decompressed.len(), 3);
+    assert_eq!(file.images.len(), 3);
+
+    let input = edit(LayerFlags::VISIBLE);
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.images_decompressed.len(), 4);
+}
+
+#[test]
+fn test_linked_cel_to_missing_cel() {
+    use crate::binary::test_utils::{edit_chunks, fixture, linked_cel};
+
+    for frame_position in [0, 5] {
+        let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+            chunks[4] = linked_cel(0, frame_position);
+        });
+        let Err(LoadSpriteError::Parse { message, .. }) = AsepriteFile::from_bytes(&input) else {
+            panic!("expected a parse error");
+        };
+        assert!(message.contains("links to frame"), "{message}");
+    }
+}
+
+#[test]
+fn test_corrupt_input_does_not_panic() {
+    // xorshift, good enough to pick bytes to corrupt
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as usize
+    };
+    for name in ["animated.aseprite", "combine.aseprite", "tags.aseprite", "linkedcells.aseprite"] {
+        let input = crate::binary::test_utils::fixture(name);
+        for len in 0..input.len() {
+            let _ = AsepriteFile::from_bytes(&input[..len]);
+        }
+        for _ in 0..500 {
+            let mut corrupted = input.clone();
+            for _ in 0..1 + next() % 4 {
+                let index = next() % corrupted.len();
+                corrupted[index] = next() as u8;
+            }
+            let _ = AsepriteFile::from_bytes(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn test_forward_linked_cel() {
+    use crate::binary::{chunk_type::ChunkType, test_utils::{edit_chunks, fixture, is_chunk, linked_cel}};
+
+    let input = fixture("combine.aseprite");
+    let original = AsepriteFile::from_bytes(&input).unwrap();
+    let expected = &original.images_decompressed[original.frames[2].cell_at_layer_index(0).unwrap().image_index];
+    // Link the cel of layer 0 in the first frame to the one in the last frame
+    let input = edit_chunks(&input, 0, |chunks| {
+        // the first cel is the one of layer 0
+        let cel = chunks.iter().position(|c| is_chunk(c, ChunkType::Cel)).unwrap();
+        chunks[cel] = linked_cel(0, 2);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let cel = file.frames[0].cell_at_layer_index(0).unwrap();
+    assert_eq!(&file.images_decompressed[cel.image_index], expected);
+}
+
+#[test]
+fn test_cycle_order() {
+    use crate::binary::chunks::tags::AnimationDirection;
+
+    let input = crate::binary::test_utils::fixture("animated.aseprite");
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    let tag = file.tags.iter().position(|t| t.name() == "ye").unwrap();
+    // the repeat count doesn't matter for a single cycle
+    file.tags[tag].chunk.animation_repeat = 3;
+
+    let cases: [(_, &[usize]); 4] = [
+        (AnimationDirection::Forward, &[4, 5, 6, 7]),
+        (AnimationDirection::Reverse, &[7, 6, 5, 4]),
+        (AnimationDirection::PingPong, &[4, 5, 6, 7, 6, 5]),
+        (AnimationDirection::PingPongReverse, &[7, 6, 5, 4, 5, 6]),
+    ];
+    for (direction, expected) in cases {
+        file.tags[tag].chunk.animation_direction = direction;
+        assert_eq!(file.tags[tag].cycle_order(), expected, "{direction:?}");
+    }
+
+    // too short to turn around
+    file.tags[tag].chunk.frames = (4, 5);
+    file.tags[tag].chunk.animation_direction = AnimationDirection::PingPong;
+    assert_eq!(file.tags[tag].cycle_order(), [4, 5]);
+}
+
+#[test]
+fn test_frame_timeline() {
+    use crate::binary::chunks::tags::AnimationDirection;
+
+    let input = crate::binary::test_utils::fixture("animated.aseprite");
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    let check = |file: &AsepriteFile<'_>, tag: Option<&str>, order: &[usize]| {
+        let timeline = file.frame_timeline(tag);
+        assert_eq!(timeline.iter().map(|(index, _)| *index).collect::<Vec<_>>(), order);
+        let mut start = 0;
+        for (index, window) in timeline.iter() {
+            assert_eq!(window.start, start);
+            assert_eq!(window.len() as u32, file.frames[*index].duration);
+            start = window.end;
+        }
+        let total: u32 = order.iter().map(|&index| file.frames[index].duration).sum();
+        assert_eq!(start, total);
+    };
+
+    check(&file, None, &(0..file.frames.len()).collect::<Vec<_>>());
+    check(&file, Some("ye"), &[4, 5, 6, 7]);
+    assert!(file.frame_timeline(Some("missing")).is_empty());
+
+    let tag = file.tags.iter().position(|t| t.name() == "ye").unwrap();
+    file.tags[tag].chunk.animation_direction = AnimationDirection::Reverse;
+    file.tags[tag].chunk.animation_repeat = 2;
+    check(&file, Some("ye"), &[7, 6, 5, 4, 7, 6, 5, 4]);
+    file.tags[tag].chunk.animation_direction = AnimationDirection::PingPong;
+    file.tags[tag].chunk.animation_repeat = 0;
+    check(&file, Some("ye"), &[4, 5, 6, 7, 6, 5, 4]);
+    file.tags[tag].chunk.animation_direction = AnimationDirection::PingPongReverse;
+    file.tags[tag].chunk.animation_repeat = 3;
+    check(&file, Some("ye"), &[7, 6, 5, 4, 5, 6, 7, 6, 5, 4]);
+}
+
+#[test]
+fn test_used_blend_modes() {
+    use crate::binary::test_utils::{edit_chunks, fixture, set_blend_mode};
+
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the only layer, in Normal mode
+        for blend_mode in [BlendMode::Multiply, BlendMode::Hue, BlendMode::Multiply, BlendMode::Unknown(99)] {
+            let mut layer = chunks[3].clone();
+            set_blend_mode(&mut layer, blend_mode);
+            chunks.push(layer);
+        }
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(
+        file.used_blend_modes().into_iter().collect::<Vec<_>>(),
+        [BlendMode::Normal, BlendMode::Multiply, BlendMode::Hue, BlendMode::Unknown(99)]
+    );
+}
+
+#[test]
+fn test_frame_count_mismatch() {
+    use crate::binary::test_utils::{fixture, set_frame_count};
+
+    let mut input = fixture("animated.aseprite");
+    assert_eq!(AsepriteFile::from_bytes(&input).unwrap().frame_count_mismatch(), None);
+
+    for declared in [7u16, 9] {
+        set_frame_count(&mut input, declared);
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        assert_eq!(file.frames.len(), 8);
+        assert_eq!(file.frame_count_mismatch(), Some((declared, 8)));
+    }
+}
+
+#[test]
+fn test_from_bytes_validated() {
+    use crate::binary::test_utils::{edit_chunks, fixture, set_blend_mode, set_color_depth, tilemap_layer};
+
+    let input = fixture("default.aseprite");
+    assert!(AsepriteFile::from_bytes_validated(&input, DEFAULT_MAX_IMAGE_BYTES).is_ok());
+    // default.aseprite is 32x32
+    let errors = AsepriteFile::from_bytes_validated(&input, 32 * 32 * 4 - 1).unwrap_err();
+    assert!(matches!(errors[..], [LoadSpriteError::ImageTooLarge { width: 32, height: 32, .. }]), "{errors:?}");
+
+    let mut input = edit_chunks(&input, 0, |chunks| {
+        // chunks[3] is the only layer, give it a blend mode from a newer Aseprite and add a tilemap layer using tileset 0
+        let tilemap = tilemap_layer(&chunks[3], 0);
+        chunks.push(tilemap);
+        set_blend_mode(&mut chunks[3], BlendMode::Unknown(19));
+    });
+    set_color_depth(&mut input, 8);
+
+    assert!(AsepriteFile::from_bytes(&input).is_err());
+    let errors = AsepriteFile::from_bytes_validated(&input, DEFAULT_MAX_IMAGE_BYTES).unwrap_err();
+    assert_eq!(errors.len(), 3, "{errors:?}");
+    assert!(matches!(errors[0], LoadSpriteError::Unsupported("indexed color depth")));
+    assert!(matches!(
+        &errors[1],
+        LoadSpriteError::UnsupportedBlendMode { blend_mode: BlendMode::Unknown(19), .. }
+    ), "{errors:?}");
+    assert!(matches!(errors[2], LoadSpriteError::Unsupported("tilemaps")));
+}
+
+#[test]
+fn test_image_too_large() {
+    use crate::binary::test_utils::{fixture, set_canvas_size};
+
+    let mut input = fixture("default.aseprite");
+    set_canvas_size(&mut input, 0xffff, 0xffff);
+    let err = AsepriteFile::from_bytes(&input).unwrap_err();
+    assert!(matches!(
+        err,
+        LoadSpriteError::ImageTooLarge { width: 0xffff, height: 0xffff, limit: DEFAULT_MAX_IMAGE_BYTES }
+    ), "{err}");
+
+    // default.aseprite is 32x32
+    let input = fixture("default.aseprite");
+    let file = AsepriteFile::from_bytes_with_limit(&input, 32 * 32 * 4).unwrap();
+    assert_eq!(file.max_image_bytes, 32 * 32 * 4);
+    assert!(matches!(
+        AsepriteFile::from_bytes_with_limit(&input, 32 * 32 * 4 - 1),
+        Err(LoadSpriteError::ImageTooLarge { width: 32, height: 32, .. })
+    ));
 }
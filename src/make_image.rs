@@ -1,10 +1,11 @@
+use std::ops::RangeInclusive;
+
 use crate::{
-    binary::blend_mode::BlendMode, loader::AsepriteFile, wrappers::PixelExt
+    binary::{blend_mode::BlendMode, chunks::{color_profile::ColorProfileKind, layer::LayerType, tags::AnimationDirection}, palette::Palette}, loader::{AsepriteFile, LoadSpriteError}, wrappers::PixelExt
 };
 use image::Pixel;
 use thiserror::Error;
 
-#[allow(missing_copy_implementations)]
 #[derive(Error, Debug)]
 pub enum LoadImageError {
     #[error("target buffer too small")]
@@ -14,40 +15,676 @@ pub enum LoadImageError {
     #[error("unsupported color depth")]
     UnsupportedColorDepth,
     #[error("decompression failed")]
-    DecompressError,
+    DecompressError(#[from] flate2::DecompressError),
+    #[error("compressed data ends after {decoded} of {expected} bytes")]
+    TruncatedData {
+        decoded: usize,
+        expected: usize,
+    },
     #[error("invalid image data")]
     InvalidImageData,
     #[error("empty frame")]
     EmptyFrame,
+    #[error("no frames to draw")]
+    NoFrames,
+    /// A frame that isn't in the file, see [`LoadSpriteError::FrameIndexOutOfRange`]
+    #[error(transparent)]
+    Sprite(#[from] Box<LoadSpriteError>),
+    #[error("not a static sprite, it has {0} frames")]
+    NotStatic(usize),
+    /// An image made from the file would be bigger than [`AsepriteFile::max_image_bytes`]
+    #[error("a {width}x{height} output image takes more than the {limit} bytes allowed")]
+    OutputTooLarge {
+        width: u64,
+        height: u64,
+        limit: usize,
+    },
+}
+
+impl From<LoadSpriteError> for LoadImageError {
+    fn from(e: LoadSpriteError) -> Self {
+        Self::Sprite(Box::new(e))
+    }
+}
+
+/// A pixel buffer frames can be composited into, see [`AsepriteFile::combined_frame_image_into`].
+/// Lets a frame be drawn straight into a texture upload buffer instead of going through an [`image::RgbaImage`].
+pub trait PixelSink {
+    fn dimensions(&self) -> (u32, u32);
+    fn get_pixel(&self, x: u32, y: u32) -> image::Rgba<u8>;
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: image::Rgba<u8>);
+}
+
+impl PixelSink for image::RgbaImage {
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> image::Rgba<u8> {
+        *self.get_pixel(x, y)
+    }
+
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: image::Rgba<u8>) {
+        self.put_pixel(x, y, pixel);
+    }
+}
+
+/// The space color channels are blended in
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlendSpace {
+    /// Straight on the stored values, like Aseprite without color management
+    Encoded,
+    /// In linear light, the stored values being encoded with the sRGB curve
+    LinearSrgb,
+    /// In linear light, the stored values being encoded with a plain gamma curve
+    LinearGamma(f32),
+}
+
+impl BlendSpace {
+    fn of(kind: ColorProfileKind) -> Self {
+        match kind {
+            // without a profile there's no curve to undo, same as Aseprite without color management
+            ColorProfileKind::NoColorProfile => BlendSpace::Encoded,
+            ColorProfileKind::FixedGamma(gamma) => BlendSpace::LinearGamma(gamma as f32),
+            // ICC profiles aren't interpreted, most of them are close enough to sRGB
+            ColorProfileKind::Srgb | ColorProfileKind::EmbeddedIcc | ColorProfileKind::Unknown(_) => BlendSpace::LinearSrgb,
+        }
+    }
+
+    fn decode(self, c: f64) -> f64 {
+        match self {
+            BlendSpace::Encoded => c,
+            BlendSpace::LinearSrgb if c <= 0.04045 => c / 12.92,
+            BlendSpace::LinearSrgb => ((c + 0.055) / 1.055).powf(2.4),
+            BlendSpace::LinearGamma(gamma) => c.powf(gamma as f64),
+        }
+    }
+
+    fn encode(self, c: f64) -> f64 {
+        match self {
+            BlendSpace::Encoded => c,
+            BlendSpace::LinearSrgb if c <= 0.0031308 => c * 12.92,
+            BlendSpace::LinearSrgb => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            BlendSpace::LinearGamma(gamma) => c.powf(1.0 / gamma as f64),
+        }
+    }
+}
+
+/// Color of the lines between frames in [`AsepriteFile::contact_sheet`]
+pub const CONTACT_SHEET_SEPARATOR: image::Rgba<u8> = image::Rgba([255, 0, 255, 255]);
+
+/// Whether `blend_mode` is in [`blend_function`], the others leave the backdrop as is
+pub(crate) fn renders_blend_mode(blend_mode: BlendMode) -> bool {
+    blend_function(blend_mode).is_some()
+}
+
+/// Color math on channels from 0 to 255. In [`BlendSpace::Encoded`] every step rounds or truncates
+/// to integers like the 8 bit math of Aseprite, the linear spaces keep the fractions.
+#[derive(Debug, Clone, Copy)]
+struct ChannelMath {
+    integer: bool,
+}
+
+impl ChannelMath {
+    fn of(space: BlendSpace) -> Self {
+        Self { integer: space == BlendSpace::Encoded }
+    }
+
+    /// `a * b / 255`, `MUL_UN8` in Aseprite
+    fn mul(self, a: f64, b: f64) -> f64 {
+        if self.integer {
+            mul_un8(a as i32, b as i32) as f64
+        } else {
+            a * b / 255.0
+        }
+    }
+
+    /// `a * 255 / b`, `DIV_UN8` in Aseprite
+    fn div(self, a: f64, b: f64) -> f64 {
+        if self.integer {
+            ((a as i32 * 255 + b as i32 / 2) / b as i32) as f64
+        } else {
+            a * 255.0 / b
+        }
+    }
+
+    fn trunc(self, c: f64) -> f64 {
+        if self.integer { c.trunc() } else { c }
+    }
+
+    fn round(self, c: f64) -> f64 {
+        if self.integer { (c + 0.5).floor() } else { c }
+    }
+}
+
+/// `a * b / 255` rounded, including for negative `a`, same as `MUL_UN8` in Aseprite
+fn mul_un8(a: i32, b: i32) -> i32 {
+    let t = a * b + 0x80;
+    ((t >> 8) + t) >> 8
+}
+
+/// Mixes the color channels of the backdrop with those of the source, see [`blend_function`]
+type BlendFunction = fn(ChannelMath, [f64; 3], [f64; 3]) -> [f64; 3];
+
+/// The blend modes Aseprite has, ported from its `doc/blend_funcs.cpp`.
+/// The functions take and return RGB from 0 to 255, the alpha is handled by [`blend_pixel`].
+fn blend_function(blend_mode: BlendMode) -> Option<BlendFunction> {
+    fn each(b: [f64; 3], s: [f64; 3], f: impl Fn(f64, f64) -> f64) -> [f64; 3] {
+        [f(b[0], s[0]), f(b[1], s[1]), f(b[2], s[2])]
+    }
+    fn hard_light(math: ChannelMath, b: f64, s: f64) -> f64 {
+        if s < 127.5 {
+            math.mul(b, 2.0 * s)
+        } else {
+            let s = 2.0 * s - 255.0;
+            b + s - math.mul(b, s)
+        }
+    }
+    fn soft_light(math: ChannelMath, b: f64, s: f64) -> f64 {
+        let (b, s) = (b / 255.0, s / 255.0);
+        let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+        let r = if s <= 0.5 { b - (1.0 - 2.0 * s) * b * (1.0 - b) } else { b + (2.0 * s - 1.0) * (d - b) };
+        math.round(r * 255.0)
+    }
+    fn hsl(math: ChannelMath, rgb: [f64; 3]) -> [f64; 3] {
+        rgb.map(|c| math.trunc(c * 255.0))
+    }
+
+    let function: BlendFunction = match blend_mode {
+        BlendMode::Normal => |_, _, s| s,
+        BlendMode::Multiply => |math, b, s| each(b, s, |b, s| math.mul(b, s)),
+        BlendMode::Screen => |math, b, s| each(b, s, |b, s| b + s - math.mul(b, s)),
+        BlendMode::Overlay => |math, b, s| each(b, s, |b, s| hard_light(math, s, b)),
+        BlendMode::Darken => |_, b, s| each(b, s, f64::min),
+        BlendMode::Lighten => |_, b, s| each(b, s, f64::max),
+        BlendMode::ColorDodge => |math, b, s| {
+            each(b, s, |b, s| match 255.0 - s {
+                _ if b == 0.0 => 0.0,
+                s if b >= s => 255.0,
+                s => math.div(b, s),
+            })
+        },
+        BlendMode::ColorBurn => |math, b, s| {
+            each(b, s, |b, s| match 255.0 - b {
+                _ if b == 255.0 => 255.0,
+                b if b >= s => 0.0,
+                b => 255.0 - math.div(b, s),
+            })
+        },
+        BlendMode::HardLight => |math, b, s| each(b, s, |b, s| hard_light(math, b, s)),
+        BlendMode::SoftLight => |math, b, s| each(b, s, |b, s| soft_light(math, b, s)),
+        BlendMode::Difference => |_, b, s| each(b, s, |b, s| (b - s).abs()),
+        BlendMode::Exclusion => |math, b, s| each(b, s, |b, s| b + s - 2.0 * math.mul(b, s)),
+        BlendMode::Hue => |math, b, s| {
+            let (b, s) = (b.map(|c| c / 255.0), s.map(|c| c / 255.0));
+            hsl(math, set_lum(set_sat(s, sat(b)), lum(b)))
+        },
+        BlendMode::Saturation => |math, b, s| {
+            let (b, s) = (b.map(|c| c / 255.0), s.map(|c| c / 255.0));
+            hsl(math, set_lum(set_sat(b, sat(s)), lum(b)))
+        },
+        BlendMode::Color => |math, b, s| {
+            let (b, s) = (b.map(|c| c / 255.0), s.map(|c| c / 255.0));
+            hsl(math, set_lum(s, lum(b)))
+        },
+        BlendMode::Luminosity => |math, b, s| {
+            let (b, s) = (b.map(|c| c / 255.0), s.map(|c| c / 255.0));
+            hsl(math, set_lum(b, lum(s)))
+        },
+        BlendMode::Addition => |_, b, s| each(b, s, |b, s| (b + s).min(255.0)),
+        BlendMode::Subtract => |_, b, s| each(b, s, |b, s| (b - s).max(0.0)),
+        BlendMode::Divide => |math, b, s| {
+            each(b, s, |b, s| match b {
+                _ if b == 0.0 => 0.0,
+                _ if b >= s => 255.0,
+                _ => math.div(b, s),
+            })
+        },
+        BlendMode::Unknown(_) => return None,
+    };
+    Some(function)
+}
+
+fn lum([r, g, b]: [f64; 3]) -> f64 {
+    0.3 * r + 0.59 * g + 0.11 * b
+}
+
+fn sat([r, g, b]: [f64; 3]) -> f64 {
+    r.max(g.max(b)) - r.min(g.min(b))
 }
 
-fn blend_channel(first: u8, second: u8, alpha: u8, blend_mode: BlendMode) -> u8 {
-    let alpha = alpha as f32 / u8::MAX as f32;
-    let first = first as f32 / u8::MAX as f32;
-    let second = second as f32 / u8::MAX as f32;
-
-    let result = match blend_mode {
-        BlendMode::Normal => second,
-        BlendMode::Multiply => first * second,
-        BlendMode::Screen => 1.0 - (1.0 - first) * (1.0 - second),
-        BlendMode::Darken => first.min(second),
-        BlendMode::Lighten => first.max(second),
-        BlendMode::Addition => (first + second).min(1.0),
-        BlendMode::Subtract => (first - second).max(0.0),
-        BlendMode::Difference => (first - second).abs(),
-        BlendMode::Overlay => {
-            if first < 0.5 {
-                2.0 * first * second
-            } else {
-                1.0 - 2.0 * (1.0 - first) * (1.0 - second)
+fn clip_color(mut rgb: [f64; 3]) -> [f64; 3] {
+    let l = lum(rgb);
+    let [r, g, b] = rgb;
+    let (n, x) = (r.min(g.min(b)), r.max(g.max(b)));
+    if n < 0.0 {
+        rgb = rgb.map(|c| l + (c - l) * l / (l - n));
+    }
+    if x > 1.0 {
+        rgb = rgb.map(|c| l + (c - l) * (1.0 - l) / (x - l));
+    }
+    rgb
+}
+
+fn set_lum(rgb: [f64; 3], l: f64) -> [f64; 3] {
+    let d = l - lum(rgb);
+    clip_color(rgb.map(|c| c + d))
+}
+
+/// Aseprite picks the min, mid and max channels as references with the `MIN`, `MID` and `MAX` macros,
+/// on ties some of them are the same channel and the later writes win. Same here with indices.
+fn set_sat(mut rgb: [f64; 3], s: f64) -> [f64; 3] {
+    let [r, g, b] = rgb;
+    let min = if r < g.min(b) { 0 } else if g < b { 1 } else { 2 };
+    let mid = if r > g {
+        if g > b { 1 } else if r > b { 2 } else { 0 }
+    } else if g > b {
+        if b > r { 2 } else { 0 }
+    } else {
+        1
+    };
+    let max = if r > g.max(b) { 0 } else if g > b { 1 } else { 2 };
+    if rgb[max] > rgb[min] {
+        rgb[mid] = (rgb[mid] - rgb[min]) * s / (rgb[max] - rgb[min]);
+        rgb[max] = s;
+    } else {
+        rgb[mid] = 0.0;
+        rgb[max] = 0.0;
+    }
+    rgb[min] = 0.0;
+    rgb
+}
+
+/// A pixel while it's blended: the color in the blend space from 0 to 255, the alpha as is
+#[derive(Debug, Clone, Copy)]
+struct BlendPixel {
+    rgb: [f64; 3],
+    alpha: i32,
+}
+
+impl BlendPixel {
+    fn decode(pixel: image::Rgba<u8>, space: BlendSpace) -> Self {
+        let [r, g, b, a] = pixel.0;
+        Self { rgb: [r, g, b].map(|c| space.decode(c as f64 / 255.0) * 255.0), alpha: a as i32 }
+    }
+
+    fn encode(self, space: BlendSpace) -> image::Rgba<u8> {
+        let [r, g, b] = self.rgb.map(|c| (space.encode((c / 255.0).clamp(0.0, 1.0)) * 255.0).round() as u8);
+        image::Rgba([r, g, b, self.alpha as u8])
+    }
+
+    /// `rgba_blender_normal` in Aseprite
+    fn normal(math: ChannelMath, backdrop: Self, src: Self, opacity: i32) -> Self {
+        if backdrop.alpha == 0 {
+            return Self { alpha: mul_un8(src.alpha, opacity), ..src };
+        }
+        if src.alpha == 0 {
+            return backdrop;
+        }
+        let src_alpha = mul_un8(src.alpha, opacity);
+        let alpha = src_alpha + backdrop.alpha - mul_un8(backdrop.alpha, src_alpha);
+        let mut rgb = backdrop.rgb;
+        for (c, s) in rgb.iter_mut().zip(src.rgb) {
+            *c += math.trunc((s - *c) * src_alpha as f64 / alpha as f64);
+        }
+        Self { rgb, alpha }
+    }
+
+    /// `rgba_blender_merge` in Aseprite
+    fn merge(math: ChannelMath, backdrop: Self, src: Self, opacity: i32) -> Self {
+        let mut rgb = if backdrop.alpha == 0 {
+            src.rgb
+        } else if src.alpha == 0 {
+            backdrop.rgb
+        } else {
+            let mut rgb = backdrop.rgb;
+            for (c, s) in rgb.iter_mut().zip(src.rgb) {
+                *c += math.mul(s - *c, opacity as f64);
             }
+            rgb
+        };
+        let alpha = backdrop.alpha + mul_un8(src.alpha - backdrop.alpha, opacity);
+        if alpha == 0 {
+            rgb = [0.0; 3];
         }
-        // @todo: missing modes
-        _ => first,
+        Self { rgb, alpha }
+    }
+}
+
+/// Blends `src` over `backdrop` like Aseprite, `opacity` multiplies the alpha of `src`.
+/// The blend modes other than Normal go through the `rgba_blender_*_n` functions of Aseprite,
+/// which fade the blended color into a plain Normal blend as the backdrop gets transparent.
+fn blend_pixel(backdrop: image::Rgba<u8>, src: image::Rgba<u8>, opacity: u8, blend_mode: BlendMode, space: BlendSpace) -> image::Rgba<u8> {
+    let Some(function) = blend_function(blend_mode) else {
+        return backdrop;
     };
+    let math = ChannelMath::of(space);
+    let (backdrop, src, opacity) = (BlendPixel::decode(backdrop, space), BlendPixel::decode(src, space), opacity as i32);
+
+    let normal = BlendPixel::normal(math, backdrop, src, opacity);
+    if blend_mode == BlendMode::Normal || backdrop.alpha == 0 {
+        return normal.encode(space);
+    }
+    let blended = BlendPixel::normal(math, backdrop, BlendPixel { rgb: function(math, backdrop.rgb, src.rgb), ..src }, opacity);
+    let faded = BlendPixel::merge(math, normal, blended, backdrop.alpha);
+    let composite_alpha = mul_un8(backdrop.alpha, mul_un8(src.alpha, opacity));
+    BlendPixel::merge(math, faded, blended, composite_alpha).encode(space)
+}
+
+/// Blends `img` onto `target` at `position` with `blend_mode`, the parts of `img` outside of `target` are cut off.
+/// `cel_opacity` is multiplied in on top of the layer opacity, `space` only applies to the color channels.
+fn blend_layer_image(
+    target: &mut impl PixelSink,
+    img: &image::RgbaImage,
+    position: (i32, i32),
+    cel_opacity: u8,
+    layer_opacity: u8,
+    blend_mode: BlendMode,
+    space: BlendSpace,
+) {
+    let (width, height) = target.dimensions();
+    let (left, top) = ((-position.0).max(0) as u32, (-position.1).max(0) as u32);
+    let right = (width as i64 - position.0 as i64).clamp(0, img.width() as i64) as u32;
+    let bottom = (height as i64 - position.1 as i64).clamp(0, img.height() as i64) as u32;
+
+    // Opaque pixels of a fully opaque Normal layer replace what's under them, same as blend_pixel
+    let opacity = mul_un8(cel_opacity as i32, layer_opacity as i32) as u8;
+    if opacity == 0 {
+        return;
+    }
+    let opaque_normal = opacity == u8::MAX && blend_mode == BlendMode::Normal;
+    for (x, y) in (top..bottom).flat_map(|y| (left..right).map(move |x| (x, y))) {
+        let cel_pixel = img.get_pixel(x, y);
+        let (x, y) = (x.wrapping_add_signed(position.0), y.wrapping_add_signed(position.1));
+        if opaque_normal && cel_pixel.a() == u8::MAX {
+            target.put_pixel(x, y, *cel_pixel);
+            continue;
+        }
+        let target_pixel = target.get_pixel(x, y);
+        target.put_pixel(x, y, blend_pixel(target_pixel, *cel_pixel, opacity, blend_mode, space));
+    }
+}
+
+fn premultiply(img: &mut image::RgbaImage) {
+    for pixel in img.pixels_mut() {
+        let alpha = pixel.a() as u16;
+        for c in pixel.channels_mut()[..3].iter_mut() {
+            *c = ((*c as u16 * alpha + u8::MAX as u16 / 2) / u8::MAX as u16) as u8;
+        }
+    }
+}
+
+/// Bounding box of the cels as `(x, y, width, height)`, without what's left of or above the canvas
+fn cel_bounds(cels: &[&crate::wrappers::Cel<'_>], images: &[image::RgbaImage]) -> Option<(u32, u32, u32, u32)> {
+    let (x, y, w, h) = cel_extent(cels, images)?;
+    let (right, bottom) = (x + w as i32, y + h as i32);
+    let (x, y) = (x.max(0), y.max(0));
+    (right > x && bottom > y).then(|| (x as u32, y as u32, (right - x) as u32, (bottom - y) as u32))
+}
+
+/// Bounding box of the cels as `(x, y, width, height)`, which can start left of or above the canvas
+fn cel_extent(cels: &[&crate::wrappers::Cel<'_>], images: &[image::RgbaImage]) -> Option<(i32, i32, u32, u32)> {
+    if cels.is_empty() {
+        return None;
+    }
+    let mut min_xy = (i32::MAX, i32::MAX);
+    let mut max_xy = (i32::MIN, i32::MIN);
+    for cel in cels {
+        let im = &images[cel.image_index];
+        let (x, y) = (cel.chunk.x as i32, cel.chunk.y as i32);
+        min_xy.0 = min_xy.0.min(x);
+        min_xy.1 = min_xy.1.min(y);
+        max_xy.0 = max_xy.0.max(x + im.width() as i32);
+        max_xy.1 = max_xy.1.max(y + im.height() as i32);
+    }
+    Some((min_xy.0, min_xy.1, (max_xy.0 - min_xy.0) as u32, (max_xy.1 - min_xy.1) as u32))
+}
+
+/// Greedily covers the non transparent pixels of `img` with `(x, y, width, height)` rectangles.
+/// Each rectangle starts at the first uncovered pixel in reading order, grows right as far as it can, then down.
+/// Not the minimal cover, but exact and good enough for hitboxes.
+fn opaque_rects(img: &image::RgbaImage) -> Vec<(u32, u32, u32, u32)> {
+    let (width, height) = img.dimensions();
+    let mut covered = vec![false; (width * height) as usize];
+    let free = |covered: &[bool], x: u32, y: u32| img.get_pixel(x, y).a() != 0 && !covered[(y * width + x) as usize];
+
+    let mut out = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !free(&covered, x, y) {
+                continue;
+            }
+            let w = (x..width).take_while(|&rx| free(&covered, rx, y)).count() as u32;
+            let h = (y..height).take_while(|&ry| (x..x + w).all(|rx| free(&covered, rx, ry))).count() as u32;
+            for ry in y..y + h {
+                covered[(ry * width + x) as usize..(ry * width + x + w) as usize].fill(true);
+            }
+            out.push((x, y, w, h));
+        }
+    }
+    out
+}
+
+/// The `(x, y, width, height)` of the pixels of `img` that aren't fully transparent, `None` if there are none
+fn opaque_bounds(img: &image::RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    content_bounds(img, |_| false)
+}
+
+/// Same as [`opaque_bounds`], `is_background` picks more pixels to trim away than the transparent ones
+fn content_bounds(img: &image::RgbaImage, is_background: impl Fn(&image::Rgba<u8>) -> bool) -> Option<(u32, u32, u32, u32)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.a() != 0 && !is_background(pixel) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    (min_x != u32::MAX).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// What [`indexed_to_rgba`] does with pixels whose index is past the end of the palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(not(test), allow(dead_code))] // indexed files are rejected by the loader for now
+pub(crate) enum OutOfRangeIndex {
+    /// Fail with [`LoadImageError::InvalidImageData`]
+    #[default]
+    Error,
+    /// Leave the pixel transparent
+    Transparent,
+}
+
+/// Converts indexed pixels to RGBA `target` with `palette`.
+/// A truncated palette chunk can leave indices without a color, see [`OutOfRangeIndex`].
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn indexed_to_rgba(
+    source: &[u8],
+    palette: &Palette,
+    target: &mut [u8],
+    out_of_range: OutOfRangeIndex,
+) -> Result<(), LoadImageError> {
+    if target.len() != source.len() * 4 {
+        return Err(LoadImageError::InvalidImageData);
+    }
+    for (px, out) in source.iter().zip(target.chunks_exact_mut(4)) {
+        let color = match (palette.entries.get(*px as usize), out_of_range) {
+            (Some((color, _)), _) => *color,
+            (None, OutOfRangeIndex::Transparent) => image::Rgba([0, 0, 0, 0]),
+            (None, OutOfRangeIndex::Error) => return Err(LoadImageError::InvalidImageData),
+        };
+        out.copy_from_slice(&color.0);
+    }
+    Ok(())
+}
+
+fn default_packer_config() -> texture_packer::TexturePackerConfig {
+    texture_packer::TexturePackerConfig {
+        max_width: 512,
+        max_height: 512,
+        allow_rotation: false,
+        texture_outlines: true,
+        border_padding: 0,
+        force_max_dimensions: false,
+        texture_padding: 0,
+        texture_extrusion: 0,
+        trim: false, // should already be trimmed but just in case, don't want to mess up offsets
+    }
+}
+
+/// Composites `cels` onto `target`, whose top left corner is at `offset` on the canvas.
+/// The cels of a layer are merged together first, otherwise the layer opacity would be applied twice where they overlap.
+/// `overrides` replace the blend mode or opacity of some layers, by layer index.
+fn composite_cels(
+    target: &mut impl PixelSink,
+    offset: (i32, i32),
+    cels: &[&crate::wrappers::Cel<'_>],
+    layers: &[crate::wrappers::Layer<'_>],
+    images: &[image::RgbaImage],
+    overrides: &ahash::HashMap<usize, LayerOverride>,
+    space: BlendSpace,
+) {
+    let compositor = CelCompositor::new(layers, images, overrides, space);
+    compositor.composite(target, offset, None, cels);
+}
+
+/// What [`composite_cels`] draws in one go: the cels of a layer, or of an isolated group (see [`CelCompositor`])
+enum CompositeStep<'c, 'a> {
+    Layer(usize, Vec<&'c crate::wrappers::Cel<'a>>),
+    Group(usize, Vec<&'c crate::wrappers::Cel<'a>>),
+}
+
+/// Does the work of [`composite_cels`].
+/// A group that isn't opaque or uses another blend mode than Normal is isolated, like in Aseprite:
+/// its children are composited on their own and the result is blended with the opacity and blend mode of the group.
+/// Other groups don't change how their children are drawn.
+struct CelCompositor<'c, 'a> {
+    layers: &'c [crate::wrappers::Layer<'a>],
+    images: &'c [image::RgbaImage],
+    overrides: &'c ahash::HashMap<usize, LayerOverride>,
+    space: BlendSpace,
+    /// For each layer, the closest group around it that is isolated
+    isolated_parent: Vec<Option<usize>>,
+}
+
+impl<'c, 'a> CelCompositor<'c, 'a> {
+    fn new(
+        layers: &'c [crate::wrappers::Layer<'a>],
+        images: &'c [image::RgbaImage],
+        overrides: &'c ahash::HashMap<usize, LayerOverride>,
+        space: BlendSpace,
+    ) -> Self {
+        let mut compositor = Self { layers, images, overrides, space, isolated_parent: Vec::with_capacity(layers.len()) };
+        // the groups around the current layer, by child level
+        let mut parents: Vec<usize> = Vec::new();
+        for (index, layer) in layers.iter().enumerate() {
+            parents.truncate(layer.chunk.child_level as usize);
+            let isolated_parent = parents.iter().rev().copied().find(|&group| compositor.is_isolated(group));
+            compositor.isolated_parent.push(isolated_parent);
+            if layer.chunk.layer_type == LayerType::Group {
+                parents.push(index);
+            }
+        }
+        compositor
+    }
+
+    fn opacity_and_blend_mode(&self, layer_index: usize) -> (u8, BlendMode) {
+        let layer = &self.layers[layer_index];
+        let layer_override = self.overrides.get(&layer_index).copied().unwrap_or_default();
+        (
+            layer_override.opacity.unwrap_or(layer.chunk.opacity),
+            layer_override.blend_mode.unwrap_or(layer.chunk.blend_mode),
+        )
+    }
+
+    fn is_isolated(&self, group: usize) -> bool {
+        self.opacity_and_blend_mode(group) != (u8::MAX, BlendMode::Normal)
+    }
+
+    /// The outermost isolated group around `layer_index` inside `within`, `None` if the layer is drawn directly
+    fn outer_isolated_group(&self, layer_index: usize, within: Option<usize>) -> Option<usize> {
+        let mut outer = None;
+        let mut group = self.isolated_parent[layer_index];
+        while let Some(index) = group {
+            if Some(index) == within {
+                break;
+            }
+            outer = Some(index);
+            group = self.isolated_parent[index];
+        }
+        outer
+    }
+
+    /// Composites the `cels` inside the isolated group `within`, or the whole frame for `None`
+    fn composite(
+        &self,
+        target: &mut impl PixelSink,
+        offset: (i32, i32),
+        within: Option<usize>,
+        cels: &[&'c crate::wrappers::Cel<'a>],
+    ) {
+        // Like Aseprite, cels are drawn in the order of `layer index + z-index`, the lower z-index first on ties.
+        // The sort is stable so cels of the same layer and z-index stay together and get merged.
+        let mut cels = cels.to_vec();
+        cels.sort_by_key(|cel| (cel.layer_index() as i64 + cel.z_index() as i64, cel.z_index()));
+        let mut steps: Vec<CompositeStep<'c, 'a>> = Vec::new();
+        for cel in cels {
+            match self.outer_isolated_group(cel.layer_index(), within) {
+                None => match steps.last_mut() {
+                    Some(CompositeStep::Layer(layer_index, layer_cels)) if *layer_index == cel.layer_index() => layer_cels.push(cel),
+                    _ => steps.push(CompositeStep::Layer(cel.layer_index(), vec![cel])),
+                },
+                Some(group) => {
+                    let existing = steps.iter_mut().find_map(|step| match step {
+                        CompositeStep::Group(index, group_cels) if *index == group => Some(group_cels),
+                        _ => None,
+                    });
+                    match existing {
+                        Some(group_cels) => group_cels.push(cel),
+                        None => steps.push(CompositeStep::Group(group, vec![cel])),
+                    }
+                }
+            }
+        }
+
+        for step in steps {
+            match step {
+                CompositeStep::Layer(layer_index, layer_cels) => self.composite_layer(target, offset, layer_index, &layer_cels),
+                CompositeStep::Group(group, group_cels) => {
+                    let (width, height) = target.dimensions();
+                    let mut scratch = image::RgbaImage::new(width, height);
+                    self.composite(&mut scratch, offset, Some(group), &group_cels);
+                    let (opacity, blend_mode) = self.opacity_and_blend_mode(group);
+                    blend_layer_image(target, &scratch, (0, 0), u8::MAX, opacity, blend_mode, self.space);
+                }
+            }
+        }
+    }
 
-    let blended = first * (1.0 - alpha) + result * alpha;
-    (blended.clamp(0.0, 1.0) * 255.0).round() as u8
+    fn composite_layer(
+        &self,
+        target: &mut impl PixelSink,
+        offset: (i32, i32),
+        layer_index: usize,
+        layer_cels: &[&crate::wrappers::Cel<'_>],
+    ) {
+        let (opacity, blend_mode) = self.opacity_and_blend_mode(layer_index);
+        if let [cel] = layer_cels[..] {
+            let position = (cel.chunk.x as i32 - offset.0, cel.chunk.y as i32 - offset.1);
+            blend_layer_image(target, &self.images[cel.image_index], position, cel.opacity(), opacity, blend_mode, self.space);
+            return;
+        }
+
+        let Some((min_x, min_y, w, h)) = cel_extent(layer_cels, self.images) else {
+            return;
+        };
+        let mut merged = image::RgbaImage::new(w, h);
+        for cel in layer_cels {
+            let position = (cel.chunk.x as i32 - min_x, cel.chunk.y as i32 - min_y);
+            blend_layer_image(&mut merged, &self.images[cel.image_index], position, cel.opacity(), u8::MAX, BlendMode::Normal, self.space);
+        }
+        blend_layer_image(target, &merged, (min_x - offset.0, min_y - offset.1), u8::MAX, opacity, blend_mode, self.space);
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -59,147 +696,742 @@ pub struct CroppedImage {
     pub displacement_y: u32,
 }
 
+/// How tightly [`crate::wrappers::Frame::combined_frame_image_cropped_with`] crops a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrimMode {
+    /// To the union of the cels, transparent pixels inside the cels are kept. Skips a scan of the image.
+    #[default]
+    CelBounds,
+    /// To the pixels that aren't fully transparent once composited
+    Opaque,
+    /// To the pixels that are neither fully transparent nor this color once composited,
+    /// for sprites drawn on a solid background color instead of transparency
+    ChromaKey(image::Rgba<u8>),
+}
+
+impl CroppedImage {
+    /// Crops away the fully transparent rows and columns around the image, adjusting the displacement.
+    /// `None` if every pixel is transparent.
+    pub fn trimmed(&self) -> Option<CroppedImage> {
+        opaque_bounds(&self.img).map(|bounds| self.crop_to(bounds))
+    }
+
+    /// Same as [`Self::trimmed`], also cropping away the rows and columns that are all `key`.
+    /// The `key` pixels left inside are kept as they are.
+    pub fn trimmed_keyed(&self, key: image::Rgba<u8>) -> Option<CroppedImage> {
+        content_bounds(&self.img, |pixel| *pixel == key).map(|bounds| self.crop_to(bounds))
+    }
+
+    fn crop_to(&self, (x, y, w, h): (u32, u32, u32, u32)) -> CroppedImage {
+        CroppedImage {
+            img: image::imageops::crop_imm(&self.img, x, y, w, h).to_image(),
+            displacement_x: self.displacement_x + x,
+            displacement_y: self.displacement_y + y,
+        }
+    }
+
+    /// The image drawn at its displacement on a transparent canvas of `canvas_size`, undoing the crop.
+    /// Pixels outside the canvas are dropped.
+    pub fn to_canvas(&self, canvas_size: (u32, u32)) -> image::RgbaImage {
+        let mut canvas = image::RgbaImage::new(canvas_size.0, canvas_size.1);
+        image::imageops::replace(&mut canvas, &self.img, self.displacement_x as i64, self.displacement_y as i64);
+        canvas
+    }
+}
+
+/// How a frame compares to the one before it, see [`AsepriteFile::frame_delta`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDelta {
+    /// The first frame, there's nothing to reuse
+    First,
+    /// Made of the same images at the same places as the previous frame, it looks the same
+    Unchanged,
+    /// Some cel differs, the frame may or may not look different
+    Changed,
+}
+
+/// Every frame of a file composited into a single image, without the layer structure
+#[derive(Debug, Clone)]
+pub struct FlattenedSprite {
+    pub canvas_size: (u32, u32),
+    pub frames: Vec<FlattenedFrame>,
+    pub tags: Vec<FlattenedTag>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlattenedFrame {
+    pub img: image::RgbaImage,
+    /// In milliseconds
+    pub duration: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlattenedTag {
+    pub name: String,
+    pub frame_range: RangeInclusive<usize>,
+    pub animation_direction: AnimationDirection,
+    pub animation_repeat: u16,
+}
+
+/// Where a frame ended up in a packed spritesheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedFrame {
+    pub frame_index: usize,
+    /// Rect of the cropped frame in the sheet
+    pub rect: texture_packer::Rect,
+    /// Where the cropped frame goes on the canvas, see [`CroppedImage`]
+    pub displacement: (u32, u32),
+}
+
+/// How neighbouring frames are drawn by [`AsepriteFile::onion_skin_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnionSkinConfig {
+    /// Tint mixed into the frames before the current one
+    pub before_tint: image::Rgb<u8>,
+    /// Tint mixed into the frames after the current one
+    pub after_tint: image::Rgb<u8>,
+    /// Opacity of the frames right next to the current one
+    pub opacity: u8,
+    /// Each frame further away is this much more transparent
+    pub opacity_step: u8,
+}
+
+impl Default for OnionSkinConfig {
+    fn default() -> Self {
+        // Same opacities as aseprite's defaults
+        Self {
+            before_tint: image::Rgb([0, 0, 255]),
+            after_tint: image::Rgb([255, 0, 0]),
+            opacity: 68,
+            opacity_step: 28,
+        }
+    }
+}
+
+/// Builds the image of a frame from a chosen set of layers, e.g. a character without its equipment.
+/// By default it renders the same image as [`AsepriteFile::combined_frame_image`].
+#[derive(Debug, Clone)]
+pub struct FrameBuilder<'f, 'a> {
+    file: &'f AsepriteFile<'a>,
+    frame_index: usize,
+    included: Vec<usize>,
+    excluded: Vec<usize>,
+    only_visible: bool,
+    include_reference: bool,
+    overrides: ahash::HashMap<usize, LayerOverride>,
+}
+
+/// Replaces the blend mode or opacity of a layer while building a frame, see [`FrameBuilder::override_layer`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LayerOverride {
+    pub blend_mode: Option<BlendMode>,
+    pub opacity: Option<u8>,
+}
+
+impl FrameBuilder<'_, '_> {
+    /// Only render the included layers, instead of all of them
+    pub fn include_layer(mut self, layer_index: usize) -> Self {
+        self.included.push(layer_index);
+        self
+    }
+
+    pub fn exclude_layer(mut self, layer_index: usize) -> Self {
+        self.excluded.push(layer_index);
+        self
+    }
+
+    /// Skip the layers hidden in aseprite, themselves or through a group, on by default
+    pub fn only_visible(mut self, only_visible: bool) -> Self {
+        self.only_visible = only_visible;
+        self
+    }
+
+    /// Also render reference layers, off by default as they aren't part of the sprite
+    pub fn include_reference(mut self, include_reference: bool) -> Self {
+        self.include_reference = include_reference;
+        self
+    }
+
+    /// Render layer `layer_index` with a different blend mode or opacity, the file is left untouched
+    pub fn override_layer(mut self, layer_index: usize, layer_override: LayerOverride) -> Self {
+        self.overrides.insert(layer_index, layer_override);
+        self
+    }
+
+    pub fn build(&self) -> Result<image::RgbaImage, LoadImageError> {
+        let mut shown = vec![false; self.file.layers.len()];
+        for (index, _) in self.file.visible_layers() {
+            shown[index] = true;
+        }
+        self.file.composite_layers(self.frame_index, image::Rgba([0, 0, 0, 0]), &self.overrides, BlendSpace::Encoded, |index, layer| {
+            (self.included.is_empty() || self.included.contains(&index))
+                && !self.excluded.contains(&index)
+                && (!self.only_visible || shown[index])
+                && (self.include_reference || !layer.is_reference())
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Hitbox {
-    pub offset: (u32, u32),
+    /// Top left corner on the canvas, negative when the cel hangs off the top or left edge
+    pub offset: (i32, i32),
     pub size: (u32, u32),
     pub layer_id: usize,
 }
 
-impl crate::wrappers::Frame<'_> {
-    pub fn combined_frame_image_cropped(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage]) -> Result<CroppedImage, LoadImageError> {
-        let mut min_xy = (u32::MAX,u32::MAX);
-        let mut max_xy = (0,0);
-        let mut is_cell = false;
-        for cel in self.cells.iter() {
-            let layer = &layers[cel.layer_index()];
-            if layer.parameters.contains_key(&crate::wrappers::LayerParameter::Invisible) {
+impl crate::wrappers::Frame<'_> {
+    pub fn combined_frame_image_cropped(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage]) -> Result<CroppedImage, LoadImageError> {
+        self.combined_frame_image_cropped_with(layers, images, TrimMode::CelBounds)
+    }
+
+    /// Same as [`Self::combined_frame_image_cropped`], [`TrimMode::Opaque`] and [`TrimMode::ChromaKey`] crop to the painted pixels for tighter atlases.
+    /// A frame whose cels are all transparent is an [`LoadImageError::EmptyFrame`] then.
+    pub fn combined_frame_image_cropped_with(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage], trim: TrimMode) -> Result<CroppedImage, LoadImageError> {
+        let cels = self.exported_cels(layers);
+        let (x, y, w, h) = cel_bounds(&cels, images).ok_or(LoadImageError::EmptyFrame)?;
+
+        let mut pixels = image::RgbaImage::new(w, h);
+
+        composite_cels(&mut pixels, (x as i32, y as i32), &cels, layers, images, &Default::default(), BlendSpace::Encoded);
+
+        let cropped = CroppedImage {
+            img: pixels,
+            displacement_x: x,
+            displacement_y: y,
+        };
+        match trim {
+            TrimMode::CelBounds => Ok(cropped),
+            TrimMode::Opaque => cropped.trimmed().ok_or(LoadImageError::EmptyFrame),
+            TrimMode::ChromaKey(key) => cropped.trimmed_keyed(key).ok_or(LoadImageError::EmptyFrame),
+        }
+    }
+
+    /// The `(x, y, width, height)` of [`Self::combined_frame_image_cropped`] on the canvas,
+    /// without compositing anything. `None` for an empty frame.
+    pub fn trim_bounds(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage]) -> Option<(u32, u32, u32, u32)> {
+        cel_bounds(&self.exported_cels(layers), images)
+    }
+
+    fn exported_cels(&self, layers: &[crate::wrappers::Layer<'_>]) -> Vec<&crate::wrappers::Cel<'_>> {
+        self.cells
+            .iter()
+            .filter(|cel| {
+                let layer = &layers[cel.layer_index()];
+                !layer.parameters.contains_key(&crate::wrappers::LayerParameter::Invisible) && !layer.is_reference()
+            })
+            .collect()
+    }
+
+    /// The painted pixels of the hitbox layers, as rectangles that exactly cover them without overlapping
+    pub fn hitboxes(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage]) -> Vec<Hitbox> {
+        let mut out = Vec::new();
+        for cel in self.cells.iter() {
+            let layer = &layers[cel.layer_index()];
+            if !layer.parameters.contains_key(&crate::wrappers::LayerParameter::Hitbox) {
+                continue;
+            }
+            let (x, y) = (cel.chunk.x as i32, cel.chunk.y as i32);
+            out.extend(opaque_rects(&images[cel.image_index]).into_iter().map(|(rx, ry, w, h)| Hitbox {
+                offset: (x + rx as i32, y + ry as i32),
+                size: (w, h),
+                layer_id: cel.layer_index(),
+            }));
+        }
+        out
+    }
+}
+
+impl AsepriteFile<'_> {
+    /// Get image loader for a given frame index
+    /// This will combine all visible layers into a single image, reference layers are left out
+    /// It would be a good idea to detect duplicates, some frames could be identical to others
+    pub fn combined_frame_image(&self, frame_index: usize) -> Result<image::RgbaImage, LoadImageError> {
+        self.combined_frame_image_on(frame_index, image::Rgba([0, 0, 0, 0]))
+    }
+
+    /// [`Self::combined_frame_image`] of every frame in order, each composited when the iterator gets to it
+    ///
+    /// ```
+    /// # use assu_parser::loader::AsepriteFile;
+    /// let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    /// let file = AsepriteFile::from_bytes(&input).unwrap();
+    /// let frames = file.iter_combined_frames().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(frames.len(), 3);
+    /// assert_eq!(frames[1], file.combined_frame_image(1).unwrap());
+    /// ```
+    pub fn iter_combined_frames(&self) -> impl Iterator<Item = Result<image::RgbaImage, LoadImageError>> + '_ {
+        (0..self.frames.len()).map(|index| self.combined_frame_image(index))
+    }
+
+    /// The image of a static sprite (see [`AsepriteFile::is_static`]), same as `combined_frame_image(0)`
+    pub fn single_image(&self) -> Result<image::RgbaImage, LoadImageError> {
+        if !self.is_static() {
+            return Err(LoadImageError::NotStatic(self.frames.len()));
+        }
+        self.combined_frame_image(0)
+    }
+
+    /// Same as [`Self::combined_frame_image`], with the layers composited over a solid `background`
+    /// instead of a transparent canvas
+    pub fn combined_frame_image_on(&self, frame_index: usize, background: image::Rgba<u8>) -> Result<image::RgbaImage, LoadImageError> {
+        let rendered = self.rendered_layers();
+        self.composite_layers(frame_index, background, &Default::default(), BlendSpace::Encoded, |index, _| rendered[index])
+    }
+
+    /// Same as [`Self::combined_frame_image`], blending the colors in linear light instead of on the stored values.
+    /// The colors are decoded with the gamma of the color profile, sRGB if it doesn't have a fixed one.
+    /// Closer to what Aseprite shows with color management, mostly visible on half transparent layers and gradients.
+    pub fn combined_frame_image_linear(&self, frame_index: usize) -> Result<image::RgbaImage, LoadImageError> {
+        let space = BlendSpace::of(self.color_profile_kind());
+        let rendered = self.rendered_layers();
+        self.composite_layers(frame_index, image::Rgba([0, 0, 0, 0]), &Default::default(), space, |index, _| rendered[index])
+    }
+
+    /// Start building an image of a frame from a subset of the layers, see [`FrameBuilder`]
+    pub fn frame_builder(&self, frame_index: usize) -> FrameBuilder<'_, '_> {
+        FrameBuilder {
+            file: self,
+            frame_index,
+            included: Vec::new(),
+            excluded: Vec::new(),
+            only_visible: true,
+            include_reference: false,
+            overrides: Default::default(),
+        }
+    }
+
+    /// Same as [`Self::combined_frame_image`], composited into `sink` over whatever it already holds.
+    /// The frame is drawn at the top left of `sink`, which has to be at least as big as the canvas.
+    pub fn combined_frame_image_into(&self, frame_index: usize, sink: &mut impl PixelSink) -> Result<(), LoadImageError> {
+        let rendered = self.rendered_layers();
+        self.composite_layers_into(frame_index, sink, &Default::default(), BlendSpace::Encoded, |index, _| rendered[index])
+    }
+
+    fn composite_layers(
+        &self,
+        frame_index: usize,
+        background: image::Rgba<u8>,
+        overrides: &ahash::HashMap<usize, LayerOverride>,
+        space: BlendSpace,
+        include_layer: impl Fn(usize, &crate::wrappers::Layer<'_>) -> bool,
+    ) -> Result<image::RgbaImage, LoadImageError> {
+        let mut pixels = image::RgbaImage::from_pixel(self.canvas_width() as u32, self.canvas_height() as u32, background);
+        self.composite_layers_into(frame_index, &mut pixels, overrides, space, include_layer)?;
+        Ok(pixels)
+    }
+
+    fn composite_layers_into(
+        &self,
+        frame_index: usize,
+        sink: &mut impl PixelSink,
+        overrides: &ahash::HashMap<usize, LayerOverride>,
+        space: BlendSpace,
+        include_layer: impl Fn(usize, &crate::wrappers::Layer<'_>) -> bool,
+    ) -> Result<(), LoadImageError> {
+        let frame = self.frames.get(frame_index).ok_or(LoadSpriteError::FrameIndexOutOfRange(frame_index))?;
+        let (width, height) = sink.dimensions();
+        if width < self.canvas_width() as u32 || height < self.canvas_height() as u32 {
+            return Err(LoadImageError::TargetBufferTooSmall);
+        }
+
+        let cels: Vec<_> = frame.cells
+            .iter()
+            .filter(|cel| include_layer(cel.layer_index(), &self.layers[cel.layer_index()]))
+            .collect();
+        composite_cels(sink, (0, 0), &cels, &self.layers, &self.images_decompressed, overrides, space);
+        Ok(())
+    }
+
+    /// Fails if a `width` by `height` image made from the file takes more than [`Self::max_image_bytes`]
+    fn output_size(&self, width: u64, height: u64) -> Result<(u32, u32), LoadImageError> {
+        let too_large = || LoadImageError::OutputTooLarge { width, height, limit: self.max_image_bytes };
+        if width.saturating_mul(height).saturating_mul(4) > self.max_image_bytes as u64 {
+            return Err(too_large());
+        }
+        Ok((u32::try_from(width).map_err(|_| too_large())?, u32::try_from(height).map_err(|_| too_large())?))
+    }
+
+    /// Whether each layer is drawn in [`Self::combined_frame_image`]: shown (see [`Self::visible_layers`]) and not a reference layer
+    fn rendered_layers(&self) -> Vec<bool> {
+        let mut rendered = vec![false; self.layers.len()];
+        for (index, layer) in self.visible_layers() {
+            rendered[index] = !layer.is_reference();
+        }
+        rendered
+    }
+
+    /// Same as [`Self::combined_frame_image`], with the color channels multiplied by alpha.
+    /// The regular output has straight alpha, where a transparent pixel can still hold any color.
+    /// Premultiplied textures don't bleed those colors into their edges when sampled with bilinear filtering.
+    pub fn combined_frame_image_premultiplied(&self, frame_index: usize) -> Result<image::RgbaImage, LoadImageError> {
+        let mut img = self.combined_frame_image(frame_index)?;
+        premultiply(&mut img);
+        Ok(img)
+    }
+
+    /// The `(x, y, width, height)` of the cropped image of a frame, see [`crate::wrappers::Frame::trim_bounds`].
+    /// `None` for an empty frame or an out of range index.
+    pub fn frame_trim_bounds(&self, frame_index: usize) -> Option<(u32, u32, u32, u32)> {
+        self.frames.get(frame_index)?.trim_bounds(&self.layers, &self.images_decompressed)
+    }
+
+    /// The part of [`Self::combined_frame_image`] in `region`, e.g. a viewport over a large canvas.
+    /// Only the cels overlapping `region` are composited. `region` is clipped to the canvas.
+    pub fn combined_frame_image_region(&self, frame_index: usize, region: texture_packer::Rect) -> Result<image::RgbaImage, LoadImageError> {
+        let frame = self.frames.get(frame_index).ok_or(LoadSpriteError::FrameIndexOutOfRange(frame_index))?;
+        let canvas_size = (self.canvas_width() as u32, self.canvas_height() as u32);
+        let (x, y) = (region.x.min(canvas_size.0), region.y.min(canvas_size.1));
+        let width = region.w.min(canvas_size.0 - x);
+        let height = region.h.min(canvas_size.1 - y);
+
+        let rendered = self.rendered_layers();
+        // cels can hang off the top left of the canvas
+        let (left, top, right, bottom) = (x as i64, y as i64, (x + width) as i64, (y + height) as i64);
+        let cels: Vec<_> = frame.cells
+            .iter()
+            .filter(|cel| {
+                let img = &self.images_decompressed[cel.image_index];
+                let (cel_x, cel_y) = (cel.chunk.x as i64, cel.chunk.y as i64);
+                rendered[cel.layer_index()]
+                    && cel_x < right && cel_x + img.width() as i64 > left
+                    && cel_y < bottom && cel_y + img.height() as i64 > top
+            })
+            .collect();
+
+        let mut pixels = image::RgbaImage::new(width, height);
+        composite_cels(&mut pixels, (x as i32, y as i32), &cels, &self.layers, &self.images_decompressed, &Default::default(), BlendSpace::Encoded);
+        Ok(pixels)
+    }
+
+    /// Same as [`Self::combined_frame_image`], upscaled by an integer factor with nearest-neighbor sampling
+    /// so pixel art stays crisp. A `scale` of 0 is [`LoadImageError::InvalidImageData`].
+    pub fn combined_frame_image_scaled(&self, frame_index: usize, scale: u32) -> Result<image::RgbaImage, LoadImageError> {
+        if scale == 0 {
+            return Err(LoadImageError::InvalidImageData);
+        }
+        let (width, height) = self.output_size(self.canvas_width() as u64 * scale as u64, self.canvas_height() as u64 * scale as u64)?;
+        let img = self.combined_frame_image(frame_index)?;
+        Ok(image::RgbaImage::from_fn(width, height, |x, y| {
+            *img.get_pixel(x / scale, y / scale)
+        }))
+    }
+
+    /// Composite a frame over faded, tinted copies of the `before` previous and `after` next frames
+    pub fn onion_skin(&self, frame_index: usize, before: usize, after: usize) -> Result<image::RgbaImage, LoadImageError> {
+        self.onion_skin_with(frame_index, before, after, OnionSkinConfig::default())
+    }
+
+    pub fn onion_skin_with(
+        &self,
+        frame_index: usize,
+        before: usize,
+        after: usize,
+        config: OnionSkinConfig,
+    ) -> Result<image::RgbaImage, LoadImageError> {
+        if frame_index >= self.frames.len() {
+            return Err(LoadSpriteError::FrameIndexOutOfRange(frame_index).into());
+        }
+        let mut pixels = image::RgbaImage::new(self.canvas_width() as u32, self.canvas_height() as u32);
+
+        // Furthest frames first so the closer ones end up on top
+        for distance in (1..=before.max(after)).rev() {
+            let fade = config.opacity_step as usize * (distance - 1);
+            let opacity = config.opacity.saturating_sub(fade.min(u8::MAX as usize) as u8);
+
+            let mut neighbours = Vec::with_capacity(2);
+            if distance <= before {
+                if let Some(index) = frame_index.checked_sub(distance) {
+                    neighbours.push((index, config.before_tint));
+                }
+            }
+            if distance <= after {
+                neighbours.push((frame_index + distance, config.after_tint));
+            }
+            for (index, tint) in neighbours {
+                if index >= self.frames.len() {
+                    continue;
+                }
+                let neighbour = self.combined_frame_image(index)?;
+                for (target_pixel, pixel) in pixels.pixels_mut().zip(neighbour.pixels()) {
+                    let mut tinted = *pixel;
+                    for (c, t) in tinted.channels_mut()[..3].iter_mut().zip(tint.channels()) {
+                        *c = ((*c as u16 + *t as u16) / 2) as u8;
+                    }
+                    *target_pixel = blend_pixel(*target_pixel, tinted, opacity, BlendMode::Normal, BlendSpace::Encoded);
+                }
+            }
+        }
+
+        let current = self.combined_frame_image(frame_index)?;
+        for (target_pixel, pixel) in pixels.pixels_mut().zip(current.pixels()) {
+            *target_pixel = blend_pixel(*target_pixel, *pixel, u8::MAX, BlendMode::Normal, BlendSpace::Encoded);
+        }
+
+        Ok(pixels)
+    }
+
+    /// Get the cel of a single layer in every frame, `None` where the layer has no cel.
+    /// Like [`crate::wrappers::Frame::combined_frame_image_cropped`], what's left of or above the canvas is cut off.
+    pub fn layer_frames(&self, layer_index: usize) -> Vec<Option<CroppedImage>> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                let cel = frame.cell_at_layer_index(layer_index)?;
+                let (x, y, w, h) = cel_bounds(&[&cel], &self.images_decompressed)?;
+                let (left, top) = ((x as i32 - cel.chunk.x as i32) as u32, (y as i32 - cel.chunk.y as i32) as u32);
+                Some(CroppedImage {
+                    img: image::imageops::crop_imm(&self.images_decompressed[cel.image_index], left, top, w, h).to_image(),
+                    displacement_x: x,
+                    displacement_y: y,
+                })
+            })
+            .collect()
+    }
+
+    /// The decoded cel of layer `layer_index` in frame `frame_index` with its `(x, y)` on the canvas,
+    /// negative when the cel starts left of or above it. `None` if there is no such cel.
+    pub fn cel_image_with_offset(&self, frame_index: usize, layer_index: usize) -> Option<(image::RgbaImage, (i32, i32))> {
+        let cel = self.frames.get(frame_index)?.cell_at_layer_index(layer_index)?;
+        let img = self.images_decompressed.get(cel.image_index)?.clone();
+        Some((img, (cel.chunk.x as i32, cel.chunk.y as i32)))
+    }
+
+    /// The frames that look the same once composited, in groups of at least two sorted by frame index.
+    /// Frames made of the same cels are grouped without compositing them, the others are compared pixel by pixel.
+    /// Unlike [`Self::dedup_images`] this doesn't change the file.
+    pub fn duplicate_frame_groups(&self) -> Vec<Vec<usize>> {
+        use std::collections::hash_map::Entry;
+
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut by_cels: ahash::HashMap<Vec<_>, usize> = ahash::HashMap::default();
+        let rendered = self.rendered_layers();
+        for (index, frame) in self.frames.iter().enumerate() {
+            match by_cels.entry(self.cel_signature(frame, &rendered)) {
+                Entry::Occupied(entry) => groups[*entry.get()].push(index),
+                Entry::Vacant(entry) => {
+                    entry.insert(groups.len());
+                    groups.push(vec![index]);
+                }
+            }
+        }
+
+        // Different cels can still give the same pixels
+        let mut merged: Vec<Vec<usize>> = Vec::new();
+        let mut by_pixels: ahash::HashMap<image::RgbaImage, usize> = ahash::HashMap::default();
+        for group in groups {
+            let Ok(img) = self.combined_frame_image(group[0]) else {
+                merged.push(group);
                 continue;
+            };
+            match by_pixels.entry(img) {
+                Entry::Occupied(entry) => merged[*entry.get()].extend(group),
+                Entry::Vacant(entry) => {
+                    entry.insert(merged.len());
+                    merged.push(group);
+                }
             }
-            is_cell = true;
-            let im = &images[cel.image_index];
-            min_xy.0 = u32::min(min_xy.0, cel.x());
-            min_xy.1 = u32::min(min_xy.1, cel.y());
-            max_xy.0 = u32::max(max_xy.0, cel.x() + im.width());
-            max_xy.1 = u32::max(max_xy.1, cel.y() + im.height());
         }
-        if !is_cell {
-            return Err(LoadImageError::EmptyFrame);
+
+        merged.retain(|group| group.len() > 1);
+        for group in &mut merged {
+            group.sort_unstable();
         }
-        let offset_xy = min_xy;
-        let dims_xy = (max_xy.0 - min_xy.0, max_xy.1 - min_xy.1);
+        merged
+    }
 
-        let mut pixels = image::RgbaImage::new(dims_xy.0, dims_xy.1);
+    /// Whether frame `frame_index` shows the same images at the same places as the frame before it,
+    /// so an encoder can skip it. Only looks at the cels, not at the pixels like [`Self::duplicate_frame_groups`].
+    /// `None` if there is no such frame.
+    pub fn frame_delta(&self, frame_index: usize) -> Option<FrameDelta> {
+        let frame = self.frames.get(frame_index)?;
+        let Some(previous) = frame_index.checked_sub(1).map(|index| &self.frames[index]) else {
+            return Some(FrameDelta::First);
+        };
+        let rendered = self.rendered_layers();
+        if self.cel_signature(previous, &rendered) == self.cel_signature(frame, &rendered) {
+            Some(FrameDelta::Unchanged)
+        } else {
+            Some(FrameDelta::Changed)
+        }
+    }
 
-        for cel in self.cells.iter() {
-            let layer = &layers[cel.layer_index()];
-            if layer.parameters.contains_key(&crate::wrappers::LayerParameter::Invisible) {
-                continue;
-            }
+    /// What decides how the cels of `frame` on the `rendered` layers look once composited, see [`Self::rendered_layers`]
+    fn cel_signature(&self, frame: &crate::wrappers::Frame<'_>, rendered: &[bool]) -> Vec<(usize, usize, i16, i16, u8, i16)> {
+        frame.cells
+            .iter()
+            .filter(|cel| rendered[cel.layer_index()])
+            .map(|cel| (cel.layer_index(), cel.image_index, cel.chunk.x, cel.chunk.y, cel.opacity(), cel.z_index()))
+            .collect()
+    }
 
-            let im = &images[cel.image_index];
+    /// Composite every frame, keeping only the tags and durations
+    pub fn flatten(&self) -> Result<FlattenedSprite, LoadImageError> {
+        let frames = self.frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| Ok(FlattenedFrame {
+                img: self.combined_frame_image(index)?,
+                duration: frame.duration,
+            }))
+            .collect::<Result<_, LoadImageError>>()?;
 
-            for (x, y, cel_pixel) in im.enumerate_pixels() {
-                let target_pixel = pixels.get_pixel_mut(x + cel.x() - offset_xy.0, y + cel.y() - offset_xy.1);
+        let tags = self.tags
+            .iter()
+            .map(|tag| FlattenedTag {
+                name: tag.name().to_string(),
+                frame_range: tag.frame_range(),
+                animation_direction: tag.chunk.animation_direction,
+                animation_repeat: tag.chunk.animation_repeat,
+            })
+            .collect();
 
-                let total_alpha =
-                    ((cel_pixel.a() as u16 * layer.chunk.opacity as u16) / u8::MAX as u16) as u8;
+        Ok(FlattenedSprite {
+            canvas_size: (self.canvas_width() as u32, self.canvas_height() as u32),
+            frames,
+            tags,
+        })
+    }
 
-                for (target_c, cell_c) in target_pixel.channels_mut().iter_mut().zip(cel_pixel.channels()) {
-                    *target_c =
-                        blend_channel(*target_c, *cell_c, total_alpha, layer.chunk.blend_mode);
-                }
-            }
+    /// Lay out every frame at full canvas size in a grid of `columns` columns, left-to-right then top-to-bottom.
+    /// `columns == 0` puts all frames in a single row.
+    /// Returns the sheet and the rect of each frame in it.
+    pub fn strip_spritesheet(&self, columns: usize) -> Result<(image::RgbaImage, Vec<texture_packer::Rect>), LoadImageError> {
+        if self.frames.is_empty() {
+            return Err(LoadImageError::NoFrames);
         }
+        let columns = if columns == 0 { self.frames.len() } else { columns };
+        let rows = self.frames.len().div_ceil(columns);
+        let (width, height) = (self.canvas_width() as u32, self.canvas_height() as u32);
 
-        Ok(CroppedImage {
-            img: pixels,
-            displacement_x: offset_xy.0,
-            displacement_y: offset_xy.1,
-        })
+        let (sheet_width, sheet_height) = self.output_size(width as u64 * columns.min(self.frames.len()) as u64, height as u64 * rows as u64)?;
+        let mut sheet = image::RgbaImage::new(sheet_width, sheet_height);
+        let mut rects = Vec::with_capacity(self.frames.len());
+        for index in 0..self.frames.len() {
+            let rect = texture_packer::Rect::new(
+                (index % columns) as u32 * width,
+                (index / columns) as u32 * height,
+                width,
+                height,
+            );
+            image::imageops::replace(&mut sheet, &self.combined_frame_image(index)?, rect.x as i64, rect.y as i64);
+            rects.push(rect);
+        }
+        Ok((sheet, rects))
     }
 
-    pub fn hitboxes(&self, layers: &[crate::wrappers::Layer<'_>], images: &[image::RgbaImage]) -> Vec<Hitbox> {
-        let mut out = Vec::new();
-        for cel in self.cells.iter() {
-            let layer = &layers[cel.layer_index()];
-            if !layer.parameters.contains_key(&crate::wrappers::LayerParameter::Hitbox) {
-                continue;
+    /// Same layout as [`Self::strip_spritesheet`] with a 1px [`CONTACT_SHEET_SEPARATOR`] line between frames,
+    /// to look over an animation while debugging
+    pub fn contact_sheet(&self, columns: usize) -> Result<image::RgbaImage, LoadImageError> {
+        if self.frames.is_empty() {
+            return Err(LoadImageError::NoFrames);
+        }
+        let columns = if columns == 0 { self.frames.len() } else { columns };
+        let rows = self.frames.len().div_ceil(columns) as u32;
+        let columns_used = columns.min(self.frames.len()) as u32;
+        let (width, height) = (self.canvas_width() as u32, self.canvas_height() as u32);
+
+        let (sheet_width, sheet_height) = self.output_size((width as u64 + 1) * columns_used as u64 - 1, (height as u64 + 1) * rows as u64 - 1)?;
+        let mut sheet = image::RgbaImage::new(sheet_width, sheet_height);
+        for (x, y, pixel) in sheet.enumerate_pixels_mut() {
+            if x % (width + 1) == width || y % (height + 1) == height {
+                *pixel = CONTACT_SHEET_SEPARATOR;
             }
-            let img = &images[cel.image_index];
-            // TODO: this currently just takes the bounding box of whatever was painted.
-            //       would be possible to decompose the painting into pixel-perfect smaller rectangles
-            //       See https://en.wikipedia.org/wiki/Polygon_covering#Covering_a_rectilinear_polygon_with_rectangles
-            out.push(Hitbox {
-                offset: (cel.x(), cel.x()),
-                size: img.dimensions(),
-                layer_id: cel.layer_index(),
-            })
         }
-        out
+        for (index, frame) in self.iter_combined_frames().enumerate() {
+            let x = (index % columns) as u32 * (width + 1);
+            let y = (index / columns) as u32 * (height + 1);
+            image::imageops::replace(&mut sheet, &frame?, x as i64, y as i64);
+        }
+        Ok(sheet)
     }
-}
 
-impl AsepriteFile<'_> {
-    /// Get image loader for a given frame index
-    /// This will combine all layers into a single image
-    /// It would be a good idea to detect duplicates, some frames could be identical to others
-    pub fn combined_frame_image(&self, frame_index: usize) -> Result<image::RgbaImage, LoadImageError> {
-        let mut pixels = image::RgbaImage::new(self.canvas_width() as u32, self.canvas_height() as u32);
+    /// Pack the deduplicated cropped frames into a 512x512 spritesheet
+    pub fn packed_spritesheet(&self) -> anyhow::Result<image::RgbaImage> {
+        self.packed_spritesheet_with(default_packer_config())
+    }
 
-        let frame = &self.frames[frame_index];
+    /// Same as [`Self::packed_spritesheet`] with a custom packer config, e.g. for sprites that don't fit in 512x512.
+    /// Keep `trim` and `allow_rotation` off, the frames are already cropped.
+    pub fn packed_spritesheet_with(&self, config: texture_packer::TexturePackerConfig) -> anyhow::Result<image::RgbaImage> {
+        self.packed_spritesheet_frames(config).map(|(sheet, _)| sheet)
+    }
 
-        for cel in frame.cells.iter() {
-            let layer = &self.layers[cel.layer_index()];
-            if !layer.visible() {
-                continue;
-            }
+    /// Same as [`Self::packed_spritesheet_with`], also returning where each frame landed in the sheet.
+    /// Empty frames aren't packed and have no entry, identical frames share the same rect.
+    pub fn packed_spritesheet_frames(&self, config: texture_packer::TexturePackerConfig) -> anyhow::Result<(image::RgbaImage, Vec<PackedFrame>)> {
+        self.pack_frames(config, false)
+    }
 
-            let im = &self.images_decompressed[cel.image_index];
+    /// Same as [`Self::packed_spritesheet_frames`], but frames not covered by any tag are left out of the sheet.
+    pub fn packed_spritesheet_frames_tagged_only(&self, config: texture_packer::TexturePackerConfig) -> anyhow::Result<(image::RgbaImage, Vec<PackedFrame>)> {
+        self.pack_frames(config, true)
+    }
 
-            for (x, y, cel_pixel) in im.enumerate_pixels() {
-                let target_pixel = pixels.get_pixel_mut(x + cel.x(), y + cel.y());
+    /// Pack the frames of `tag` into a 512x512 sheet of their own, e.g. one `walk.png` per animation.
+    /// Returns the sheet and the rect of each frame of the tag, in tag order.
+    pub fn export_tag_spritesheet(&self, tag: &str) -> anyhow::Result<(image::RgbaImage, Vec<texture_packer::Rect>)> {
+        let config = texture_packer::TexturePackerConfig {
+            texture_outlines: false,
+            ..default_packer_config()
+        };
+        self.export_tag_spritesheet_with(tag, config)
+    }
 
-                let total_alpha =
-                    ((cel_pixel.a() as u16 * layer.chunk.opacity as u16) / u8::MAX as u16) as u8;
+    /// Same as [`Self::export_tag_spritesheet`] with a custom packer config.
+    /// The frames are packed at full canvas size so the rects line up without any displacement,
+    /// identical frames share the same rect.
+    pub fn export_tag_spritesheet_with(&self, tag: &str, config: texture_packer::TexturePackerConfig) -> anyhow::Result<(image::RgbaImage, Vec<texture_packer::Rect>)> {
+        let mut packer = texture_packer::TexturePacker::new_skyline(config);
 
-                for (target_c, cell_c) in target_pixel.channels_mut().iter_mut().zip(cel_pixel.channels()) {
-                    *target_c =
-                        blend_channel(*target_c, *cell_c, total_alpha, layer.chunk.blend_mode);
+        let mut frames: Vec<image::RgbaImage> = Vec::new();
+        let mut frame_map = Vec::new();
+        for index in self.frame_range(Some(tag))? {
+            let img = self.combined_frame_image(index)?;
+            match frames.iter().position(|o| o == &img) {
+                Some(p) => frame_map.push(p),
+                None => {
+                    frame_map.push(frames.len());
+                    frames.push(img);
                 }
             }
         }
+        for (i, img) in frames.into_iter().enumerate() {
+            packer.pack_own(i.to_string(), img).map_err(|s| anyhow::anyhow!("{s:?}"))?;
+        }
 
-        Ok(pixels)
-    }
+        let rects = frame_map
+            .into_iter()
+            .map(|p| {
+                packer
+                    .get_frame(&p.to_string())
+                    .map(|frame| frame.frame)
+                    .ok_or_else(|| anyhow::anyhow!("frame {p} of tag {tag:?} was not packed"))
+            })
+            .collect::<anyhow::Result<_>>()?;
 
-    pub fn packed_spritesheet(&self) -> anyhow::Result<image::RgbaImage> {
-        let config = texture_packer::TexturePackerConfig {
-            max_width: 512,
-            max_height: 512,
-            allow_rotation: false,
-            texture_outlines: true,
-            border_padding: 0,
-            force_max_dimensions: false,
-            texture_padding: 0,
-            texture_extrusion: 0,
-            trim: false, // should already be trimmed but just in case, don't want to mess up offsets
-        };
+        let out = texture_packer::exporter::ImageExporter::export(&packer).map_err(|s| anyhow::anyhow!(s))?;
+        Ok((out.to_rgba8(), rects))
+    }
 
+    fn pack_frames(&self, config: texture_packer::TexturePackerConfig, only_tagged: bool) -> anyhow::Result<(image::RgbaImage, Vec<PackedFrame>)> {
         let mut packer = texture_packer::TexturePacker::new_skyline(config);
 
         let mut frames = Vec::new();
-        let mut frame_map = ahash::HashMap::default();
+        let mut frame_map = Vec::new();
 
         for (i, f) in self.frames.iter().enumerate() {
+            if only_tagged && !self.is_tagged(i) {
+                continue;
+            }
             let f = f.combined_frame_image_cropped(&self.layers, &self.images_decompressed);
             match f {
                 Ok(f) => {
                     let p = frames.iter().position(|o| o == &f);
                     if let Some(p) = p {
-                        frame_map.insert(i, p);
+                        frame_map.push((i, p));
                     } else {
-                        frame_map.insert(i, frames.len());
+                        frame_map.push((i, frames.len()));
                         frames.push(f);
                     }
                 },
@@ -207,6 +1439,10 @@ impl AsepriteFile<'_> {
                 Err(e) => Err(e)?,
             }
         }
+        // An empty sheet isn't worth exporting, and zero sized images upset the exporter
+        if frames.is_empty() {
+            Err(LoadImageError::NoFrames)?;
+        }
 
         /* let mut frames = frames
             .into_iter()
@@ -217,17 +1453,905 @@ impl AsepriteFile<'_> {
         frames.sort_unstable_by_key(|(_, a)| a.img.width() * a.img.height());
         frames.reverse(); */
 
+        let displacements = frames.iter().map(|f| (f.displacement_x, f.displacement_y)).collect::<Vec<_>>();
         for (i, f) in frames.into_iter().enumerate() {
             packer.pack_own(i.to_string(), f.img).map_err(|s| anyhow::anyhow!("{s:?}"))?;
         }
 
+        let packed_frames = frame_map
+            .into_iter()
+            .map(|(frame_index, p)| {
+                let rect = packer
+                    .get_frame(&p.to_string())
+                    .ok_or_else(|| anyhow::anyhow!("frame {frame_index} was not packed"))?
+                    .frame;
+                Ok(PackedFrame { frame_index, rect, displacement: displacements[p] })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
         let out = texture_packer::exporter::ImageExporter::export(&packer).map_err(|s| anyhow::anyhow!(s))?;
         
-        Ok(out.to_rgba8())
+        Ok((out.to_rgba8(), packed_frames))
+    }
+
+}
+
+
+#[test]
+fn test_trim_mode_opaque() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    // 4x4 cel at (2, 3) with only the 2x1 block at (1, 2) painted
+    let mut pixels = [0; 4 * 4 * 4];
+    for x in 1..3 {
+        let i = (2 * 4 + x) * 4;
+        pixels[i..i + 4].copy_from_slice(&[10, 20, 30, 255]);
+    }
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[4] is the cel of the only layer
+        chunks[4] = raw_cel(0, (2, 3), (4, 4), &pixels);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let frame = &file.frames[0];
+
+    let cropped = frame.combined_frame_image_cropped(&file.layers, &file.images_decompressed).unwrap();
+    assert_eq!(cropped.img.dimensions(), (4, 4));
+    assert_eq!((cropped.displacement_x, cropped.displacement_y), (2, 3));
+
+    let trimmed = frame.combined_frame_image_cropped_with(&file.layers, &file.images_decompressed, TrimMode::Opaque).unwrap();
+    assert_eq!(trimmed.img.dimensions(), (2, 1));
+    assert_eq!((trimmed.displacement_x, trimmed.displacement_y), (3, 5));
+    assert!(trimmed.img.pixels().all(|p| p.0 == [10, 20, 30, 255]));
+    assert_eq!(cropped.trimmed(), Some(trimmed));
+
+    // a cel with nothing painted leaves nothing to trim to
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (2, 3), (4, 4), &[0; 4 * 4 * 4]);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(matches!(
+        file.frames[0].combined_frame_image_cropped_with(&file.layers, &file.images_decompressed, TrimMode::Opaque),
+        Err(LoadImageError::EmptyFrame)
+    ));
+}
+
+#[test]
+fn test_trim_chroma_key() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let magenta = image::Rgba([255, 0, 255, 255]);
+    // A solid magenta background with two painted pixels
+    let mut pixels = magenta.0.repeat(4 * 4);
+    for (x, y) in [(1, 1), (2, 2)] {
+        pixels[(y * 4 + x) * 4..][..4].copy_from_slice(&[10, 20, 30, 255]);
+    }
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (2, 3), (4, 4), &pixels);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let frame = &file.frames[0];
+    let trim = |mode| frame.combined_frame_image_cropped_with(&file.layers, &file.images_decompressed, mode);
+
+    // Nothing is transparent
+    assert_eq!(trim(TrimMode::Opaque).unwrap().img.dimensions(), (4, 4));
+
+    let keyed = trim(TrimMode::ChromaKey(magenta)).unwrap();
+    assert_eq!(keyed.img.dimensions(), (2, 2));
+    assert_eq!((keyed.displacement_x, keyed.displacement_y), (3, 4));
+    assert_eq!(*keyed.img.get_pixel(1, 0), magenta);
+
+    // Another key leaves the magenta
+    let other = trim(TrimMode::ChromaKey(image::Rgba([0, 255, 0, 255]))).unwrap();
+    assert_eq!(other.img.dimensions(), (4, 4));
+
+    // A frame of only the key color is empty
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (2, 3), (4, 4), &magenta.0.repeat(4 * 4));
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(matches!(
+        file.frames[0].combined_frame_image_cropped_with(&file.layers, &file.images_decompressed, TrimMode::ChromaKey(magenta)),
+        Err(LoadImageError::EmptyFrame)
+    ));
+}
+
+#[test]
+fn test_combined_frame_image_region() {
+    for name in ["combine.aseprite", "layers.aseprite"] {
+        let input = std::fs::read(format!("tests/aseprite_files/{name}")).unwrap();
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        let (width, height) = (file.canvas_width() as u32, file.canvas_height() as u32);
+        for frame_index in 0..file.frames.len() {
+            let full = file.combined_frame_image(frame_index).unwrap();
+            for (x, y, w, h) in [(0, 0, width, height), (1, 2, 3, 4), (width / 2, height / 3, width, height), (0, height - 1, width, 1)] {
+                let region = file.combined_frame_image_region(frame_index, texture_packer::Rect::new(x, y, w, h)).unwrap();
+                let (w, h) = (w.min(width - x), h.min(height - y));
+                assert_eq!(region, image::imageops::crop_imm(&full, x, y, w, h).to_image(), "{name} frame {frame_index} at {x},{y}");
+            }
+        }
+        let outside = file.combined_frame_image_region(0, texture_packer::Rect::new(width, 0, 5, 5)).unwrap();
+        assert_eq!(outside.dimensions(), (0, 5));
+    }
+}
+
+#[test]
+fn test_combined_frame_image_region_hidden_group() {
+    use crate::binary::{chunks::layer::LayerFlags, test_utils::{edit_chunks, fixture, raw_cel, set_child_level, set_layer_type}};
+
+    // chunks[3] is the only layer: a white background, then a group with a red layer in it
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 2), &[255, 255, 255, 255].repeat(4));
+        let mut group = chunks[3].clone();
+        set_layer_type(&mut group, LayerType::Group);
+        let mut child = chunks[3].clone();
+        set_child_level(&mut child, 1);
+        chunks.push(group);
+        chunks.push(child);
+        chunks.push(raw_cel(2, (0, 0), (2, 2), &[255, 0, 0, 255].repeat(4)));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    let region = texture_packer::Rect::new(0, 0, 2, 2);
+    assert_eq!(file.combined_frame_image_region(0, region).unwrap().get_pixel(1, 1).0, [255, 0, 0, 255]);
+
+    // the child is still visible itself, hiding the group hides it
+    file.layers[1].chunk.flags.remove(LayerFlags::VISIBLE);
+    assert!(file.layers[2].visible());
+    let img = file.combined_frame_image_region(0, region).unwrap();
+    assert_eq!(img.get_pixel(1, 1).0, [255, 255, 255, 255]);
+    assert_eq!(img, image::imageops::crop_imm(&file.combined_frame_image(0).unwrap(), 0, 0, 2, 2).to_image());
+    // same for the frame builder, unless hidden layers are asked for
+    assert_eq!(file.frame_builder(0).build().unwrap(), file.combined_frame_image(0).unwrap());
+    assert_eq!(file.frame_builder(0).only_visible(false).build().unwrap().get_pixel(1, 1).0, [255, 0, 0, 255]);
+
+    // an isolated group is drawn within the region too
+    file.layers[1].chunk.flags.insert(LayerFlags::VISIBLE);
+    file.layers[1].chunk.opacity = 128;
+    let region = texture_packer::Rect::new(1, 1, 1, 1);
+    assert_eq!(file.combined_frame_image_region(0, region).unwrap().get_pixel(0, 0).0, [255, 127, 127, 255]);
+}
+
+#[test]
+fn test_indexed_to_rgba() {
+    let palette = Palette {
+        entries: vec![
+            (image::Rgba([0, 0, 0, 0]), None),
+            (image::Rgba([255, 0, 0, 255]), Some("red".to_string())),
+        ],
+    };
+    let mut target = [7; 12];
+
+    indexed_to_rgba(&[1, 0, 1], &palette, &mut target, OutOfRangeIndex::Error).unwrap();
+    assert_eq!(target, [255, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 255]);
+
+    // index 2 is past the end of the palette
+    assert!(matches!(
+        indexed_to_rgba(&[1, 2, 0], &palette, &mut target, OutOfRangeIndex::Error),
+        Err(LoadImageError::InvalidImageData)
+    ));
+    indexed_to_rgba(&[1, 2, 255], &palette, &mut target, OutOfRangeIndex::Transparent).unwrap();
+    assert_eq!(target, [255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert!(indexed_to_rgba(&[1], &palette, &mut target, OutOfRangeIndex::Error).is_err());
+}
+
+#[test]
+fn test_combined_frame_image_linear() {
+    use crate::binary::{chunks::color_profile::ColorProfile, scalars::Fixed};
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel, set_blend_mode};
+
+    // grays in soft light over middle gray
+    let backdrop = [128, 128, 128, 255].repeat(3);
+    let source = [[64, 64, 64, 255], [160, 160, 160, 255], [230, 230, 230, 255]].concat();
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        // chunks[3] is the only layer, chunks[4] its cel
+        let mut layer = chunks[3].clone();
+        set_blend_mode(&mut layer, BlendMode::SoftLight);
+        chunks[4] = raw_cel(0, (0, 0), (3, 1), &backdrop);
+        chunks.push(layer);
+        chunks.push(raw_cel(1, (0, 0), (3, 1), &source));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::Srgb);
+
+    let row = |img: &image::RgbaImage| (0..3).map(|x| img.get_pixel(x, 0).0).collect::<Vec<_>>();
+    let encoded = file.combined_frame_image(0).unwrap();
+    assert_eq!(row(&encoded), [[96, 96, 96, 255], [141, 141, 141, 255], [170, 170, 170, 255]]);
+    // the same math on linear light, where middle gray is much darker than 0.5
+    let linear = file.combined_frame_image_linear(0).unwrap();
+    assert_eq!(row(&linear), [[72, 72, 72, 255], [113, 113, 113, 255], [162, 162, 162, 255]]);
+    // everything else is left alone
+    assert_eq!(linear.get_pixel(1, 1), encoded.get_pixel(1, 1));
+
+    // a fixed gamma of 1 means the stored values already are linear
+    file.color_profile.fixed_gamma = Some(Fixed(1, 0));
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::FixedGamma(1.0));
+    assert_eq!(file.combined_frame_image_linear(0).unwrap(), encoded);
+
+    // without a profile the values are blended as they're stored
+    file.color_profile.profile = ColorProfile::NoColorProfile;
+    assert_eq!(file.color_profile_kind(), ColorProfileKind::NoColorProfile);
+    assert_eq!(file.combined_frame_image_linear(0).unwrap(), encoded);
+}
+
+#[test]
+fn test_opaque_normal_fast_path() {
+    // Every opaque color blended with the full math gives the color back
+    for value in 0..=u8::MAX {
+        for backdrop in [[0, 0, 0, 0], [77, 77, 77, 128], [255, 255, 255, 255]] {
+            let src = image::Rgba([value, 255 - value, value / 2, u8::MAX]);
+            assert_eq!(blend_pixel(image::Rgba(backdrop), src, u8::MAX, BlendMode::Normal, BlendSpace::Encoded), src);
+        }
+    }
+}
+
+#[test]
+fn test_layer_frames_off_canvas() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let (red, green) = ([255, 0, 0, 255], [0, 255, 0, 255]);
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (-2, 3), (4, 1), &[red, red, green, green].concat());
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let cropped = file.layer_frames(0)[0].clone().unwrap();
+    assert_eq!((cropped.displacement_x, cropped.displacement_y), (0, 3));
+    assert_eq!(cropped.img, image::RgbaImage::from_raw(2, 1, [green, green].concat()).unwrap());
+    assert_eq!(cropped.to_canvas((32, 32)), file.combined_frame_image(0).unwrap());
+
+    // nothing left on the canvas
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (-4, 3), (4, 1), &[red, red, green, green].concat());
+    });
+    assert_eq!(AsepriteFile::from_bytes(&input).unwrap().layer_frames(0), [None]);
+}
+
+#[test]
+fn test_cel_image_with_offset() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let input = fixture("default.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let cel = file.frames[0].cell_at_layer_index(0).unwrap();
+    let (img, offset) = file.cel_image_with_offset(0, 0).unwrap();
+    assert_eq!(img, file.images_decompressed[cel.image_index]);
+    assert_eq!(offset, (cel.chunk.x as i32, cel.chunk.y as i32));
+    assert!(file.cel_image_with_offset(0, 1).is_none());
+    assert!(file.cel_image_with_offset(1, 0).is_none());
+
+    // Hanging off the top left of the canvas
+    let pixels = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]].concat();
+    let input = edit_chunks(&input, 0, |chunks| {
+        chunks[4] = raw_cel(0, (-3, -1), (2, 2), &pixels);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let cel = file.frames[0].cell_at_layer_index(0).unwrap();
+    let (img, offset) = file.cel_image_with_offset(0, 0).unwrap();
+    assert_eq!(offset, (-3, -1));
+    assert_eq!(offset, (cel.chunk.x as i32, cel.chunk.y as i32));
+    assert_eq!(img.as_raw(), &pixels);
+    // entirely off the canvas, nothing is drawn
+    assert!(file.combined_frame_image(0).unwrap().pixels().all(|pixel| pixel.0 == [0; 4]));
+
+    // only the bottom right pixel is on the canvas
+    let input = edit_chunks(&input, 0, |chunks| {
+        chunks[4] = raw_cel(0, (-1, -1), (2, 2), &pixels);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let img = file.combined_frame_image(0).unwrap();
+    assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert_eq!(img.pixels().filter(|pixel| pixel.0 != [0; 4]).count(), 1);
+    let region = file.combined_frame_image_region(0, texture_packer::Rect::new(0, 0, 2, 2)).unwrap();
+    assert_eq!(region, image::imageops::crop_imm(&img, 0, 0, 2, 2).to_image());
+    let cropped = file.frames[0].combined_frame_image_cropped(&file.layers, &file.images_decompressed).unwrap();
+    assert_eq!((cropped.displacement_x, cropped.displacement_y), (0, 0));
+    assert_eq!(cropped.img.as_raw(), &[255, 255, 255, 255]);
+}
+
+#[test]
+fn test_duplicate_frame_groups() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    // Frames 4 and 5 are drawn the same in the fixture
+    assert_eq!(file.duplicate_frame_groups(), [[4, 5]]);
+
+    // Frame 3 uses the same cels as frame 0
+    file.frames[3].cells = file.frames[0].cells.clone();
+    assert_eq!(file.duplicate_frame_groups(), [vec![0, 3], vec![4, 5]]);
+
+    // Frame 6 draws the same pixels from images of its own
+    let mut cells = file.frames[0].cells.clone();
+    for cel in &mut cells {
+        file.images_decompressed.push(file.images_decompressed[cel.image_index].clone());
+        cel.image_index = file.images_decompressed.len() - 1;
     }
+    file.frames[6].cells = cells;
+    assert_eq!(file.duplicate_frame_groups(), [vec![0, 3, 6], vec![4, 5]]);
+    assert_eq!(file.combined_frame_image(6).unwrap(), file.combined_frame_image(0).unwrap());
+}
+
+#[test]
+fn test_contact_sheet() {
+    let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!((file.canvas_width(), file.canvas_height(), file.frames.len()), (128, 128, 3));
+
+    // 2 columns, 2 rows with the last cell empty
+    let sheet = file.contact_sheet(2).unwrap();
+    assert_eq!(sheet.dimensions(), (128 * 2 + 1, 128 * 2 + 1));
+    assert_eq!(*sheet.get_pixel(128, 5), CONTACT_SHEET_SEPARATOR);
+    assert_eq!(*sheet.get_pixel(5, 128), CONTACT_SHEET_SEPARATOR);
+    assert_eq!(*sheet.get_pixel(200, 200), image::Rgba([0, 0, 0, 0]));
+    let frame_2 = image::imageops::crop_imm(&sheet, 0, 129, 128, 128).to_image();
+    assert_eq!(frame_2, file.combined_frame_image(2).unwrap());
+
+    assert_eq!(file.contact_sheet(0).unwrap().dimensions(), (128 * 3 + 2, 128));
+    assert_eq!(file.contact_sheet(1).unwrap().dimensions(), (128, 128 * 3 + 2));
+
+    // the sheets are held to the same limit as the images of the file
+    let mut file = file;
+    file.max_image_bytes = (128 * 3 + 2) * 128 * 4 - 1;
+    assert!(matches!(
+        file.contact_sheet(0),
+        Err(LoadImageError::OutputTooLarge { width: 386, height: 128, .. })
+    ));
+    assert_eq!(file.strip_spritesheet(0).unwrap().0.dimensions(), (128 * 3, 128));
+    file.max_image_bytes = 128 * 3 * 128 * 4 - 1;
+    assert!(matches!(
+        file.strip_spritesheet(0),
+        Err(LoadImageError::OutputTooLarge { width: 384, height: 128, .. })
+    ));
+}
+
+#[test]
+fn test_empty_frames() {
+    use crate::binary::test_utils::{edit_chunks, fixture};
+
+    // One layer, no cel
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.remove(4);
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.layers.len(), 1);
+    assert!(file.frames[0].cells.is_empty());
+
+    let canvas = file.combined_frame_image(0).unwrap();
+    assert!(canvas.pixels().all(|p| p.0 == [0, 0, 0, 0]));
+    assert!(matches!(
+        file.frames[0].combined_frame_image_cropped(&file.layers, &file.images_decompressed),
+        Err(LoadImageError::EmptyFrame)
+    ));
+    assert_eq!(file.frame_trim_bounds(0), None);
+    let err = file.packed_spritesheet().unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(LoadImageError::NoFrames)), "{err}");
+    assert_eq!(file.strip_spritesheet(0).unwrap().0, canvas);
+
+    // No frames at all
+    file.frames.clear();
+    assert!(matches!(
+        file.combined_frame_image(0),
+        Err(LoadImageError::Sprite(e)) if matches!(*e, LoadSpriteError::FrameIndexOutOfRange(0))
+    ));
+    assert!(matches!(file.strip_spritesheet(0), Err(LoadImageError::NoFrames)));
+    assert!(file.frame_range(None).is_err());
+    assert!(file.packed_spritesheet().is_err());
+    assert!(file.flatten().unwrap().frames.is_empty());
+}
+
+#[test]
+fn test_export_tag_spritesheet() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let tag = file.tag("ye").unwrap();
+
+    let (sheet, rects) = file.export_tag_spritesheet("ye").unwrap();
+    assert_eq!(rects.len(), tag.frame_range().count());
+    for (rect, index) in rects.iter().zip(tag.frame_range()) {
+        assert_eq!((rect.w, rect.h), (file.canvas_width() as u32, file.canvas_height() as u32));
+        let packed = image::imageops::crop_imm(&sheet, rect.x, rect.y, rect.w, rect.h).to_image();
+        assert_eq!(packed, file.combined_frame_image(index).unwrap());
+    }
+
+    assert!(file.export_tag_spritesheet("missing").is_err());
+}
+
+#[test]
+fn test_packed_spritesheet_with() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel, set_canvas_size};
+    let mut input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (600, 1), &[255; 600 * 4]);
+    });
+    set_canvas_size(&mut input, 600, 32);
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+
+    assert!(file.packed_spritesheet().is_err());
+    let config = texture_packer::TexturePackerConfig {
+        max_width: 1024,
+        max_height: 1024,
+        allow_rotation: false,
+        trim: false,
+        ..Default::default()
+    };
+    let sheet = file.packed_spritesheet_with(config).unwrap();
+    assert!(sheet.width() >= 600);
+}
+
+#[test]
+fn test_packed_spritesheet_frames() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+
+    let config = texture_packer::TexturePackerConfig {
+        allow_rotation: false,
+        trim: false,
+        ..Default::default()
+    };
+    let (sheet, packed_frames) = file.packed_spritesheet_frames(config).unwrap();
+    assert_eq!(
+        packed_frames.iter().map(|f| f.frame_index).collect::<Vec<_>>(),
+        (0..file.frames.len()).collect::<Vec<_>>()
+    );
+    for (i, a) in packed_frames.iter().enumerate() {
+        let cropped = file.frames[a.frame_index]
+            .combined_frame_image_cropped(&file.layers, &file.images_decompressed)
+            .unwrap();
+        assert_eq!(a.displacement, (cropped.displacement_x, cropped.displacement_y));
+        let view = image::imageops::crop_imm(&sheet, a.rect.x, a.rect.y, a.rect.w, a.rect.h).to_image();
+        assert_eq!(view, cropped.img);
+
+        for b in &packed_frames[i + 1..] {
+            let overlaps = a.rect.x < b.rect.x + b.rect.w
+                && b.rect.x < a.rect.x + a.rect.w
+                && a.rect.y < b.rect.y + b.rect.h
+                && b.rect.y < a.rect.y + a.rect.h;
+            // Identical frames share their rect
+            assert!(a.rect == b.rect || !overlaps);
+        }
+    }
+}
+
+#[test]
+fn test_packed_spritesheet_frames_tagged_only() {
+    use crate::binary::{chunk_type::ChunkType, test_utils::{edit_chunks, fixture, is_chunk, set_tag_range}};
+
+    // Shrink the "ye" tag from 4..=7 to 4..=5, frames 6 and 7 become filler
+    let input = edit_chunks(&fixture("animated.aseprite"), 0, |chunks| {
+        let tags = chunks.iter_mut().find(|c| is_chunk(c, ChunkType::Tags)).unwrap();
+        set_tag_range(tags, 1, 4, 5);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.tag("ye").unwrap().frame_range(), 4..=5);
+
+    let config = texture_packer::TexturePackerConfig {
+        allow_rotation: false,
+        trim: false,
+        ..Default::default()
+    };
+    let (_, all) = file.packed_spritesheet_frames(config).unwrap();
+    let (_, tagged) = file.packed_spritesheet_frames_tagged_only(config).unwrap();
+    assert_eq!(all.len(), 8);
+    assert_eq!(
+        tagged.iter().map(|f| f.frame_index).collect::<Vec<_>>(),
+        (0..=5).collect::<Vec<_>>()
+    );
+    assert_eq!(&all[..6], &tagged[..]);
+}
+
+#[test]
+fn test_combined_frame_image_on() {
+    use crate::binary::test_utils::{edit_chunks, fixture};
+
+    let background = image::Rgba([12, 34, 56, 255]);
+    // chunks[4] is the only cel, without it the frame is fully transparent
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks.remove(4);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let img = file.combined_frame_image_on(0, background).unwrap();
+    assert_eq!(img.dimensions(), (32, 32));
+    assert!(img.pixels().all(|p| *p == background));
+
+    let input = fixture("default.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let transparent = file.combined_frame_image(0).unwrap();
+    let img = file.combined_frame_image_on(0, background).unwrap();
+    for (p, t) in img.pixels().zip(transparent.pixels()) {
+        if t.0[3] == 0 {
+            assert_eq!(*p, background);
+        }
+    }
+}
+
+#[test]
+fn test_overlapping_cels_on_one_layer() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let red = [255, 0, 0, 255].repeat(4);
+    // chunks[4] is the only cel, replace it by two overlapping ones on the same layer
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 2), &red);
+        chunks.push(raw_cel(0, (1, 0), (2, 2), &red));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    file.layers[0].chunk.opacity = 128;
+
+    let img = file.combined_frame_image(0).unwrap();
+    let single = *img.get_pixel(0, 0);
+    assert!(single.0[3] > 0 && single.0[3] < 255);
+    // Aseprite merges the cels of a layer before applying its opacity
+    assert_eq!(*img.get_pixel(1, 0), single);
+    assert_eq!(*img.get_pixel(2, 1), single);
+    assert_eq!(*img.get_pixel(3, 0), image::Rgba([0, 0, 0, 0]));
+
+    let cropped = file.frames[0].combined_frame_image_cropped(&file.layers, &file.images_decompressed).unwrap();
+    assert_eq!(cropped.img, image::imageops::crop_imm(&img, 0, 0, 3, 2).to_image());
+
+    // The cels are merged with the same integer math and cel opacity as a single cel
+    file.layers[0].chunk.opacity = 255;
+    file.frames[0].cells[1].chunk.opacity = 130;
+    for (cel, color) in [[200, 40, 10, 200], [30, 90, 220, 200]].into_iter().enumerate() {
+        let image_index = file.frames[0].cells[cel].image_index;
+        file.images_decompressed[image_index] = image::RgbaImage::from_pixel(2, 2, image::Rgba(color));
+    }
+    let img = file.combined_frame_image(0).unwrap();
+    assert_eq!(img.get_pixel(0, 0).0, [200, 40, 10, 200]);
+    assert_eq!(img.get_pixel(1, 0).0, [122, 62, 106, 222]);
+    assert_eq!(img.get_pixel(2, 0).0, [30, 90, 220, 102]);
+}
+
+#[test]
+fn test_negative_z_index() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+
+    let red = [255, 0, 0, 255].repeat(4);
+    let blue = [0, 0, 255, 255].repeat(4);
+    // chunks[3] is the only layer, add a second one above it whose cel overlaps the first at (1, 0)
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 2), &red);
+        chunks.push(chunks[3].clone());
+        chunks.push(raw_cel(1, (1, 0), (2, 2), &blue));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(1, 0).0, [0, 0, 255, 255]);
+
+    // -1 ties with the bottom layer and the lower z-index goes first, anything lower is behind it too
+    for z_index in [-1, -100] {
+        file.frames[0].cells[1].chunk.z_index = z_index;
+        let img = file.combined_frame_image(0).unwrap();
+        assert_eq!(img.get_pixel(1, 0).0, [255, 0, 0, 255], "z-index {z_index}");
+        assert_eq!(img.get_pixel(2, 0).0, [0, 0, 255, 255], "z-index {z_index}");
+    }
+
+    // Raising the bottom cel above the top layer does the same
+    file.frames[0].cells[1].chunk.z_index = 0;
+    file.frames[0].cells[0].chunk.z_index = 1;
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(1, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_group_opacity() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel, set_child_level, set_layer_type};
+
+    let white = [255, 255, 255, 255].repeat(2);
+    let red = [255, 0, 0, 255].repeat(2);
+    // chunks[3] is the only layer: the background, then a group with a layer in it
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 1), &white);
+        let mut group = chunks[3].clone();
+        set_layer_type(&mut group, LayerType::Group);
+        let mut child = chunks[3].clone();
+        set_child_level(&mut child, 1);
+        chunks.push(group);
+        chunks.push(child);
+        chunks.push(raw_cel(2, (0, 0), (1, 1), &red[..4]));
+        chunks.push(raw_cel(2, (1, 0), (1, 1), &red[..4]));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.layers[1].chunk.layer_type, LayerType::Group);
+    assert_eq!(file.layers[2].chunk.child_level, 1);
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+
+    // Same as Aseprite, 50% red over white
+    file.layers[1].chunk.opacity = 128;
+    let img = file.combined_frame_image(0).unwrap();
+    assert_eq!(img.get_pixel(0, 0).0, [255, 127, 127, 255]);
+    assert_eq!(img.get_pixel(1, 0).0, [255, 127, 127, 255]);
+
+    // The group opacity applies once to what's inside, on top of the opacity of the children
+    file.layers[2].chunk.opacity = 128;
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(0, 0).0, [255, 191, 191, 255]);
+
+    // Blend mode of the group: multiply with white gives the children back
+    file.layers[1].chunk.opacity = 255;
+    file.layers[2].chunk.opacity = 255;
+    file.layers[1].chunk.blend_mode = BlendMode::Multiply;
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    // Children are blended inside the group, not with what's under it
+    file.layers[2].chunk.blend_mode = BlendMode::Difference;
+    assert_eq!(file.combined_frame_image(0).unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn test_single_image_in_group() {
+    use crate::binary::{chunks::layer::LayerFlags, test_utils::{edit_chunks, fixture, raw_cel, set_child_level, set_layer_type}};
+
+    // a single cel covering the canvas, inside a group
+    let pixels = [[255, 0, 0, 255], [0, 255, 0, 128]].repeat(32 * 16).concat();
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        let mut group = chunks[3].clone();
+        set_layer_type(&mut group, LayerType::Group);
+        let mut child = chunks[3].clone();
+        set_child_level(&mut child, 1);
+        chunks[3] = group;
+        chunks[4] = child;
+        chunks.push(raw_cel(1, (0, 0), (32, 32), &pixels));
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(file.is_static());
+    assert_eq!(file.single_image().unwrap(), file.combined_frame_image(0).unwrap());
+
+    file.layers[0].chunk.opacity = 128;
+    let half = file.single_image().unwrap();
+    assert_eq!(half.get_pixel(0, 0).0, [255, 0, 0, 128]);
+    assert_eq!(half, file.combined_frame_image(0).unwrap());
+
+    file.layers[0].chunk.opacity = 255;
+    file.layers[0].chunk.flags.remove(LayerFlags::VISIBLE);
+    let hidden = file.single_image().unwrap();
+    assert!(hidden.pixels().all(|pixel| pixel.0 == [0; 4]));
+    assert_eq!(hidden, file.combined_frame_image(0).unwrap());
+}
+
+#[test]
+fn test_cel_opacity() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel, set_cel_opacity};
+
+    let red = [255, 0, 0, 255].repeat(4);
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 2), &red);
+        set_cel_opacity(&mut chunks[4], 128);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.layers[0].chunk.opacity, 255);
+    assert_eq!(file.frames[0].cells[0].opacity(), 128);
 
+    // Same as a fully opaque cel on a half transparent layer
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (0, 0), (2, 2), &red);
+    });
+    let mut layer_opacity = AsepriteFile::from_bytes(&input).unwrap();
+    layer_opacity.layers[0].chunk.opacity = 128;
+
+    let img = file.combined_frame_image(0).unwrap();
+    assert_eq!(img.get_pixel(0, 0).0[3], 128);
+    assert_eq!(img, layer_opacity.combined_frame_image(0).unwrap());
+
+    // Both multiply together
+    let mut both = file;
+    both.layers[0].chunk.opacity = 128;
+    assert_eq!(both.combined_frame_image(0).unwrap().get_pixel(0, 0).0[3], 64);
+}
+
+#[test]
+fn test_combined_frame_image_into() {
+    /// Tightly packed RGBA rows, like a texture upload buffer
+    struct Buffer {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    }
+
+    impl PixelSink for Buffer {
+        fn dimensions(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        fn get_pixel(&self, x: u32, y: u32) -> image::Rgba<u8> {
+            let i = (y * self.width + x) as usize * 4;
+            image::Rgba(self.data[i..i + 4].try_into().unwrap())
+        }
+
+        fn put_pixel(&mut self, x: u32, y: u32, pixel: image::Rgba<u8>) {
+            let i = (y * self.width + x) as usize * 4;
+            self.data[i..i + 4].copy_from_slice(&pixel.0);
+        }
+    }
+
+    let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let (width, height) = (file.canvas_width() as u32, file.canvas_height() as u32);
+    for index in 0..file.frames.len() {
+        let mut buffer = Buffer { width, height, data: vec![0; (width * height * 4) as usize] };
+        file.combined_frame_image_into(index, &mut buffer).unwrap();
+        assert_eq!(buffer.data, file.combined_frame_image(index).unwrap().into_raw());
+    }
+
+    let mut small = Buffer { width: width - 1, height, data: vec![0; ((width - 1) * height * 4) as usize] };
+    assert!(matches!(
+        file.combined_frame_image_into(0, &mut small),
+        Err(LoadImageError::TargetBufferTooSmall)
+    ));
+}
+
+#[test]
+fn test_hitboxes() {
+    use crate::{binary::test_utils::{edit_chunks, fixture, raw_cel}, wrappers::LayerParameter};
+
+    // An L-shape in a 3x3 cel:
+    // X . .
+    // X . .
+    // X X X
+    let (o, x) = ([0, 0, 0, 0], [255, 255, 255, 255]);
+    let pixels = [x, o, o, x, o, o, x, x, x].concat();
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (2, 5), (3, 3), &pixels);
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert!(file.frames[0].hitboxes(&file.layers, &file.images_decompressed).is_empty());
+
+    file.layers[0].parameters.insert(LayerParameter::Hitbox, String::new());
+    let hitboxes = file.frames[0].hitboxes(&file.layers, &file.images_decompressed);
+    assert!(hitboxes.len() >= 2);
+
+    let mut mask = vec![0; 9];
+    for hitbox in hitboxes.iter() {
+        assert_eq!(hitbox.layer_id, 0);
+        for y in hitbox.offset.1..hitbox.offset.1 + hitbox.size.1 as i32 {
+            for x in hitbox.offset.0..hitbox.offset.0 + hitbox.size.0 as i32 {
+                mask[((y - 5) * 3 + x - 2) as usize] += 1;
+            }
+        }
+    }
+    assert_eq!(mask, [1, 0, 0, 1, 0, 0, 1, 1, 1]);
+
+    // A cel hanging off the left edge, its first opaque column is still left of the canvas
+    let pixels = [o, x, x, o, x, x].concat();
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (-2, 1), (3, 2), &pixels);
+    });
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    file.layers[0].parameters.insert(LayerParameter::Hitbox, String::new());
+    let hitboxes = file.frames[0].hitboxes(&file.layers, &file.images_decompressed);
+    assert_eq!(hitboxes, [Hitbox { offset: (-1, 1), size: (2, 2), layer_id: 0 }]);
+}
+
+#[test]
+fn test_reference_layer_not_composited() {
+    use crate::binary::{chunks::layer::LayerFlags, test_utils::{edit_chunks, fixture, raw_cel, set_layer_flags}};
+
+    // chunks[3] is the only layer and chunks[4] its (empty) cel
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (3, 4), (2, 1), &[255, 0, 0, 255, 0, 255, 0, 255]);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let with_layer = file.combined_frame_image(0).unwrap();
+    assert_eq!(with_layer.pixels().filter(|p| p.0[3] != 0).count(), 2);
+
+    let flags = LayerFlags::VISIBLE | LayerFlags::REFERENCE;
+    let input = edit_chunks(&input, 0, |chunks| {
+        set_layer_flags(&mut chunks[3], flags);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.combined_frame_image(0).unwrap(), image::RgbaImage::new(32, 32));
+    assert!(matches!(
+        file.frames[0].combined_frame_image_cropped(&file.layers, &file.images_decompressed),
+        Err(LoadImageError::EmptyFrame)
+    ));
+    assert_eq!(file.frame_builder(0).include_reference(true).build().unwrap(), with_layer);
+}
+
+#[test]
+fn test_premultiply() {
+    let mut img = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 128]));
+    premultiply(&mut img);
+    assert_eq!(*img.get_pixel(0, 0), image::Rgba([128, 128, 128, 128]));
+
+    let input = std::fs::read("tests/aseprite_files/combine.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let straight = file.combined_frame_image(0).unwrap();
+    let premultiplied = file.combined_frame_image_premultiplied(0).unwrap();
+    for (s, p) in straight.pixels().zip(premultiplied.pixels()) {
+        assert_eq!(s.a(), p.a());
+        if s.a() == 255 {
+            assert_eq!(s, p);
+        }
+        if s.a() == 0 {
+            assert_eq!(*p, image::Rgba([0, 0, 0, 0]));
+        }
+    }
+}
+
+#[test]
+fn test_frame_delta() {
+    use crate::binary::{chunk_type::ChunkType, chunks::layer::LayerFlags, test_utils::{edit_chunks, fixture, is_chunk, linked_cel_like}};
+
+    let is_cel = |chunk: &Vec<u8>| is_chunk(chunk, ChunkType::Cel);
+    let input = fixture("combine.aseprite");
+    let mut previous = Vec::new();
+    edit_chunks(&input, 1, |chunks| previous = chunks.iter().filter(|c| is_cel(c)).cloned().collect());
+    // Frame 2 links every cel of frame 1, at the same place
+    let linked = edit_chunks(&input, 2, |chunks| {
+        chunks.retain(|c| !is_cel(c));
+        chunks.extend(previous.iter().map(|cel| linked_cel_like(cel, 1)));
+    });
+
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    assert_eq!(file.frame_delta(0), Some(FrameDelta::First));
+    assert_eq!(file.frame_delta(2), Some(FrameDelta::Changed));
+    assert_eq!(file.frame_delta(3), None);
+
+    // Everything in a hidden group: nothing is drawn in either frame
+    file.layers[0].chunk.layer_type = LayerType::Group;
+    file.layers[1].chunk.child_level = 1;
+    file.layers[2].chunk.child_level = 1;
+    file.layers[0].chunk.flags.remove(LayerFlags::VISIBLE);
+    assert!(file.layers[1].visible() && file.layers[2].visible());
+    assert_eq!(file.frame_delta(2), Some(FrameDelta::Unchanged));
+
+    let file = AsepriteFile::from_bytes(&linked).unwrap();
+    assert_eq!(file.frame_delta(1), Some(FrameDelta::Changed));
+    assert_eq!(file.frame_delta(2), Some(FrameDelta::Unchanged));
+    assert_eq!(file.combined_frame_image(1).unwrap(), file.combined_frame_image(2).unwrap());
 }
 
+#[test]
+fn test_cropped_to_canvas() {
+    use crate::binary::test_utils::fixture;
+
+    let input = fixture("combine.aseprite");
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+    let canvas_size = (file.canvas_width() as u32, file.canvas_height() as u32);
+    for (index, frame) in file.frames.iter().enumerate() {
+        let cropped = frame.combined_frame_image_cropped(&file.layers, &file.images_decompressed).unwrap();
+        assert_ne!(cropped.img.dimensions(), canvas_size);
+        assert_eq!(cropped.to_canvas(canvas_size), file.combined_frame_image(index).unwrap());
+    }
+}
+
+#[test]
+fn test_cropped_image_eq() {
+    let img = image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 255]));
+    let a = CroppedImage { img: img.clone(), displacement_x: 4, displacement_y: 5 };
+    let b = CroppedImage { img: img.clone(), displacement_x: 4, displacement_y: 5 };
+    let offset = CroppedImage { img, displacement_x: 5, displacement_y: 5 };
+    assert_eq!(a, b);
+    assert_ne!(a, offset);
+
+    let hasher = ahash::RandomState::new();
+    assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+}
+
+#[test]
+fn test_combined_frame_image_scaled() {
+    use crate::binary::test_utils::{edit_chunks, fixture, raw_cel};
+    let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+        chunks[4] = raw_cel(0, (5, 7), (1, 1), &[255, 0, 0, 255]);
+    });
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+
+    let scale = 3;
+    let img = file.combined_frame_image_scaled(0, scale).unwrap();
+    assert_eq!(img.dimensions(), (32 * scale, 32 * scale));
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let in_dot = (5 * scale..6 * scale).contains(&x) && (7 * scale..8 * scale).contains(&y);
+        let expected = if in_dot { [255, 0, 0, 255] } else { [0; 4] };
+        assert_eq!(pixel.0, expected, "at {x},{y}");
+    }
+
+    assert!(matches!(file.combined_frame_image_scaled(0, 0), Err(LoadImageError::InvalidImageData)));
+
+    // the scale can't get around the image size limit
+    let mut file = file;
+    assert!(matches!(
+        file.combined_frame_image_scaled(0, u32::MAX),
+        Err(LoadImageError::OutputTooLarge { .. })
+    ));
+    file.max_image_bytes = 32 * 32 * 4 * 9;
+    assert!(file.combined_frame_image_scaled(0, 3).is_ok());
+    assert!(matches!(
+        file.combined_frame_image_scaled(0, 4),
+        Err(LoadImageError::OutputTooLarge { width: 128, height: 128, .. })
+    ));
+}
 
 /*     pub fn get_image_as_rgba(&self, index: usize) -> Result<DecompressedImage<'_>, LoadImageError> {
         let image = &self.images_decompressed[index];
@@ -244,6 +2368,7 @@ impl AsepriteFile<'_> {
                     image.data,
                     &self.palette,
                     target,
+                    OutOfRangeIndex::Error,
                 )?;
             }
             ColorDepth::Unknown(_) => return Err(LoadImageError::UnsupportedColorDepth),
@@ -264,19 +2389,4 @@ impl AsepriteFile<'_> {
     }
     Ok(())
 }
-
-fn indexed_to_rgba(
-    source: &[u8],
-    palette: &Palette,
-    target: &mut [u8],
-) -> Result<(), LoadImageError> {
-    if target.len() != source.len() * 4 {
-        return Err(LoadImageError::InvalidImageData);
-    }
-    let pixels = target.as_rgba_mut();
-    for (i, px) in source.iter().enumerate() {
-        pixels[i] = palette.colors[*px as usize];
-    }
-    Ok(())
-}
     */
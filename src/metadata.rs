@@ -0,0 +1,87 @@
+//! The layout of a file (sizes, layers, tags, frame durations) without decoding any image.
+//! Unlike [`AsepriteFile`](crate::loader::AsepriteFile) this only needs `alloc`.
+
+use alloc::vec::Vec;
+
+use crate::binary::{
+    chunk::Chunk,
+    chunks::{layer::LayerChunk, tags::TagChunk},
+    errors::ParseError,
+    header::Header,
+    raw_file::parse_raw_file,
+    scalars::Word,
+};
+
+#[derive(Debug)]
+pub struct Metadata<'a> {
+    pub header: Header,
+    /// All layers in the file in order
+    pub layers: Vec<LayerChunk<'a>>,
+    /// All tags in the file
+    pub tags: Vec<TagChunk<'a>>,
+    /// Duration of each frame, in milliseconds
+    pub frame_durations: Vec<Word>,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, nom::Err<ParseError<'a>>> {
+        let file = parse_raw_file(data)?;
+        let mut layers = Vec::new();
+        let mut tags = Vec::new();
+        let mut frame_durations = Vec::with_capacity(file.frames.len());
+        for frame in file.frames {
+            frame_durations.push(frame.duration);
+            for chunk in frame.chunks {
+                match chunk {
+                    Chunk::Layer(layer) => layers.push(layer),
+                    Chunk::Tags(tags_chunk) => tags.extend(tags_chunk.tags),
+                    _ => (),
+                }
+            }
+        }
+        Ok(Self {
+            header: file.header,
+            layers,
+            tags,
+            frame_durations,
+        })
+    }
+
+    pub fn canvas_size(&self) -> (Word, Word) {
+        (self.header.width, self.header.height)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_durations.len()
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&TagChunk<'a>> {
+        self.tags.iter().find(|t| t.name == name)
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_metadata() {
+    use crate::loader::AsepriteFile;
+
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let metadata = Metadata::from_bytes(&input).unwrap();
+    let file = AsepriteFile::from_bytes(&input).unwrap();
+
+    assert_eq!(metadata.canvas_size(), (file.canvas_width(), file.canvas_height()));
+    assert_eq!(metadata.frame_count(), file.frames.len());
+    assert_eq!(
+        metadata.frame_durations.iter().map(|&d| d as u32).collect::<Vec<_>>(),
+        file.frames.iter().map(|f| f.duration).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        metadata.layers.iter().map(|l| l.name).collect::<Vec<_>>(),
+        file.layers.iter().map(|l| l.name()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        metadata.tags.iter().map(|t| t.name).collect::<Vec<_>>(),
+        file.tags.iter().map(|t| t.name()).collect::<Vec<_>>()
+    );
+    assert_eq!(metadata.tag("ye").unwrap().frames, (4, 7));
+}
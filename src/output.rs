@@ -1,8 +1,8 @@
-use std::ops::{Index, RangeBounds};
+use std::{borrow::Cow, ops::{Index, IndexMut}};
 
 use itertools::Itertools;
 
-use crate::{loader::AsepriteFile, make_image::{CroppedImage, Hitbox, LoadImageError}, wrappers::{LayerParameters, TagParameters}};
+use crate::{binary::{blend_mode::BlendMode, chunks::layer::LayerType}, loader::AsepriteFile, make_image::{Hitbox, LoadImageError}, wrappers::{FrameParameters, LayerParameters, TagParameters}};
 
 #[derive(Debug, Clone)]
 pub struct ImageId {
@@ -10,6 +10,8 @@ pub struct ImageId {
     pub image_ref: String,
     // offset by this amount if texture has tl as origin
     pub tl_offset: (u32, u32),
+    /// The pivot of the frame relative to the top left of the image, to align sprites by it instead of their center
+    pub anchor: Option<(i32, i32)>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,7 +19,10 @@ pub struct AnimFrame {
     pub duration: u32, // milliseconds
     pub image_ids: Option<ImageId>, // Todo turn into vec, to have split layers
     pub hitboxes: Vec<Hitbox>,
-    pub actions: Vec<()>, // TODO: A frame has no user data, use the ones from each cell all together?
+    /// The pivot on the canvas, see [`AnimationSetOptions::pivot_slice`]
+    pub pivot: Option<(i32, i32)>,
+    /// From the user data of the cels of the frame, see [`Frame::parameters`](crate::wrappers::Frame::parameters)
+    pub actions: FrameParameters,
 }
 
 #[derive(Debug)]
@@ -26,60 +31,188 @@ pub struct Animation {
     pub actions: TagParameters,
 }
 
+/// Images already in the packer by content hash, so identical frames are only packed once
+#[derive(Default)]
+struct PackedImages {
+    hasher: ahash::RandomState,
+    by_hash: ahash::AHashMap<u64, Vec<(image::RgbaImage, String)>>,
+}
+
+impl PackedImages {
+    fn hash(&self, img: &image::RgbaImage) -> u64 {
+        self.hasher.hash_one((img.dimensions(), img.as_raw()))
+    }
+
+    fn get(&self, img: &image::RgbaImage) -> Option<&str> {
+        self.by_hash.get(&self.hash(img))?
+            .iter()
+            .find(|(packed, _)| packed == img)
+            .map(|(_, img_ref)| img_ref.as_str())
+    }
+
+    fn insert(&mut self, img: image::RgbaImage, img_ref: String) {
+        let hash = self.hash(&img);
+        self.by_hash.entry(hash).or_default().push((img, img_ref));
+    }
+}
+
+/// How [`AnimationSet::from_ase_with`] packs the frames, [`AnimationSet::from_ase`] uses the defaults
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnimationSetOptions<'s> {
+    /// Frames not covered by any tag aren't packed, those can't be part of an animation anyway
+    pub tagged_only: bool,
+    /// Identical frames aren't merged: every non-empty frame gets an image of its own, e.g. for per-frame effects on the GPU
+    pub keep_duplicates: bool,
+    /// Records the pivot of this slice (e.g. `"pivot"`) in each frame, every file needs the slice.
+    /// Frames before the first key of the slice, or whose key has no pivot, have none.
+    pub pivot_slice: Option<&'s str>,
+    /// Names the animations `{base name}{tag}` instead of after the tag alone, so files with the same tags can be packed together
+    pub prefix_animations: bool,
+}
+
 #[derive(Debug)]
-pub struct AnimationSet {
+pub struct AnimationSet<'a> {
     pub canvas_size: (u32, u32),
+    /// By layer id, the [`Hitbox::layer_id`] of the hitboxes found on the layer
     pub layer_parameters: Vec<LayerParameters>,
-    pub animations: ahash::AHashMap<String, Animation>, // TODO: not string, some form of enum repr?
+    /// By tag name, borrowed from the file data. Tag names are free text in Aseprite, so they are kept as strings.
+    pub animations: ahash::AHashMap<Cow<'a, str>, Animation>,
 }
 
-impl AnimationSet {
-    pub fn from_ase(file: AsepriteFile<'_>, base_name: &str, packer: &mut texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>) -> anyhow::Result<Self> {
-        let mut frame_image_dedup = bimap::BiHashMap::<String,image::RgbaImage,ahash::RandomState, ahash::RandomState>::default();
+impl<'a> AnimationSet<'a> {
+    /// Copies the tag names so the set doesn't borrow the file data anymore
+    pub fn into_owned(self) -> AnimationSet<'static> {
+        AnimationSet {
+            canvas_size: self.canvas_size,
+            layer_parameters: self.layer_parameters,
+            animations: self.animations
+                .into_iter()
+                .map(|(name, animation)| (Cow::Owned(name.into_owned()), animation))
+                .collect(),
+        }
+    }
+
+    /// The names of the animations, in no particular order
+    pub fn animation_names(&self) -> impl Iterator<Item = &str> {
+        self.animations.keys().map(|name| name.as_ref())
+    }
+
+    /// Get the animation of a tag
+    pub fn get(&self, name: &str) -> Option<&Animation> {
+        self.animations.get(name)
+    }
+
+    pub fn from_ase(file: AsepriteFile<'a>, base_name: &str, packer: &mut texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>) -> anyhow::Result<Self> {
+        Self::from_ase_with([(base_name.to_string(), file)], packer, AnimationSetOptions::default())
+    }
+
+    /// Same as [`Self::from_ase`] with `options`, for one or several files packed into the same atlas,
+    /// e.g. a character split in body, head and effects files. The image refs of a file start with the base name given with it.
+    /// The canvas size is the largest of all the files. The layers of the files are numbered one after the other,
+    /// the layer ids of the hitboxes of a file are offset by the layer count of the files before it.
+    /// A frame found in several files is only packed once, under the image ref of the first file.
+    pub fn from_ase_with(
+        files: impl IntoIterator<Item = (String, AsepriteFile<'a>)>,
+        packer: &mut texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>,
+        options: AnimationSetOptions<'_>,
+    ) -> anyhow::Result<Self> {
+        let mut out = Self {
+            canvas_size: (0, 0),
+            layer_parameters: Vec::new(),
+            animations: Default::default(),
+        };
+        let mut packed = PackedImages::default();
+        for (base_name, file) in files {
+            if let Some(pivot_slice) = options.pivot_slice {
+                anyhow::ensure!(file.slice(pivot_slice).is_some(), "no slice named {pivot_slice:?}");
+            }
+            let packed = (!options.keep_duplicates).then_some(&mut packed);
+            let set = Self::from_ase_inner(file, &base_name, packer, options.tagged_only, options.pivot_slice, packed)?;
+            out.canvas_size.0 = out.canvas_size.0.max(set.canvas_size.0);
+            out.canvas_size.1 = out.canvas_size.1.max(set.canvas_size.1);
+            let layer_offset = out.layer_parameters.len();
+            out.layer_parameters.extend(set.layer_parameters);
+            for (name, mut animation) in set.animations {
+                for hitbox in animation.frames.iter_mut().flat_map(|f| f.hitboxes.iter_mut()) {
+                    hitbox.layer_id += layer_offset;
+                }
+                let name = match options.prefix_animations {
+                    true => Cow::Owned(format!("{base_name}{name}")),
+                    false => name,
+                };
+                anyhow::ensure!(!out.animations.contains_key(&name), "animation {name:?} appears twice");
+                out.animations.insert(name, animation);
+            }
+        }
+        Ok(out)
+    }
+
+    fn from_ase_inner(file: AsepriteFile<'a>, base_name: &str, packer: &mut texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>, only_tagged: bool, pivot_slice: Option<&str>, mut packed: Option<&mut PackedImages>) -> anyhow::Result<Self> {
+        let tagged = (0..file.frames.len()).map(|i| file.is_tagged(i)).collect_vec();
+        let pivots = (0..file.frames.len())
+            .map(|i| pivot_slice.and_then(|name| file.slice_pivot(name, i)))
+            .collect_vec();
 
         let mut anim_frames = Vec::new();
         for (ind, f) in file.frames.into_iter().enumerate() {
+            if only_tagged && !tagged[ind] {
+                // never referenced by an animation, keeps the indices in the tag ranges valid
+                anim_frames.push(AnimFrame {
+                    duration: f.duration,
+                    image_ids: None,
+                    hitboxes: Vec::new(),
+                    pivot: pivots[ind],
+                    actions: f.parameters(),
+                });
+                continue;
+            }
             let img = f.combined_frame_image_cropped(&file.layers, &file.images_decompressed);
             let img = match img {
                 Ok(img) => Some(img),
                 Err(LoadImageError::EmptyFrame) => None,
-                Err(e) => anyhow::bail!(e.to_string()),
+                Err(e) => return Err(e.into()),
             };
             let mut img_id = Vec::new();
             if let Some(img) = img {
                 let tl_offset = (img.displacement_x, img.displacement_y); 
-                let img_ref = if let Some(img_ref) = frame_image_dedup.get_by_right(&img.img) {
+                let img_ref = if let Some(img_ref) = packed.as_deref().and_then(|packed| packed.get(&img.img)) {
                     img_ref.to_owned()
                 } else {
                     let img_ref = format!("{base_name}{ind}");
-                    // TODO: this packs all frames, even the ones not included under any animations
                     packer.pack_own(img_ref.clone(), img.img.clone()).map_err(|e| anyhow::anyhow!("{e:?}"))?;
-                    frame_image_dedup.insert(img_ref.clone(), img.img);
+                    if let Some(packed) = packed.as_deref_mut() {
+                        packed.insert(img.img, img_ref.clone());
+                    }
                     img_ref
                 };
 
-                img_id.push(ImageId { image_ref: img_ref, tl_offset });
+                let anchor = pivots[ind].map(|(x, y)| (x - tl_offset.0 as i32, y - tl_offset.1 as i32));
+                img_id.push(ImageId { image_ref: img_ref, tl_offset, anchor });
             }
 
             anim_frames.push(AnimFrame {
                 duration: f.duration,
                 image_ids: img_id.first().cloned(),
                 hitboxes: f.hitboxes(&file.layers, &file.images_decompressed),
-                actions: Default::default(),
+                pivot: pivots[ind],
+                actions: f.parameters(),
             })
         }
 
-        let animations: ahash::AHashMap<String, Animation> = file.tags
+        let animations: ahash::AHashMap<Cow<'a, str>, Animation> = file.tags
             .into_iter()
             .map(|t| {
-                let frames = anim_frames[t.frame_range()].to_owned();
-                
+                // checked when loading, but the tags can be edited since
+                let Some(frames) = anim_frames.get(t.frame_range()) else {
+                    anyhow::bail!("tag {:?} covers frames {:?} but there are only {} frames", t.name(), t.frame_range(), anim_frames.len());
+                };
+
                 let a = Animation {
-                    frames,
+                    frames: frames.to_owned(),
                     actions: t.parameters,
                 };
-                (t.chunk.name.to_string(), a)
-            }).collect();
+                Ok((Cow::Borrowed(t.chunk.name), a))
+            }).collect::<anyhow::Result<_>>()?;
 
         let layer_parameters = file.layers.into_iter().map(|l| l.parameters).collect_vec();
         
@@ -91,6 +224,165 @@ impl AnimationSet {
     }
 }
 
+/// Where a packed image ended up in a [`SpriteAtlas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasFrame {
+    /// Index in [`SpriteAtlas::pages`]
+    pub page: usize,
+    pub rect: texture_packer::Rect,
+}
+
+/// The packed pages and animations of a file, for engines that don't want to deal with the packer
+#[derive(Debug)]
+pub struct SpriteAtlas<'a> {
+    pub pages: Vec<image::RgbaImage>,
+    /// By [`ImageId::image_ref`]
+    pub frames: ahash::AHashMap<String, AtlasFrame>,
+    pub animations: AnimationSet<'a>,
+}
+
+impl SpriteAtlas<'_> {
+    /// Where the image of an animation frame is, `None` for empty frames
+    pub fn frame(&self, image_id: &ImageId) -> Option<&AtlasFrame> {
+        self.frames.get(&image_id.image_ref)
+    }
+}
+
+impl<'a> AsepriteFile<'a> {
+    /// Packs the frames into as many pages as needed and builds the animations pointing into them.
+    /// Use [`AnimationSet::from_ase`] with a packer of your own to pack several files together.
+    /// Keep `trim` and `allow_rotation` off, the frames are already cropped.
+    pub fn build_atlas(self, config: texture_packer::TexturePackerConfig) -> anyhow::Result<SpriteAtlas<'a>> {
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let animations = AnimationSet::from_ase(self, "", &mut packer)?;
+        let (pages, frames) = export_pages(&packer)?;
+        Ok(SpriteAtlas { pages, frames, animations })
+    }
+
+    /// Packs the cels of each visible layer on their own instead of compositing the frames,
+    /// for engines that stack the layers themselves (e.g. to tint a glow layer).
+    /// Drawing the cels of [`LayeredAtlas::layers`] bottom to top with their blend mode and opacity gives back the frames.
+    pub fn build_layered_atlas(&self, config: texture_packer::TexturePackerConfig) -> anyhow::Result<LayeredAtlas> {
+        let layers = self.visible_layers()
+            .filter(|(_, layer)| layer.chunk.layer_type != LayerType::Group && !layer.is_reference())
+            .map(|(index, layer)| AtlasLayer {
+                index,
+                name: layer.name().to_string(),
+                blend_mode: layer.chunk.blend_mode,
+                opacity: layer.chunk.opacity,
+            })
+            .collect_vec();
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let mut cel_refs = Vec::new();
+        for (frame_index, frame) in self.frames.iter().enumerate() {
+            for layer in &layers {
+                let Some(cel) = frame.cell_at_layer_index(layer.index) else {
+                    continue;
+                };
+                // linked cels share their image, it's only packed once
+                let image_ref = cel.image_index.to_string();
+                if packer.get_frame(&image_ref).is_none() {
+                    let img = self.images_decompressed[cel.image_index].clone();
+                    packer.pack_own(image_ref.clone(), img).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+                }
+                cel_refs.push(((layer.index, frame_index), image_ref, (cel.chunk.x as i32, cel.chunk.y as i32), cel.opacity()));
+            }
+        }
+
+        let (pages, frames) = export_pages(&packer)?;
+        let cels = cel_refs
+            .into_iter()
+            .map(|(key, image_ref, displacement, opacity)| {
+                let AtlasFrame { page, rect } = frames[&image_ref];
+                (key, AtlasCel { page, rect, displacement, opacity })
+            })
+            .collect();
+        Ok(LayeredAtlas {
+            canvas_size: (self.canvas_width() as u32, self.canvas_height() as u32),
+            pages,
+            layers,
+            cels,
+        })
+    }
+}
+
+/// Exports the pages of `packer`, with where each image ended up
+fn export_pages(
+    packer: &texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>,
+) -> anyhow::Result<(Vec<image::RgbaImage>, ahash::AHashMap<String, AtlasFrame>)> {
+    let mut pages = Vec::with_capacity(packer.get_pages().len());
+    let mut frames = ahash::AHashMap::new();
+    for (page, packed) in packer.get_pages().iter().enumerate() {
+        let img = texture_packer::exporter::ImageExporter::export(packed).map_err(|s| anyhow::anyhow!(s))?;
+        pages.push(img.to_rgba8());
+        frames.extend(packed.get_frames().iter().map(|(image_ref, frame)| {
+            (image_ref.clone(), AtlasFrame { page, rect: frame.frame })
+        }));
+    }
+    Ok((pages, frames))
+}
+
+/// A layer of a [`LayeredAtlas`], and how it's blended onto the ones below
+#[derive(Debug, Clone)]
+pub struct AtlasLayer {
+    /// Index in [`AsepriteFile::layers`]
+    pub index: usize,
+    pub name: String,
+    pub blend_mode: BlendMode,
+    pub opacity: u8,
+}
+
+/// Where a cel ended up in a [`LayeredAtlas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasCel {
+    /// Index in [`LayeredAtlas::pages`]
+    pub page: usize,
+    pub rect: texture_packer::Rect,
+    /// Where the top left of the cel goes on the canvas, negative when it hangs off the top or left edge
+    pub displacement: (i32, i32),
+    /// Multiplied with the opacity of the layer
+    pub opacity: u8,
+}
+
+/// The cels of every visible layer packed separately, see [`AsepriteFile::build_layered_atlas`]
+#[derive(Debug)]
+pub struct LayeredAtlas {
+    pub canvas_size: (u32, u32),
+    pub pages: Vec<image::RgbaImage>,
+    /// The packed layers, bottom to top
+    pub layers: Vec<AtlasLayer>,
+    /// By `(layer_index, frame_index)`, layers without a cel in a frame have no entry
+    pub cels: ahash::AHashMap<(usize, usize), AtlasCel>,
+}
+
+/// The animation of a tag, panics if there is no such tag
+///
+/// ```
+/// # use assu_parser::{loader::AsepriteFile, AnimationSet};
+/// let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+/// let file = AsepriteFile::from_bytes(&input).unwrap();
+/// let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+/// let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+/// let set = AnimationSet::from_ase(file, "animated", &mut packer).unwrap();
+/// assert_eq!(set["ye"].frames.len(), 4);
+/// assert!(set.get("missing").is_none());
+/// assert!(set.animation_names().any(|name| name == "ye"));
+/// ```
+impl Index<&str> for AnimationSet<'_> {
+    type Output = Animation;
+
+    fn index(&self, name: &str) -> &Animation {
+        self.get(name).unwrap_or_else(|| panic!("no animation named {name:?}"))
+    }
+}
+
+impl IndexMut<&str> for AnimationSet<'_> {
+    fn index_mut(&mut self, name: &str) -> &mut Animation {
+        self.animations.get_mut(name).unwrap_or_else(|| panic!("no animation named {name:?}"))
+    }
+}
+
 pub fn tl_offset_to_centered(tl_offset: (u32, u32), sprite_size: (u32, u32), canvas_size: (u32, u32)) -> (f32, f32){
     // Motivation: do not want the 'centered' sprite to be offset by 1/2 a pixel compared to other sprites
     //             This happens if the canvas has even dimensions, the resulting sprite will be too centered
@@ -134,4 +426,297 @@ mod tests {
             assert!(left_top_pos.1.fract() < 0.00001);
         }
     }
+
+    #[test]
+    fn test_from_ase_with_pivot() {
+        use crate::binary::test_utils::{edit_chunks, fixture, pivot_slice};
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}};
+
+        let input = edit_chunks(&fixture("animated.aseprite"), 0, |chunks| {
+            chunks.push(pivot_slice("pivot", &[(0, 1, 2, 4, 4, 2, 3), (5, 10, 10, 4, 4, -1, 0)]));
+        });
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let with_pivot = |file, pivot_slice, packer: &mut _| {
+            let options = AnimationSetOptions { pivot_slice: Some(pivot_slice), ..Default::default() };
+            AnimationSet::from_ase_with([("animated".to_string(), file)], packer, options)
+        };
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        assert!(with_pivot(file, "missing", &mut packer).is_err());
+
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        assert_eq!(file.frame_range(Some("ye")).unwrap(), 4..=7);
+        let set = with_pivot(file, "pivot", &mut packer).unwrap();
+        let frames = &set["ye"].frames;
+        let pivots = frames.iter().map(|f| f.pivot).collect::<Vec<_>>();
+        assert_eq!(pivots, [Some((3, 5)), Some((9, 10)), Some((9, 10)), Some((9, 10))]);
+        for frame in frames {
+            let Some(image_id) = &frame.image_ids else { continue };
+            let (x, y) = frame.pivot.unwrap();
+            assert_eq!(image_id.anchor, Some((x - image_id.tl_offset.0 as i32, y - image_id.tl_offset.1 as i32)));
+        }
+
+        // without a pivot slice nothing is recorded
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        let set = AnimationSet::from_ase(file, "animated", &mut packer).unwrap();
+        assert!(set["ye"].frames.iter().all(|f| f.pivot.is_none()));
+    }
+
+    #[test]
+    fn test_build_atlas() {
+        use crate::loader::AsepriteFile;
+
+        let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        let expected = file.frame_range(Some("ye")).unwrap()
+            .map(|index| file.frames[index].combined_frame_image_cropped(&file.layers, &file.images_decompressed).ok())
+            .collect::<Vec<_>>();
+
+        let config = texture_packer::TexturePackerConfig {
+            allow_rotation: false,
+            trim: false,
+            texture_outlines: false,
+            ..Default::default()
+        };
+        let atlas = file.build_atlas(config).unwrap();
+
+        assert!(!atlas.pages.is_empty());
+        let animation = &atlas.animations["ye"];
+        assert_eq!(animation.frames.len(), expected.len());
+        for (frame, expected) in animation.frames.iter().zip(expected) {
+            let (Some(image_id), Some(expected)) = (&frame.image_ids, expected) else {
+                assert!(frame.image_ids.is_none());
+                continue;
+            };
+            let packed = atlas.frame(image_id).unwrap();
+            let rect = packed.rect;
+            let img = image::imageops::crop_imm(&atlas.pages[packed.page], rect.x, rect.y, rect.w, rect.h).to_image();
+            assert_eq!(img, expected.img);
+            assert_eq!(image_id.tl_offset, (expected.displacement_x, expected.displacement_y));
+        }
+    }
+
+    #[test]
+    fn test_build_layered_atlas() {
+        use crate::{binary::test_utils::{edit_chunks, fixture, raw_cel}, loader::AsepriteFile};
+
+        let config = texture_packer::TexturePackerConfig {
+            allow_rotation: false,
+            trim: false,
+            texture_outlines: false,
+            ..Default::default()
+        };
+        for name in ["combine.aseprite", "linkedcells.aseprite", "layers.aseprite"] {
+            let input = std::fs::read(format!("tests/aseprite_files/{name}")).unwrap();
+            let file = AsepriteFile::from_bytes(&input).unwrap();
+            let atlas = file.build_layered_atlas(config).unwrap();
+
+            let visible_cels = file.iter_cels()
+                .filter(|(_, layer_index, _)| file.visible_layers().any(|(index, _)| index == *layer_index))
+                .collect::<Vec<_>>();
+            assert_eq!(atlas.cels.len(), visible_cels.len(), "{name}");
+            for (frame_index, layer_index, cel) in visible_cels {
+                let packed = atlas.cels[&(layer_index, frame_index)];
+                assert_eq!(packed.displacement, (cel.chunk.x as i32, cel.chunk.y as i32));
+                assert_eq!(packed.opacity, cel.opacity());
+                let rect = packed.rect;
+                let img = image::imageops::crop_imm(&atlas.pages[packed.page], rect.x, rect.y, rect.w, rect.h).to_image();
+                assert_eq!(img, file.images_decompressed[cel.image_index]);
+            }
+            assert!(atlas.layers.iter().all(|layer| file.layers[layer.index].visible()));
+        }
+
+        // a cel hanging off the top left corner is put back where it was
+        let pixels = (0..16u8).flat_map(|i| [i * 16, 255 - i * 16, 0, 255]).collect::<Vec<_>>();
+        let input = edit_chunks(&fixture("default.aseprite"), 0, |chunks| {
+            chunks[4] = raw_cel(0, (-3, -2), (4, 4), &pixels);
+        });
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        let atlas = file.build_layered_atlas(config).unwrap();
+        let packed = atlas.cels[&(0, 0)];
+        assert_eq!(packed.displacement, (-3, -2));
+        let rect = packed.rect;
+        let cel = image::imageops::crop_imm(&atlas.pages[packed.page], rect.x, rect.y, rect.w, rect.h).to_image();
+        let mut stacked = image::RgbaImage::new(atlas.canvas_size.0, atlas.canvas_size.1);
+        image::imageops::overlay(&mut stacked, &cel, packed.displacement.0 as i64, packed.displacement.1 as i64);
+        assert_eq!(stacked, file.combined_frame_image(0).unwrap());
+    }
+
+    #[test]
+    fn test_from_ase_without_dedup() {
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}};
+
+        let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        // a pose held for a few frames
+        let load = || {
+            let mut file = AsepriteFile::from_bytes(&input).unwrap();
+            for index in 1..3 {
+                file.frames[index].cells = file.frames[0].cells.clone();
+            }
+            file
+        };
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+        let packed_count = |packer: &texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>| {
+            packer.get_pages().iter().map(|page| page.get_frames().len()).sum::<usize>()
+        };
+
+        let file = load();
+        let frame_count = file.frame_count();
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        AnimationSet::from_ase(file, "animated", &mut packer).unwrap();
+        assert!(packed_count(&packer) <= frame_count - 2);
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let options = AnimationSetOptions { keep_duplicates: true, ..Default::default() };
+        let set = AnimationSet::from_ase_with([("animated".to_string(), load())], &mut packer, options).unwrap();
+        assert_eq!(packed_count(&packer), frame_count);
+        let frames = &set["yipee"].frames;
+        let refs = frames[..3].iter().map(|f| f.image_ids.as_ref().unwrap().image_ref.as_str()).collect::<Vec<_>>();
+        assert_eq!(refs, ["animated0", "animated1", "animated2"]);
+    }
+
+    #[test]
+    fn test_from_ase_tagged_only() {
+        use itertools::Itertools;
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}};
+
+        let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        let mut file = AsepriteFile::from_bytes(&input).unwrap();
+        // only the first two frames are tagged now
+        file.tags.truncate(1);
+        file.tags[0].chunk.frames = (0, 1);
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let options = AnimationSetOptions { tagged_only: true, keep_duplicates: true, ..Default::default() };
+        let set = AnimationSet::from_ase_with([("animated".to_string(), file)], &mut packer, options).unwrap();
+
+        let packed = packer.get_pages().iter().flat_map(|page| page.get_frames().keys()).cloned().sorted().collect::<Vec<_>>();
+        assert_eq!(packed, ["animated0", "animated1"]);
+        assert_eq!(set.animations.len(), 1);
+    }
+
+    #[test]
+    fn test_from_ase_many() {
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}, wrappers::LayerParameter};
+
+        let animated = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        let tags = std::fs::read("tests/aseprite_files/tags.aseprite").unwrap();
+        let files = [("animated", &animated), ("tags", &tags)]
+            .map(|(prefix, input)| (format!("{prefix}/"), AsepriteFile::from_bytes(input).unwrap()));
+        let expected = files.iter()
+            .flat_map(|(prefix, file)| file.tag_names().map(move |name| format!("{prefix}{name}")))
+            .collect::<Vec<_>>();
+
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let many = AnimationSetOptions { prefix_animations: true, ..Default::default() };
+        let set = AnimationSet::from_ase_with(files, &mut packer, many).unwrap();
+
+        assert!(!expected.is_empty());
+        assert_eq!(set.animations.len(), expected.len());
+        for name in &expected {
+            assert!(set.get(name).is_some(), "missing {name}");
+        }
+        assert!(set.animations.values()
+            .flat_map(|a| &a.frames)
+            .filter_map(|f| f.image_ids.as_ref())
+            .all(|id| id.image_ref.starts_with("animated/") || id.image_ref.starts_with("tags/")));
+
+        // the hitboxes still point at the parameters of their layer
+        let hitboxes = set.animations.values().flat_map(|a| &a.frames).flat_map(|f| &f.hitboxes).collect::<Vec<_>>();
+        assert!(!hitboxes.is_empty());
+        for hitbox in hitboxes {
+            assert!(set.layer_parameters[hitbox.layer_id].contains_key(&LayerParameter::Hitbox));
+        }
+    }
+
+    #[test]
+    fn test_from_ase_many_layer_ids() {
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}};
+
+        let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        let files = ["a", "b"].map(|prefix| (format!("{prefix}/"), AsepriteFile::from_bytes(&input).unwrap()));
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let many = AnimationSetOptions { prefix_animations: true, ..Default::default() };
+        let set = AnimationSet::from_ase_with(files, &mut packer, many).unwrap();
+
+        // layer 1 of each file is its hitbox layer
+        assert_eq!(set.layer_parameters.len(), 4);
+        let layer_ids = |name: &str| set[name].frames.iter().flat_map(|f| &f.hitboxes).map(|h| h.layer_id).collect::<Vec<_>>();
+        assert!(!layer_ids("a/ye").is_empty());
+        assert!(layer_ids("a/ye").iter().all(|&id| id == 1));
+        assert!(layer_ids("b/ye").iter().all(|&id| id == 3));
+        assert_eq!(set.layer_parameters[1], set.layer_parameters[3]);
+    }
+
+    #[test]
+    fn test_from_ase_many_dedup() {
+        use crate::{loader::AsepriteFile, output::{AnimationSet, AnimationSetOptions}};
+
+        let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+        let config = texture_packer::TexturePackerConfig { allow_rotation: false, trim: false, ..Default::default() };
+        let packed_count = |packer: &texture_packer::MultiTexturePacker<'_, image::RgbaImage, String>| {
+            packer.get_pages().iter().map(|page| page.get_frames().len()).sum::<usize>()
+        };
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        AnimationSet::from_ase(AsepriteFile::from_bytes(&input).unwrap(), "single", &mut packer).unwrap();
+        let single = packed_count(&packer);
+
+        // the same frames twice, e.g. variants sharing their idle frames
+        let files = ["a", "b"].map(|prefix| (format!("{prefix}/"), AsepriteFile::from_bytes(&input).unwrap()));
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(config);
+        let many = AnimationSetOptions { prefix_animations: true, ..Default::default() };
+        let set = AnimationSet::from_ase_with(files, &mut packer, many).unwrap();
+
+        assert!(single > 0);
+        assert_eq!(packed_count(&packer), single);
+        let refs = |name: &str| set[name].frames.iter()
+            .map(|f| f.image_ids.as_ref().map(|id| id.image_ref.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(refs("a/ye"), refs("b/ye"));
+        assert!(refs("b/ye").iter().flatten().all(|img_ref| img_ref.starts_with("a/")));
+    }
+
+    #[test]
+    fn test_frame_events() {
+        use crate::{loader::AsepriteFile, output::AnimationSet, wrappers::FrameParameter};
+
+        let input = std::fs::read("tests/aseprite_files/userdata.aseprite").unwrap();
+        let mut file = AsepriteFile::from_bytes(&input).unwrap();
+        // The fixture's cels have plain text as user data, "Tag 12" covers frames 0 and 1
+        file.frames[1].cells[0].user_data.text = Some("event=footstep");
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(Default::default());
+        let set = AnimationSet::from_ase(file, "userdata", &mut packer).unwrap();
+        let frames = &set["Tag 12"].frames;
+        assert!(frames[0].actions.is_empty());
+        assert_eq!(frames[1].actions, [FrameParameter::Event("footstep".to_string())]);
+    }
+
+    #[test]
+    fn test_frame_events_from_every_cel() {
+        use crate::{loader::AsepriteFile, output::AnimationSet, wrappers::FrameParameter};
+
+        let input = std::fs::read("tests/aseprite_files/userdata.aseprite").unwrap();
+        let mut file = AsepriteFile::from_bytes(&input).unwrap();
+        // Frame 2 has a cel on each layer, "Tag 23" covers frames 1 and 2
+        let cells = &mut file.frames[2].cells;
+        assert_eq!(cells.len(), 3);
+        cells[0].user_data.text = Some("event=swing");
+        cells[2].user_data.text = Some("event=hit, unknown=1");
+        file.layers[2].chunk.flags.remove(crate::binary::chunks::layer::LayerFlags::VISIBLE);
+
+        let mut packer = texture_packer::MultiTexturePacker::new_skyline(Default::default());
+        let set = AnimationSet::from_ase(file, "userdata", &mut packer).unwrap();
+        let frames = &set["Tag 23"].frames;
+        assert!(frames[0].actions.is_empty());
+        // hidden layers are often where the events live, they count too
+        assert_eq!(
+            frames[1].actions,
+            [FrameParameter::Event("swing".to_string()), FrameParameter::Event("hit".to_string())]
+        );
+    }
 }
@@ -0,0 +1,149 @@
+//! The playback of every tag worked out once, so a game only has to ask which frame to show at a given time.
+
+use std::ops::Range;
+
+use crate::{binary::chunks::tags::LoopBehavior, loader::AsepriteFile, wrappers::Tag};
+
+/// The frames a tag plays, see [`Timeline`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagTimeline {
+    /// Frame indices in the order they're played
+    pub frames: Vec<usize>,
+    /// When each of `frames` ends, in milliseconds from the start of the tag
+    pub ends: Vec<u32>,
+    pub loop_behavior: LoopBehavior,
+}
+
+impl TagTimeline {
+    /// The timeline of `tag` in `file`, see [`Timeline::new`]
+    pub fn of(file: &AsepriteFile<'_>, tag: &Tag<'_>) -> Self {
+        let loop_behavior = tag.loop_behavior();
+        let frames = match loop_behavior {
+            LoopBehavior::Infinite => tag.cycle_order(),
+            LoopBehavior::Once | LoopBehavior::Times(_) => tag.repeated_order(),
+        };
+        Self::new(file, frames, loop_behavior)
+    }
+
+    /// The timeline of the frames of `file` at `frames`, played in that order
+    pub fn new(file: &AsepriteFile<'_>, frames: Vec<usize>, loop_behavior: LoopBehavior) -> Self {
+        let mut end = 0;
+        let ends = frames
+            .iter()
+            .map(|&index| {
+                end += file.frames.get(index).map_or(0, |frame| frame.duration);
+                end
+            })
+            .collect();
+        Self { frames, ends, loop_behavior }
+    }
+
+    /// Each of the frames with its `[start, end)` window in milliseconds from the start of the tag
+    pub fn windows(&self) -> impl Iterator<Item = (usize, Range<u32>)> + '_ {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        self.frames.iter().copied().zip(starts.zip(self.ends.iter().copied()).map(|(start, end)| start..end))
+    }
+
+    /// Length of the tag in milliseconds, one cycle of it when it loops forever
+    pub fn duration(&self) -> u32 {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    /// The frame shown `time` milliseconds after the tag started.
+    /// Tags without a repeat count loop forever, the others stay on their last frame once done.
+    pub fn sample(&self, time: u32) -> Option<usize> {
+        let duration = self.duration();
+        let time = match self.loop_behavior {
+            _ if duration == 0 => 0,
+            LoopBehavior::Infinite => time % duration,
+            LoopBehavior::Once | LoopBehavior::Times(_) => time.min(duration - 1),
+        };
+        let position = self.ends.partition_point(|&end| end <= time);
+        self.frames.get(position.min(self.frames.len().saturating_sub(1))).copied()
+    }
+}
+
+/// Every tag of a file with its frame order and timings, built once with [`Timeline::new`]
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    pub tags: ahash::HashMap<String, TagTimeline>,
+}
+
+impl Timeline {
+    /// Tags that loop forever play their [cycle](Tag::cycle_order), the others are played through
+    /// as many times as their repeat count says.
+    /// When several tags have the same name the first one is kept, like [`AsepriteFile::tag`].
+    pub fn new(file: &AsepriteFile<'_>) -> Self {
+        let mut tags = ahash::HashMap::default();
+        for tag in &file.tags {
+            if tags.contains_key(tag.name()) {
+                continue;
+            }
+            tags.insert(tag.name().to_string(), TagTimeline::of(file, tag));
+        }
+        Self { tags }
+    }
+
+    pub fn tag(&self, tag: &str) -> Option<&TagTimeline> {
+        self.tags.get(tag)
+    }
+
+    /// The frame of `tag` shown `time` milliseconds after it started, see [`TagTimeline::sample`].
+    /// `None` if there is no such tag.
+    pub fn sample(&self, tag: &str, time: u32) -> Option<usize> {
+        self.tag(tag)?.sample(time)
+    }
+}
+
+#[cfg(test)]
+fn ping_pong_file(input: &[u8], animation_repeat: u16) -> AsepriteFile<'_> {
+    use crate::binary::chunks::tags::AnimationDirection;
+
+    let mut file = AsepriteFile::from_bytes(input).unwrap();
+    for frame in &mut file.frames {
+        frame.duration = 100;
+    }
+    // yipee covers frames 0 to 3
+    let tag = file.tags.iter_mut().find(|tag| tag.name() == "yipee").unwrap();
+    tag.chunk.animation_direction = AnimationDirection::PingPong;
+    tag.chunk.animation_repeat = animation_repeat;
+    file
+}
+
+#[test]
+fn test_sample_ping_pong() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let timeline = Timeline::new(&ping_pong_file(&input, 0));
+
+    let tag = timeline.tag("yipee").unwrap();
+    assert_eq!(tag.frames, [0, 1, 2, 3, 2, 1]);
+    assert_eq!(tag.duration(), 600);
+    let samples = [0, 99, 100, 350, 450, 550, 599, 600, 1050].map(|t| timeline.sample("yipee", t).unwrap());
+    assert_eq!(samples, [0, 0, 1, 3, 2, 1, 1, 0, 2]);
+    assert_eq!(timeline.sample("missing", 0), None);
+}
+
+#[test]
+fn test_sample_ping_pong_repeat() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+
+    // Plays one way and stays on the last frame
+    let timeline = Timeline::new(&ping_pong_file(&input, 1));
+    let samples = [0, 350, 400, 5000].map(|t| timeline.sample("yipee", t).unwrap());
+    assert_eq!(samples, [0, 3, 3, 3]);
+
+    // There, back and there again
+    let timeline = Timeline::new(&ping_pong_file(&input, 3));
+    assert_eq!(timeline.tag("yipee").unwrap().frames, [0, 1, 2, 3, 2, 1, 0, 1, 2, 3]);
+    let samples = [450, 650, 999, 5000].map(|t| timeline.sample("yipee", t).unwrap());
+    assert_eq!(samples, [2, 0, 3, 3]);
+}
+
+#[test]
+fn test_windows() {
+    let input = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let file = ping_pong_file(&input, 1);
+    let timeline = TagTimeline::new(&file, vec![2, 0], LoopBehavior::Once);
+    assert_eq!(timeline.windows().collect::<Vec<_>>(), [(2, 0..100), (0, 100..200)]);
+    assert_eq!(timeline.sample(150), Some(0));
+}
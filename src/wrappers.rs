@@ -1,8 +1,6 @@
-use std::{borrow::Cow, ops::{RangeBounds, RangeInclusive}, str::FromStr};
+use std::{ops::RangeInclusive, str::FromStr};
 
-use itertools::Itertools;
-
-use crate::binary::chunks::{cel::CelChunk, layer::{LayerChunk, LayerFlags}, tags::TagChunk, user_data::UserDataChunk};
+use crate::binary::chunks::{cel::CelChunk, cel_extra::PreciseBounds, layer::{LayerChunk, LayerFlags}, slice::{SliceChunk, SliceKey}, tags::{AnimationDirection, LoopBehavior, TagChunk}, user_data::UserDataChunk};
 
 /// A cel in a frame, there is usually 1 per layer
 #[derive(Debug, Clone, Copy)]
@@ -10,6 +8,8 @@ pub struct Cel<'a> {
     pub chunk: CelChunk<'a>,
     pub user_data: UserDataChunk<'a>,
     pub image_index: usize,
+    /// Set when the cel is scaled in real time (e.g. in reference layers)
+    pub precise_bounds: Option<PreciseBounds>,
 }
 
 impl Cel<'_> {
@@ -25,6 +25,10 @@ impl Cel<'_> {
     pub fn z_index(&self) -> i16 {
         self.chunk.z_index
     }
+    /// Opacity of the cel itself, applied on top of the layer opacity
+    pub fn opacity(&self) -> u8 {
+        self.chunk.opacity
+    }
 }
 
 /// A frame in the file
@@ -34,12 +38,20 @@ pub struct Frame<'a> {
     /// In milliseconds
     pub duration: u32,
     pub cells: Vec<Cel<'a>>,
+    /// Chunks other than layers, cels, tags, the palette and the color profile (slices, tilesets, old palettes...),
+    /// as they are in the file.
+    /// [`AsepriteFile::to_bytes`](crate::loader::AsepriteFile::to_bytes) writes these back untouched.
+    pub other_chunks: Vec<&'a [u8]>,
 }
 
 impl Frame<'_> {
     pub fn iter_cells(&self) -> impl Iterator<Item = &Cel<'_>> {
         self.cells.iter()
     }
+    /// The parameters in the user data of every cel, hidden layers included, in layer order
+    pub fn parameters(&self) -> FrameParameters {
+        self.cells.iter().flat_map(|cel| cel.user_data.parse_text_as_frame_parameters()).collect()
+    }
     pub fn cell_at_layer_index(&self, layer_index: usize) -> Option<Cel<'_>> {
         // Binary search since they should be sorted
         self.cells
@@ -63,6 +75,88 @@ impl Tag<'_> {
     pub fn name(&self) -> &str {
         self.chunk.name
     }
+    pub fn loop_behavior(&self) -> LoopBehavior {
+        self.chunk.animation_repeat.into()
+    }
+    /// The frame indices in the order they're played, with the direction and repeat count of the tag.
+    /// A tag that doesn't specify a repeat count plays once, or once each way for ping-pong.
+    pub(crate) fn repeated_order(&self) -> Vec<usize> {
+        let (sequence, len, ping_pong) = self.sequence();
+        let passes = match (self.chunk.animation_repeat, ping_pong) {
+            (0, true) => 2,
+            (0, false) => 1,
+            (n, _) => n as usize,
+        };
+        // Ping-pong passes share the frame they turn around on
+        let count = match ping_pong {
+            true if len > 0 => len + (passes - 1) * (len - 1),
+            _ => passes * len,
+        };
+        sequence.take(count).collect()
+    }
+    /// The frame indices of one loop of the animation, ignoring the repeat count.
+    /// Ping-pong goes there and back without repeating the frame it starts on, so the cycle loops seamlessly.
+    pub fn cycle_order(&self) -> Vec<usize> {
+        let (sequence, len, ping_pong) = self.sequence();
+        let count = if ping_pong && len > 2 { 2 * len - 2 } else { len };
+        sequence.take(count).collect()
+    }
+    /// The endless sequence of frames the direction of the tag plays, with the frame count of the tag
+    /// and whether it's ping-pong. Ping-pong turns around on the first and last frame without repeating them.
+    fn sequence(&self) -> (impl Iterator<Item = usize>, usize, bool) {
+        let range = self.frame_range();
+        let (first, len) = (*range.start(), range.count());
+        let (reversed, ping_pong) = match self.chunk.animation_direction {
+            AnimationDirection::Reverse => (true, false),
+            AnimationDirection::PingPong => (false, true),
+            AnimationDirection::PingPongReverse => (true, true),
+            AnimationDirection::Forward | AnimationDirection::Unknown(_) => (false, false),
+        };
+        let period = if ping_pong && len > 1 { 2 * len - 2 } else { len.max(1) };
+        let sequence = (0..).map(move |step| {
+            let mut offset = step % period;
+            if offset >= len {
+                // on the way back
+                offset = period - offset;
+            }
+            first + if reversed { len - 1 - offset } else { offset }
+        });
+        (sequence, len, ping_pong)
+    }
+    /// The color of the tag in the timeline, taken from the user data and falling back on
+    /// the deprecated color of the tag chunk, which older files only have.
+    /// `None` if neither is set, an unset deprecated color is all zeros.
+    pub fn color(&self) -> Option<image::Rgb<u8>> {
+        match self.user_data.color {
+            Some(c) => Some(image::Rgb([c.red, c.green, c.blue])),
+            None => {
+                let legacy = image::Rgb([self.chunk.color.red, self.chunk.color.green, self.chunk.color.blue]);
+                (legacy.0 != [0; 3]).then_some(legacy)
+            }
+        }
+    }
+}
+
+/// A named region of the canvas, with a rectangle per key frame
+#[derive(Debug, Clone)]
+pub struct Slice<'a> {
+    pub chunk: SliceChunk<'a>,
+    pub user_data: UserDataChunk<'a>,
+}
+
+impl Slice<'_> {
+    pub fn name(&self) -> &str {
+        self.chunk.name
+    }
+    /// The key in effect at `frame_index`: the last one starting at or before it.
+    /// `None` before the first key.
+    pub fn key_at(&self, frame_index: usize) -> Option<&SliceKey> {
+        self.chunk
+            .slice_keys
+            .iter()
+            .filter(|key| key.frame_number as usize <= frame_index)
+            .max_by_key(|key| key.frame_number)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -79,6 +173,27 @@ impl Layer<'_> {
     pub fn visible(&self) -> bool {
         self.chunk.flags.contains(LayerFlags::VISIBLE)
     }
+    pub fn is_editable(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::EDITABLE)
+    }
+    pub fn is_movement_locked(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::LOCK_MOVEMENT)
+    }
+    /// The bottom layer of sprites without transparency
+    pub fn is_background(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::BACKGROUND)
+    }
+    pub fn prefers_linked_cels(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::PREFER_LINKED_CELS)
+    }
+    /// Only for groups, whether they're collapsed in the layer panel
+    pub fn is_collapsed(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::COLLAPSED)
+    }
+    /// A guide layer (e.g. an imported drawing to trace), not part of the sprite
+    pub fn is_reference(&self) -> bool {
+        self.chunk.flags.contains(LayerFlags::REFERENCE)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum_macros::EnumString)]
@@ -89,40 +204,71 @@ pub enum LayerParameter {
     //Seperate, //TODO: implement
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum_macros::EnumString)]
+/// Playback controls set in the user data of a tag
+#[derive(Debug, Clone, PartialEq)]
 pub enum TagParameter {
-    // TODO: what do we want here? Velocity-controls maybe? Might be easier to do that kinda thing from code though...
+    /// `next=<tag>`: the tag to play once this one is done
+    NextTag(String),
+    /// `speed=<multiplier>`: how much faster than its frame durations the tag plays
+    Speed(f32),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum_macros::EnumString)]
+impl TagParameter {
+    fn from_key_value(key: &str, value: &str) -> Option<Self> {
+        match key {
+            "next" => Some(TagParameter::NextTag(value.to_string())),
+            "speed" => value.parse().ok().map(TagParameter::Speed),
+            _ => None,
+        }
+    }
+}
+
+/// Actions tied to a frame, set in the user data of its cels
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FrameParameter {
-    // TODO: what do we want here? Being able to tie an action to a particular frame is important
-    // Flip visibilty of another layer?
-    // 
+    /// `event=<name>`: something for the game to do when the frame is shown, e.g. play a footstep sound
+    Event(String),
+    // TODO: Flip visibilty of another layer?
+}
+
+impl FrameParameter {
+    fn from_key_value(key: &str, value: &str) -> Option<Self> {
+        match key {
+            "event" => Some(FrameParameter::Event(value.to_string())),
+            _ => None,
+        }
+    }
 }
 
 pub type LayerParameters = ahash::AHashMap<LayerParameter, String>;
-pub type TagParameters = Vec<(TagParameter, String)>;
+pub type TagParameters = Vec<TagParameter>;
+pub type FrameParameters = Vec<FrameParameter>;
+
+/// Splits comma separated `key` or `key=value` parameters into a lowercase key and a value, empty for a bare key
+fn split_parameters(text: &str) -> impl Iterator<Item = (String, &str)> {
+    text.split(',').map(|parameter| {
+        let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        (key.trim().to_ascii_lowercase(), value.trim())
+    })
+}
 
 impl UserDataChunk<'_> {
+    /// Unknown keys are skipped
     pub fn parse_text_as_layer_parameters(&self) -> LayerParameters {
-        self.text
-            .unwrap_or_default()
-            .split(',')
-            .map(str::trim)
-            .map(str::to_ascii_lowercase)
-            .flat_map(|s| LayerParameter::from_str(&s))
-            .map(|s| (s, "".to_string()))
+        split_parameters(self.text.unwrap_or_default())
+            .flat_map(|(key, value)| Some((LayerParameter::from_str(&key).ok()?, value.to_string())))
             .collect()
     }
+    /// Unknown keys and values that don't parse are skipped
     pub fn parse_text_as_tag_parameters(&self) -> TagParameters {
-        self.text
-            .unwrap_or_default()
-            .split(',')
-            .map(str::trim)
-            .map(str::to_ascii_lowercase)
-            .flat_map(|s| TagParameter::from_str(&s))
-            .map(|s| (s, "".to_string()))
+        split_parameters(self.text.unwrap_or_default())
+            .flat_map(|(key, value)| TagParameter::from_key_value(&key, value))
+            .collect()
+    }
+    /// Unknown keys are skipped
+    pub fn parse_text_as_frame_parameters(&self) -> FrameParameters {
+        split_parameters(self.text.unwrap_or_default())
+            .flat_map(|(key, value)| FrameParameter::from_key_value(&key, value))
             .collect()
     }
 }
@@ -157,3 +303,52 @@ impl PixelExt for image::Rgba<u8> {
     }   
 }
 
+
+#[test]
+fn test_parse_layer_parameters() {
+    let user_data = UserDataChunk { text: Some("hitbox=attack, invisible"), color: None };
+    let parameters = user_data.parse_text_as_layer_parameters();
+    assert_eq!(parameters.len(), 2);
+    assert_eq!(parameters[&LayerParameter::Hitbox], "attack");
+    assert_eq!(parameters[&LayerParameter::Invisible], "");
+
+    // Keys are case insensitive, values are kept as written and unknown keys are skipped
+    let user_data = UserDataChunk { text: Some(" Hitbox = Hurt Box ,glow=5,"), color: None };
+    let parameters = user_data.parse_text_as_layer_parameters();
+    assert_eq!(parameters.len(), 1);
+    assert_eq!(parameters[&LayerParameter::Hitbox], "Hurt Box");
+}
+
+#[test]
+fn test_parse_tag_parameters() {
+    use crate::loader::AsepriteFile;
+
+    let input = std::fs::read("tests/aseprite_files/userdata.aseprite").unwrap();
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    // The fixture's tags have plain text as user data
+    assert!(file.tags[0].parameters.is_empty());
+
+    let tag = &mut file.tags[0];
+    tag.user_data.text = Some("next=idle, speed=1.5");
+    assert_eq!(
+        tag.user_data.parse_text_as_tag_parameters(),
+        [TagParameter::NextTag("idle".to_string()), TagParameter::Speed(1.5)]
+    );
+
+    tag.user_data.text = Some("Speed=fast, loop, NEXT = Run Left");
+    assert_eq!(tag.user_data.parse_text_as_tag_parameters(), [TagParameter::NextTag("Run Left".to_string())]);
+}
+
+#[test]
+fn test_frame_parameters() {
+    use crate::loader::AsepriteFile;
+
+    let input = std::fs::read("tests/aseprite_files/userdata.aseprite").unwrap();
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    // The fixture's cels have plain text as user data
+    assert!(file.frames[1].parameters().is_empty());
+
+    file.frames[1].cells[0].user_data.text = Some("event=footstep");
+    assert_eq!(file.frames[1].parameters(), [FrameParameter::Event("footstep".to_string())]);
+    assert!(file.frames[0].parameters().is_empty());
+}
@@ -47,4 +47,407 @@ fn test_spritesheet_pack() {
         img.save_with_format(path, image::ImageFormat::Png).unwrap();
     }
     // hashmap/packing is random, need to verify visually
-}
\ No newline at end of file
+}
+/// Blend modes of `tests/aseprite_files/blends.aseprite`, one layer each above the backdrop, in the order of the file format
+const BLEND_MODES: [&str; 19] = [
+    "normal", "multiply", "screen", "overlay", "darken", "lighten", "color_dodge", "color_burn", "hard_light", "soft_light",
+    "difference", "exclusion", "hue", "saturation", "color", "luminosity", "addition", "subtract", "divide",
+];
+
+fn scramble(n: u32) -> u32 {
+    let mut n = n.wrapping_mul(0x9e37_79b1);
+    n ^= n >> 15;
+    n = n.wrapping_mul(0x85eb_ca77);
+    n ^ (n >> 13)
+}
+
+/// A gradient, the last rows more and more transparent down to fully transparent
+fn blend_backdrop(x: u32, y: u32) -> [u8; 4] {
+    let r = (x * 255 / 31) as u8;
+    let g = (scramble(x + y * 32) >> 8) as u8;
+    let a = [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 200, 128, 64, 0][y as usize];
+    [r, g, 255 - r, a]
+}
+
+/// Noise with the extreme values in some columns and a few alpha levels
+fn blend_source(mode: u32, x: u32, y: u32) -> [u8; 4] {
+    let [r, g, b, _] = scramble(mode * 512 + y * 32 + x).to_le_bytes();
+    let r = match x % 8 {
+        0 => 0,
+        7 => 255,
+        _ => r,
+    };
+    let a = [255, 255, 255, 128, 200, 0, 255, 1][((x + y) % 8) as usize];
+    [r, g, b, a]
+}
+
+/// Line by line port of the RGBA blenders of Aseprite (`src/doc/blend_funcs.cpp`), with its 8 bit integer math.
+/// It shares no code with the crate, the reference images of `blends.aseprite` come from it.
+mod aseprite {
+    pub type ColorT = u32;
+
+    pub fn rgba(r: i32, g: i32, b: i32, a: i32) -> ColorT {
+        (r as u32 & 0xff) | ((g as u32 & 0xff) << 8) | ((b as u32 & 0xff) << 16) | ((a as u32 & 0xff) << 24)
+    }
+    fn rgba_getr(c: ColorT) -> i32 {
+        (c & 0xff) as i32
+    }
+    fn rgba_getg(c: ColorT) -> i32 {
+        ((c >> 8) & 0xff) as i32
+    }
+    fn rgba_getb(c: ColorT) -> i32 {
+        ((c >> 16) & 0xff) as i32
+    }
+    pub fn rgba_geta(c: ColorT) -> i32 {
+        ((c >> 24) & 0xff) as i32
+    }
+    const RGBA_A_MASK: ColorT = 0xff00_0000;
+    const RGBA_RGB_MASK: ColorT = 0x00ff_ffff;
+
+    pub fn mul_un8(a: i32, b: i32) -> i32 {
+        let t = a * b + 0x80;
+        ((t >> 8) + t) >> 8
+    }
+    fn div_un8(a: i32, b: i32) -> i32 {
+        let t = a * 0xff + b / 2;
+        t / b
+    }
+
+    fn blend_multiply(b: i32, s: i32) -> i32 {
+        mul_un8(b, s)
+    }
+    fn blend_screen(b: i32, s: i32) -> i32 {
+        b + s - mul_un8(b, s)
+    }
+    fn blend_overlay(b: i32, s: i32) -> i32 {
+        blend_hard_light(s, b)
+    }
+    fn blend_darken(b: i32, s: i32) -> i32 {
+        b.min(s)
+    }
+    fn blend_lighten(b: i32, s: i32) -> i32 {
+        b.max(s)
+    }
+    fn blend_hard_light(b: i32, s: i32) -> i32 {
+        if s < 128 {
+            blend_multiply(b, s << 1)
+        } else {
+            blend_screen(b, (s << 1) - 255)
+        }
+    }
+    fn blend_difference(b: i32, s: i32) -> i32 {
+        (b - s).abs()
+    }
+    fn blend_exclusion(b: i32, s: i32) -> i32 {
+        let t = blend_multiply(b, s);
+        b + s - 2 * t
+    }
+    fn blend_divide(b: i32, s: i32) -> i32 {
+        if b == 0 {
+            0
+        } else if b >= s {
+            255
+        } else {
+            div_un8(b, s)
+        }
+    }
+    fn blend_color_dodge(b: i32, s: i32) -> i32 {
+        if b == 0 {
+            return 0;
+        }
+        let s = 255 - s;
+        if b >= s {
+            255
+        } else {
+            div_un8(b, s)
+        }
+    }
+    fn blend_color_burn(b: i32, s: i32) -> i32 {
+        if b == 255 {
+            return 255;
+        }
+        let b = 255 - b;
+        if b >= s {
+            0
+        } else {
+            255 - div_un8(b, s)
+        }
+    }
+    fn blend_soft_light(b: i32, s: i32) -> i32 {
+        let b = b as f64 / 255.0;
+        let s = s as f64 / 255.0;
+        let d = if b <= 0.25 { ((16.0 * b - 12.0) * b + 4.0) * b } else { b.sqrt() };
+        let r = if s <= 0.5 { b - (1.0 - 2.0 * s) * b * (1.0 - b) } else { b + (2.0 * s - 1.0) * (d - b) };
+        (r * 255.0 + 0.5) as i32
+    }
+
+    pub fn rgba_blender_normal(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        if (backdrop & RGBA_A_MASK) == 0 {
+            let a = mul_un8(rgba_geta(src), opacity);
+            return (src & RGBA_RGB_MASK) | ((a as u32) << 24);
+        } else if (src & RGBA_A_MASK) == 0 {
+            return backdrop;
+        }
+        let (br, bg, bb, ba) = (rgba_getr(backdrop), rgba_getg(backdrop), rgba_getb(backdrop), rgba_geta(backdrop));
+        let (sr, sg, sb) = (rgba_getr(src), rgba_getg(src), rgba_getb(src));
+        let sa = mul_un8(rgba_geta(src), opacity);
+
+        let ra = sa + ba - mul_un8(ba, sa);
+        // integer division of C, truncates towards zero
+        let rr = br + (sr - br) * sa / ra;
+        let rg = bg + (sg - bg) * sa / ra;
+        let rb = bb + (sb - bb) * sa / ra;
+        rgba(rr, rg, rb, ra)
+    }
+
+    fn rgba_blender_merge(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let (br, bg, bb, ba) = (rgba_getr(backdrop), rgba_getg(backdrop), rgba_getb(backdrop), rgba_geta(backdrop));
+        let (sr, sg, sb, sa) = (rgba_getr(src), rgba_getg(src), rgba_getb(src), rgba_geta(src));
+        let (mut rr, mut rg, mut rb);
+        if ba == 0 {
+            (rr, rg, rb) = (sr, sg, sb);
+        } else if sa == 0 {
+            (rr, rg, rb) = (br, bg, bb);
+        } else {
+            rr = br + mul_un8(sr - br, opacity);
+            rg = bg + mul_un8(sg - bg, opacity);
+            rb = bb + mul_un8(sb - bb, opacity);
+        }
+        let ra = ba + mul_un8(sa - ba, opacity);
+        if ra == 0 {
+            (rr, rg, rb) = (0, 0, 0);
+        }
+        rgba(rr, rg, rb, ra)
+    }
+
+    fn channels(backdrop: ColorT, src: ColorT, opacity: i32, f: fn(i32, i32) -> i32) -> ColorT {
+        let r = f(rgba_getr(backdrop), rgba_getr(src));
+        let g = f(rgba_getg(backdrop), rgba_getg(src));
+        let b = f(rgba_getb(backdrop), rgba_getb(src));
+        let src = rgba(r, g, b, 0) | (src & RGBA_A_MASK);
+        rgba_blender_normal(backdrop, src, opacity)
+    }
+
+    fn lum(r: f64, g: f64, b: f64) -> f64 {
+        0.3 * r + 0.59 * g + 0.11 * b
+    }
+    fn sat(r: f64, g: f64, b: f64) -> f64 {
+        r.max(g.max(b)) - r.min(g.min(b))
+    }
+    fn clip_color(r: &mut f64, g: &mut f64, b: &mut f64) {
+        let l = lum(*r, *g, *b);
+        let n = r.min(g.min(*b));
+        let x = r.max(g.max(*b));
+        if n < 0.0 {
+            *r = l + (((*r - l) * l) / (l - n));
+            *g = l + (((*g - l) * l) / (l - n));
+            *b = l + (((*b - l) * l) / (l - n));
+        }
+        if x > 1.0 {
+            *r = l + (((*r - l) * (1.0 - l)) / (x - l));
+            *g = l + (((*g - l) * (1.0 - l)) / (x - l));
+            *b = l + (((*b - l) * (1.0 - l)) / (x - l));
+        }
+    }
+    fn set_lum(r: &mut f64, g: &mut f64, b: &mut f64, l: f64) {
+        let d = l - lum(*r, *g, *b);
+        *r += d;
+        *g += d;
+        *b += d;
+        clip_color(r, g, b);
+    }
+    /// The `MIN`, `MID` and `MAX` macros of Aseprite pick references, the same channel on ties
+    fn set_sat(c: &mut [f64; 3], s: f64) {
+        let min = |x: usize, y: usize| if c[x] < c[y] { x } else { y };
+        let max = |x: usize, y: usize| if c[x] > c[y] { x } else { y };
+        let mid = |x: usize, y: usize, z: usize| {
+            if c[x] > c[y] {
+                if c[y] > c[z] { y } else if c[x] > c[z] { z } else { x }
+            } else if c[y] > c[z] {
+                if c[z] > c[x] { z } else { x }
+            } else {
+                y
+            }
+        };
+        let (min, mid, max) = (min(0, min(1, 2)), mid(0, 1, 2), max(0, max(1, 2)));
+        if c[max] > c[min] {
+            c[mid] = ((c[mid] - c[min]) * s) / (c[max] - c[min]);
+            c[max] = s;
+        } else {
+            c[max] = 0.0;
+            c[mid] = 0.0;
+        }
+        c[min] = 0.0;
+    }
+    fn unit(c: ColorT) -> [f64; 3] {
+        [rgba_getr(c) as f64 / 255.0, rgba_getg(c) as f64 / 255.0, rgba_getb(c) as f64 / 255.0]
+    }
+    fn hsl_result(backdrop: ColorT, [r, g, b]: [f64; 3], src: ColorT, opacity: i32) -> ColorT {
+        let src = rgba((255.0 * r) as i32, (255.0 * g) as i32, (255.0 * b) as i32, 0) | (src & RGBA_A_MASK);
+        rgba_blender_normal(backdrop, src, opacity)
+    }
+
+    fn rgba_blender_hsl_hue(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let [r, g, b] = unit(backdrop);
+        let s = sat(r, g, b);
+        let l = lum(r, g, b);
+        let mut c = unit(src);
+        set_sat(&mut c, s);
+        let [mut r, mut g, mut b] = c;
+        set_lum(&mut r, &mut g, &mut b, l);
+        hsl_result(backdrop, [r, g, b], src, opacity)
+    }
+    fn rgba_blender_hsl_saturation(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let [r, g, b] = unit(src);
+        let s = sat(r, g, b);
+        let mut c = unit(backdrop);
+        let l = lum(c[0], c[1], c[2]);
+        set_sat(&mut c, s);
+        let [mut r, mut g, mut b] = c;
+        set_lum(&mut r, &mut g, &mut b, l);
+        hsl_result(backdrop, [r, g, b], src, opacity)
+    }
+    fn rgba_blender_hsl_color(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let [r, g, b] = unit(backdrop);
+        let l = lum(r, g, b);
+        let [mut r, mut g, mut b] = unit(src);
+        set_lum(&mut r, &mut g, &mut b, l);
+        hsl_result(backdrop, [r, g, b], src, opacity)
+    }
+    fn rgba_blender_hsl_luminosity(backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let [r, g, b] = unit(src);
+        let l = lum(r, g, b);
+        let [mut r, mut g, mut b] = unit(backdrop);
+        set_lum(&mut r, &mut g, &mut b, l);
+        hsl_result(backdrop, [r, g, b], src, opacity)
+    }
+
+    /// `get_rgba_blender(mode, true)`, the blenders Aseprite 1.3 composites layers with
+    pub fn blend(mode: &str, backdrop: ColorT, src: ColorT, opacity: i32) -> ColorT {
+        let blender: fn(ColorT, ColorT, i32) -> ColorT = match mode {
+            "normal" => return rgba_blender_normal(backdrop, src, opacity),
+            "multiply" => |b, s, o| channels(b, s, o, blend_multiply),
+            "screen" => |b, s, o| channels(b, s, o, blend_screen),
+            "overlay" => |b, s, o| channels(b, s, o, blend_overlay),
+            "darken" => |b, s, o| channels(b, s, o, blend_darken),
+            "lighten" => |b, s, o| channels(b, s, o, blend_lighten),
+            "color_dodge" => |b, s, o| channels(b, s, o, blend_color_dodge),
+            "color_burn" => |b, s, o| channels(b, s, o, blend_color_burn),
+            "hard_light" => |b, s, o| channels(b, s, o, blend_hard_light),
+            "soft_light" => |b, s, o| channels(b, s, o, blend_soft_light),
+            "difference" => |b, s, o| channels(b, s, o, blend_difference),
+            "exclusion" => |b, s, o| channels(b, s, o, blend_exclusion),
+            "hue" => rgba_blender_hsl_hue,
+            "saturation" => rgba_blender_hsl_saturation,
+            "color" => rgba_blender_hsl_color,
+            "luminosity" => rgba_blender_hsl_luminosity,
+            "addition" => |b, s, o| channels(b, s, o, |b, s| (b + s).min(255)),
+            "subtract" => |b, s, o| channels(b, s, o, |b, s| (b - s).max(0)),
+            "divide" => |b, s, o| channels(b, s, o, blend_divide),
+            _ => unreachable!("{mode}"),
+        };
+        // RGBA_BLENDER_N
+        if backdrop & RGBA_A_MASK != 0 {
+            let normal = rgba_blender_normal(backdrop, src, opacity);
+            let blend = blender(backdrop, src, opacity);
+            let ba = rgba_geta(backdrop);
+            let normal_to_blend_merge = rgba_blender_merge(normal, blend, ba);
+            let src_total_alpha = mul_un8(rgba_geta(src), opacity);
+            let composite_alpha = mul_un8(ba, src_total_alpha);
+            rgba_blender_merge(normal_to_blend_merge, blend, composite_alpha)
+        } else {
+            rgba_blender_normal(backdrop, src, opacity)
+        }
+    }
+}
+
+#[test]
+#[ignore = "only run to regenerate blends.aseprite and its reference images"]
+fn test_generate_blends() {
+    use assu_parser::{binary::blend_mode::BlendMode, encoder::AsepriteWriter};
+
+    let (width, height) = (32, 16);
+    let mut writer = AsepriteWriter::new(width as u16, height as u16);
+    let frame = writer.add_frame(100);
+    let backdrop = writer.add_layer("backdrop");
+    writer.add_cel(frame, backdrop, (0, 0), image::RgbaImage::from_fn(width, height, |x, y| image::Rgba(blend_backdrop(x, y)))).unwrap();
+    for (mode, name) in BLEND_MODES.iter().enumerate() {
+        let layer = writer.add_layer(name);
+        let img = image::RgbaImage::from_fn(width, height, |x, y| image::Rgba(blend_source(mode as u32, x, y)));
+        writer.add_cel(frame, layer, (0, 0), img).unwrap();
+    }
+    let input = writer.to_bytes().unwrap();
+    let mut file = AsepriteFile::from_bytes(&input).unwrap();
+    for (mode, layer) in file.layers[1..].iter_mut().enumerate() {
+        layer.chunk.blend_mode = BlendMode::from(mode as u16);
+    }
+    std::fs::write("tests/aseprite_files/blends.aseprite", file.to_bytes().unwrap()).unwrap();
+
+    std::fs::create_dir_all("tests/expected_pngs/blends").unwrap();
+    for (mode, name) in BLEND_MODES.iter().enumerate() {
+        let img = image::RgbaImage::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = blend_backdrop(x, y).map(i32::from);
+            let backdrop = aseprite::rgba_blender_normal(0, aseprite::rgba(r, g, b, a), 255);
+            let [r, g, b, a] = blend_source(mode as u32, x, y).map(i32::from);
+            image::Rgba(aseprite::blend(name, backdrop, aseprite::rgba(r, g, b, a), 255).to_le_bytes())
+        });
+        img.save_with_format(format!("tests/expected_pngs/blends/{name}.png"), image::ImageFormat::Png).unwrap();
+    }
+}
+
+/// Flattens `frame` the way Aseprite does, cel by cel with [`aseprite::rgba_blender_normal`] at the
+/// cel opacity times the layer opacity. Only knows Normal layers, which is all the fixtures below have.
+fn reference_render(file: &AsepriteFile, frame: usize) -> image::RgbaImage {
+    let mut canvas = image::RgbaImage::new(file.canvas_width() as u32, file.canvas_height() as u32);
+    let mut cells = file.frames[frame].cells.iter().collect::<Vec<_>>();
+    cells.sort_by_key(|cel| cel.chunk.layer_index);
+    for cel in cells {
+        let layer = &file.layers[cel.chunk.layer_index as usize];
+        let opacity = aseprite::mul_un8(cel.chunk.opacity as i32, layer.chunk.opacity as i32);
+        let img = &file.images_decompressed[cel.image_index];
+        for (x, y, src) in img.enumerate_pixels() {
+            let (cx, cy) = (cel.chunk.x as i64 + x as i64, cel.chunk.y as i64 + y as i64);
+            if cx < 0 || cy < 0 || cx >= canvas.width() as i64 || cy >= canvas.height() as i64 {
+                continue;
+            }
+            let backdrop = canvas.get_pixel_mut(cx as u32, cy as u32);
+            let [r, g, b, a] = src.0.map(i32::from);
+            let blended = aseprite::rgba_blender_normal(u32::from_le_bytes(backdrop.0), aseprite::rgba(r, g, b, a), opacity);
+            backdrop.0 = blended.to_le_bytes();
+        }
+    }
+    canvas
+}
+
+#[test]
+#[ignore = "only run to check the combine and linkedcells reference images against the port of Aseprite's blenders"]
+fn test_reference_renders() {
+    for (fixture, names) in [("combine", ["combined", "combined_cropped"].as_slice()), ("linkedcells", &["linkedcells"])] {
+        let input = std::fs::read(format!("tests/aseprite_files/{fixture}.aseprite")).unwrap();
+        let file = AsepriteFile::from_bytes(&input).unwrap();
+        for frame in 0..file.frames.len() {
+            let full = reference_render(&file, frame);
+            for name in names {
+                let expected = image::open(format!("tests/expected_pngs/{name}_{frame}.png")).unwrap().to_rgba8();
+                let reference = match *name {
+                    "combined_cropped" => {
+                        // the cropped image covers the cels, clipped to the canvas
+                        let cells = &file.frames[frame].cells;
+                        let bounds = |cel: &assu_parser::wrappers::Cel, size: (u32, u32)| {
+                            let (x, y) = (cel.chunk.x as i64, cel.chunk.y as i64);
+                            (x.max(0), y.max(0), (x + size.0 as i64).min(full.width() as i64), (y + size.1 as i64).min(full.height() as i64))
+                        };
+                        let (x0, y0, x1, y1) = cells
+                            .iter()
+                            .map(|cel| bounds(cel, file.images_decompressed[cel.image_index].dimensions()))
+                            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+                            .unwrap();
+                        image::imageops::crop_imm(&full, x0 as u32, y0 as u32, (x1 - x0) as u32, (y1 - y0) as u32).to_image()
+                    }
+                    _ => full.clone(),
+                };
+                assert!(expected == reference, "{name}_{frame}");
+            }
+        }
+    }
+}
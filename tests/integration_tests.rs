@@ -1,4 +1,4 @@
-use assu_parser::{loader::AsepriteFile, output::AnimationSet};
+use assu_parser::loader::AsepriteFile;
 
 #[test]
 fn test_cell() {
@@ -62,6 +62,232 @@ fn test_combine_cropped() {
     }
 }
 
+#[test]
+fn test_frame_trim_bounds() {
+    let path = "tests/aseprite_files/combine.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    for (index, frame) in file.frames.iter().enumerate() {
+        let cropped = frame.combined_frame_image_cropped(&file.layers, &file.images_decompressed).unwrap();
+        assert_eq!(
+            file.frame_trim_bounds(index),
+            Some((cropped.displacement_x, cropped.displacement_y, cropped.img.width(), cropped.img.height()))
+        );
+    }
+    assert_eq!(file.frame_trim_bounds(file.frames.len()), None);
+}
+
+#[test]
+fn test_layer_frames() {
+    let path = "tests/aseprite_files/combine.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let layer_frames = file.layer_frames(1);
+    assert_eq!(layer_frames.len(), 3);
+    // The second layer has no cel in the second frame
+    assert!(layer_frames[1].is_none());
+    for frame_i in [0, 2] {
+        let img = layer_frames[frame_i].as_ref().unwrap();
+        let cel = file.frames[frame_i].cell_at_layer_index(1).unwrap();
+        assert_eq!((img.displacement_x, img.displacement_y), (cel.x(), cel.y()));
+
+        let expected_path = format!("tests/expected_pngs/cell_f{frame_i}c1.png");
+        let expected = image::io::Reader::open(expected_path).unwrap().decode().unwrap();
+        assert_eq!(expected.as_rgba8().unwrap(), &img.img);
+    }
+}
+
+#[test]
+fn test_onion_skin() {
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let current = file.combined_frame_image(3).unwrap();
+    let neighbours = [file.combined_frame_image(2).unwrap(), file.combined_frame_image(4).unwrap()];
+    let onion = file.onion_skin(3, 1, 1).unwrap();
+
+    let mut faded_pixels = 0;
+    for (x, y, pixel) in onion.enumerate_pixels() {
+        let current_pixel = current.get_pixel(x, y);
+        if current_pixel.0[3] == 255 {
+            assert_eq!(pixel, current_pixel);
+        } else if current_pixel.0[3] == 0 && neighbours.iter().any(|n| n.get_pixel(x, y).0[3] == 255) {
+            assert!(pixel.0[3] > 0 && pixel.0[3] < 255);
+            faded_pixels += 1;
+        }
+    }
+    assert!(faded_pixels > 0);
+
+    // Without neighbours it's just the frame itself
+    assert_eq!(file.onion_skin(3, 0, 0).unwrap(), current);
+}
+
+#[test]
+fn test_frame_builder() {
+    use assu_parser::binary::chunks::layer::LayerFlags;
+
+    let path = "tests/aseprite_files/combine.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let mut file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let all = file.combined_frame_image(0).unwrap();
+    assert_eq!(file.frame_builder(0).build().unwrap(), all);
+
+    let only_second = file.frame_builder(0).include_layer(1).build().unwrap();
+    let without_others = file.frame_builder(0).exclude_layer(0).exclude_layer(2).build().unwrap();
+    assert_eq!(only_second, without_others);
+    assert_ne!(only_second, all);
+
+    // Hide the second layer, it's only rendered when forced
+    file.layers[1].chunk.flags.remove(LayerFlags::VISIBLE);
+    assert_eq!(file.frame_builder(0).include_layer(1).build().unwrap(), image::RgbaImage::new(128, 128));
+    assert_eq!(file.frame_builder(0).include_layer(1).only_visible(false).build().unwrap(), only_second);
+    assert_eq!(file.frame_builder(0).only_visible(false).build().unwrap(), all);
+    assert_ne!(file.combined_frame_image(0).unwrap(), all);
+}
+
+#[test]
+fn test_frame_builder_override_layer() {
+    use assu_parser::{binary::blend_mode::BlendMode, make_image::LayerOverride};
+
+    let path = "tests/aseprite_files/combine.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let mut file = AsepriteFile::from_bytes(&file).unwrap();
+    let normal = file.combined_frame_image(2).unwrap();
+
+    // The third layer covers the second one in frame 2, multiply leaves the parts over transparent pixels alone
+    file.layers[2].chunk.blend_mode = BlendMode::Multiply;
+    let multiply = file.combined_frame_image(2).unwrap();
+    assert_ne!(multiply, normal);
+    assert_eq!(file.frame_builder(2).build().unwrap(), multiply);
+
+    let force_normal = LayerOverride { blend_mode: Some(BlendMode::Normal), ..Default::default() };
+    assert_eq!(file.frame_builder(2).override_layer(2, force_normal).build().unwrap(), normal);
+    // The file itself is untouched
+    assert_eq!(file.layers[2].chunk.blend_mode, BlendMode::Multiply);
+
+    let transparent = LayerOverride { opacity: Some(0), ..Default::default() };
+    assert_eq!(
+        file.frame_builder(2).override_layer(2, transparent).build().unwrap(),
+        file.frame_builder(2).exclude_layer(2).build().unwrap()
+    );
+}
+
+#[test]
+fn test_frame_index_out_of_range() {
+    use assu_parser::{loader::LoadSpriteError, make_image::LoadImageError};
+
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let out_of_range = |result: Result<image::RgbaImage, LoadImageError>| match result {
+        Err(LoadImageError::Sprite(e)) => match *e {
+            LoadSpriteError::FrameIndexOutOfRange(index) => Some(index),
+            _ => None,
+        },
+        _ => None,
+    };
+    assert_eq!(out_of_range(file.combined_frame_image(8)), Some(8));
+    assert_eq!(out_of_range(file.combined_frame_image_scaled(100, 2)), Some(100));
+    assert_eq!(out_of_range(file.onion_skin(9, 2, 2)), Some(9));
+    assert_eq!(out_of_range(file.frame_builder(8).build()), Some(8));
+}
+
+#[test]
+fn test_single_image() {
+    use assu_parser::make_image::LoadImageError;
+
+    for name in ["default", "layers", "tags"] {
+        let file = std::fs::read(format!("tests/aseprite_files/{name}.aseprite")).unwrap();
+        let file = AsepriteFile::from_bytes(&file).unwrap();
+        assert!(file.is_static(), "{name}");
+        assert_eq!(file.single_image().unwrap(), file.combined_frame_image(0).unwrap(), "{name}");
+    }
+
+    let file = std::fs::read("tests/aseprite_files/animated.aseprite").unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+    assert!(!file.is_static());
+    assert!(matches!(file.single_image(), Err(LoadImageError::NotStatic(8))));
+}
+
+#[test]
+fn test_flatten() {
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let flattened = file.flatten().unwrap();
+    assert_eq!(flattened.canvas_size, (16, 16));
+    assert_eq!(flattened.frames.len(), file.frames.len());
+    for (index, frame) in flattened.frames.iter().enumerate() {
+        assert_eq!(frame.img, file.combined_frame_image(index).unwrap());
+        assert_eq!(frame.duration, file.frames[index].duration);
+    }
+    assert_eq!(flattened.tags.len(), file.tags.len());
+    for (flattened_tag, tag) in flattened.tags.iter().zip(file.tags.iter()) {
+        assert_eq!(flattened_tag.name, tag.name());
+        assert_eq!(flattened_tag.frame_range, tag.frame_range());
+    }
+}
+
+#[test]
+fn test_strip_spritesheet() {
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let (sheet, rects) = file.strip_spritesheet(3).unwrap();
+    // 8 frames of 16x16 in 3 columns
+    assert_eq!(sheet.dimensions(), (48, 48));
+    assert_eq!(rects.len(), 8);
+    assert_eq!(rects[4], texture_packer::Rect::new(16, 16, 16, 16));
+    for (index, rect) in rects.iter().enumerate() {
+        let view = image::imageops::crop_imm(&sheet, rect.x, rect.y, rect.w, rect.h).to_image();
+        assert_eq!(view, file.combined_frame_image(index).unwrap());
+    }
+
+    let (sheet, rects) = file.strip_spritesheet(0).unwrap();
+    assert_eq!(sheet.dimensions(), (128, 16));
+    assert_eq!(rects[7], texture_packer::Rect::new(112, 0, 16, 16));
+}
+
+#[test]
+fn test_export_png_sequence() {
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let dir = std::env::temp_dir().join("assu_parser_png_sequence");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let paths = file.export_png_sequence(&dir, None).unwrap();
+    assert_eq!(paths.len(), file.frames.len());
+    assert!(paths.iter().all(|p| p.exists()));
+
+    let tag = &file.tags[0];
+    let paths = file.export_png_sequence(&dir, Some(tag.name())).unwrap();
+    assert_eq!(paths.len(), tag.frame_range().count());
+    assert_eq!(paths[0], dir.join(format!("{}_0.png", tag.name())));
+    let first = image::io::Reader::open(&paths[0]).unwrap().decode().unwrap();
+    let expected = file.combined_frame_image(*tag.frame_range().start()).unwrap();
+    assert_eq!(first.as_rgba8().unwrap(), &expected);
+
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), file.frames.len() + tag.frame_range().count());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // a tag name can't write outside of the directory
+    let mut file = file;
+    file.tags[0].chunk.name = "../..\\escape:*";
+    let paths = file.export_png_sequence(&dir, Some("../..\\escape:*")).unwrap();
+    assert_eq!(paths[0], dir.join("______escape___0.png"));
+    assert!(paths.iter().all(|p| p.parent() == Some(dir.as_path()) && p.exists()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[test]
 fn test_linkedcells() {
     let path = "tests/aseprite_files/linkedcells.aseprite";
@@ -105,3 +331,226 @@ fn test_userdata() {
     assert!(file.tags[2].name() == "Tag 23");
     assert!(file.tags[2].user_data.text.unwrap() == "t23");
 }
+
+#[test]
+fn test_tags_by_frame() {
+    let path = "tests/aseprite_files/userdata.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    assert_eq!(file.tag_names().collect::<Vec<_>>(), ["Tag 13", "Tag 12", "Tag 23"]);
+    let tags = file.tags_by_frame();
+    assert_eq!(tags.iter().map(|tag| tag.name()).collect::<Vec<_>>(), ["Tag 12", "Tag 13", "Tag 23"]);
+    assert_eq!(tags.iter().map(|tag| tag.frame_range()).collect::<Vec<_>>(), [0..=1, 0..=2, 1..=2]);
+}
+
+#[test]
+#[cfg(feature = "gif")]
+fn test_export_gif() {
+    use assu_parser::{binary::chunks::tags::AnimationDirection, timeline::TagTimeline};
+    use image::AnimationDecoder;
+
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let mut gif = Vec::new();
+    file.export_gif(None, &mut gif).unwrap();
+    let frames = image::codecs::gif::GifDecoder::new(&gif[..]).unwrap().into_frames().collect_frames().unwrap();
+    assert_eq!(frames.len(), file.frames.len());
+    for (frame, expected) in frames.iter().zip(file.frames.iter()) {
+        assert_eq!(frame.delay().numer_denom_ms(), (expected.duration, 1));
+    }
+
+    // looping is an application extension, left out for GIFs that play once
+    let loops = |gif: &[u8]| gif.windows(11).any(|w| w == b"NETSCAPE2.0");
+    assert!(loops(&gif));
+
+    let tag = &file.tags[0];
+    let mut gif = Vec::new();
+    file.export_gif(Some(tag.name()), &mut gif).unwrap();
+    let frames = image::codecs::gif::GifDecoder::new(&gif[..]).unwrap().into_frames().collect_frames().unwrap();
+    assert_eq!(frames.len(), tag.frame_range().count());
+    assert!(loops(&gif));
+
+    // twice ping-pong, there and back once
+    let mut file = file;
+    file.tags[0].chunk.animation_direction = AnimationDirection::PingPong;
+    file.tags[0].chunk.animation_repeat = 2;
+    let mut gif = Vec::new();
+    file.export_gif(Some("yipee"), &mut gif).unwrap();
+    let frames = image::codecs::gif::GifDecoder::new(&gif[..]).unwrap().into_frames().collect_frames().unwrap();
+    let order = TagTimeline::of(&file, &file.tags[0]).frames;
+    assert_eq!(frames.len(), order.len());
+    assert_eq!(frames.len(), 7);
+    for (frame, index) in frames.iter().zip(order) {
+        assert_eq!(frame.delay().numer_denom_ms(), (file.frames[index].duration, 1));
+    }
+    assert!(!loops(&gif));
+
+    assert!(file.export_gif(Some("missing"), Vec::new()).is_err());
+}
+
+#[test]
+#[cfg(feature = "apng")]
+fn test_export_apng() {
+    use assu_parser::{binary::chunks::tags::AnimationDirection, timeline::TagTimeline};
+
+    let path = "tests/aseprite_files/animated.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+
+    let tag = &file.tags[0];
+    let mut apng = Vec::new();
+    file.export_apng(Some(tag.name()), &mut apng).unwrap();
+
+    let mut reader = png::Decoder::new(&apng[..]).read_info().unwrap();
+    let animation_control = reader.info().animation_control.unwrap();
+    assert_eq!(animation_control.num_frames as usize, tag.frame_range().count());
+    // no repeat count, loops forever
+    assert_eq!(animation_control.num_plays, 0);
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    for index in tag.frame_range() {
+        reader.next_frame(&mut buf).unwrap();
+        let frame_control = reader.info().frame_control.unwrap();
+        assert_eq!(frame_control.delay_num as u32, file.frames[index].duration);
+        assert_eq!(frame_control.delay_den, 1000);
+        assert_eq!(&buf[..], &file.combined_frame_image(index).unwrap().into_raw()[..]);
+    }
+
+    // three times in reverse, played through once
+    let mut file = file;
+    file.tags[0].chunk.animation_direction = AnimationDirection::Reverse;
+    file.tags[0].chunk.animation_repeat = 3;
+    let mut apng = Vec::new();
+    file.export_apng(Some("yipee"), &mut apng).unwrap();
+    let mut reader = png::Decoder::new(&apng[..]).read_info().unwrap();
+    let animation_control = reader.info().animation_control.unwrap();
+    assert_eq!(animation_control.num_frames, 12);
+    assert_eq!(animation_control.num_plays, 1);
+    let mut buf = vec![0; reader.output_buffer_size()];
+    for index in TagTimeline::of(&file, &file.tags[0]).frames {
+        reader.next_frame(&mut buf).unwrap();
+        assert_eq!(&buf[..], &file.combined_frame_image(index).unwrap().into_raw()[..]);
+    }
+}
+
+/// Composites the backdrop of `blends.aseprite` with the layer `layer_index`, which is in the blend mode `name`,
+/// and compares it with `tests/expected_pngs/blends/{name}.png`.
+/// The backdrop gets transparent in the last rows, the layers above it have a few alpha levels.
+///
+/// The reference images come from a port of the blend functions of Aseprite 1.3 (`test_generate_blends` in `tests/debug.rs`),
+/// they should be replaced with images exported from Aseprite.
+fn assert_blend(layer_index: usize, name: &str) {
+    let path = "tests/aseprite_files/blends.aseprite";
+    let file = std::fs::read(path).unwrap();
+    let file = AsepriteFile::from_bytes(&file).unwrap();
+    assert_eq!(file.layers[layer_index].name(), name);
+
+    let img = file.frame_builder(0).include_layer(0).include_layer(layer_index).build().unwrap();
+    std::fs::create_dir_all("tests/generated_pngs/blends").unwrap();
+    img.save_with_format(format!("tests/generated_pngs/blends/{name}.png"), image::ImageFormat::Png).unwrap();
+
+    let expected_path = format!("tests/expected_pngs/blends/{name}.png");
+    let expected = image::io::Reader::open(expected_path).unwrap().decode().unwrap();
+    let expected_rgba = expected.as_rgba8().unwrap();
+    for (x, y, pixel) in img.enumerate_pixels() {
+        assert_eq!(pixel, expected_rgba.get_pixel(x, y), "{name} at {x},{y}");
+    }
+}
+
+#[test]
+fn test_blend_normal() {
+    assert_blend(1, "normal");
+}
+
+#[test]
+fn test_blend_multiply() {
+    assert_blend(2, "multiply");
+}
+
+#[test]
+fn test_blend_screen() {
+    assert_blend(3, "screen");
+}
+
+#[test]
+fn test_blend_overlay() {
+    assert_blend(4, "overlay");
+}
+
+#[test]
+fn test_blend_darken() {
+    assert_blend(5, "darken");
+}
+
+#[test]
+fn test_blend_lighten() {
+    assert_blend(6, "lighten");
+}
+
+#[test]
+fn test_blend_color_dodge() {
+    assert_blend(7, "color_dodge");
+}
+
+#[test]
+fn test_blend_color_burn() {
+    assert_blend(8, "color_burn");
+}
+
+#[test]
+fn test_blend_hard_light() {
+    assert_blend(9, "hard_light");
+}
+
+#[test]
+fn test_blend_soft_light() {
+    assert_blend(10, "soft_light");
+}
+
+#[test]
+fn test_blend_difference() {
+    assert_blend(11, "difference");
+}
+
+#[test]
+fn test_blend_exclusion() {
+    assert_blend(12, "exclusion");
+}
+
+#[test]
+fn test_blend_hue() {
+    assert_blend(13, "hue");
+}
+
+#[test]
+fn test_blend_saturation() {
+    assert_blend(14, "saturation");
+}
+
+#[test]
+fn test_blend_color() {
+    assert_blend(15, "color");
+}
+
+#[test]
+fn test_blend_luminosity() {
+    assert_blend(16, "luminosity");
+}
+
+#[test]
+fn test_blend_addition() {
+    assert_blend(17, "addition");
+}
+
+#[test]
+fn test_blend_subtract() {
+    assert_blend(18, "subtract");
+}
+
+#[test]
+fn test_blend_divide() {
+    assert_blend(19, "divide");
+}